@@ -124,6 +124,64 @@ impl HpetEntry {
     }
 }
 
+// Fixed ACPI Description Table
+// only the fields present in ACPI 1.0 (the table is longer in the later revisions)
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub(super) struct Fadt {
+    firmware_ctrl: u32,
+    dsdt: u32,
+    reserved: u8,
+    preferred_pm_profile: u8,
+    sci_interrupt: u16,
+    smi_command_port: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_req: u8,
+    pstate_control: u8,
+    pm1a_event_block: u32,
+    pm1b_event_block: u32,
+    pm1a_control_block: u32,
+    pm1b_control_block: u32,
+    pm2_control_block: u32,
+    pm_timer_block: u32,
+    gpe0_block: u32,
+    gpe1_block: u32,
+    pm1_event_length: u8,
+    pm1_control_length: u8,
+    pm2_control_length: u8,
+    pm_timer_length: u8,
+    gpe0_length: u8,
+    gpe1_length: u8,
+    gpe1_base: u8,
+    cstate_control: u8,
+    worst_c2_latency: u16,
+    worst_c3_latency: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alarm: u8,
+    month_alarm: u8,
+    // index of the CMOS RTC register holding the century, 0 if not supported
+    pub(super) century: u8,
+    boot_architecture_flags: u16,
+    reserved2: u8,
+    flags: u32,
+}
+
+// an ECAM region of the MCFG table, the memory mapped PCIe config space of the buses
+// `start_bus`..=`end_bus` of the segment
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub(super) struct McfgEntry {
+    pub(super) base_addr: u64,
+    pub(super) segment: u16,
+    pub(super) start_bus: u8,
+    pub(super) end_bus: u8,
+    reserved: u32,
+}
+
 macro_rules! madt_type {
     ($mt: ident, $addr: ident, $cur_len: ident) => {
         Some(MadtEntry::$mt(unsafe {
@@ -164,6 +222,24 @@ impl RsdtEntries {
     pub(super) fn find_hpet(&self) -> Option<AcpiSdt> {
         self.find(b"HPET")
     }
+
+    pub(super) fn find_mcfg(&self) -> Option<Vec<McfgEntry>> {
+        self.find(b"MCFG").map(|a| {
+            let AcpiSdtType::Mcfg(entries) = a.fields else {
+                unreachable!()
+            };
+            entries
+        })
+    }
+
+    pub(super) fn find_fadt(&self) -> Option<Fadt> {
+        self.find(b"FACP").map(|a| {
+            let AcpiSdtType::Fadt(fadt) = a.fields else {
+                unreachable!()
+            };
+            fadt
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -176,6 +252,8 @@ pub(super) enum AcpiSdtType {
         entries: Vec<MadtEntry>,
     },
     Hpet(HpetEntry),
+    Fadt(Fadt),
+    Mcfg(Vec<McfgEntry>),
 }
 
 #[derive(Debug, Clone)]
@@ -247,6 +325,21 @@ impl AcpiSdt {
                 let hpet = unsafe { *(addr.byte_add(header_length) as *const HpetEntry) };
                 AcpiSdtType::Hpet(hpet)
             }
+            b"FACP" => {
+                // the older revisions of the table stop short of the fields we know about
+                if (header.length as usize) < header_length + core::mem::size_of::<Fadt>() {
+                    return None;
+                }
+                let fadt =
+                    unsafe { (addr.byte_add(header_length) as *const Fadt).read_unaligned() };
+                AcpiSdtType::Fadt(fadt)
+            }
+            b"MCFG" => {
+                let table = unsafe {
+                    core::slice::from_raw_parts(addr as *const u8, header.length as usize)
+                };
+                AcpiSdtType::Mcfg(parse_mcfg(table.get(header_length..).unwrap_or(&[])))
+            }
             o => {
                 crate::println!(
                     "Unsupported ACPI System Table: {} @ {:x}",
@@ -261,6 +354,18 @@ impl AcpiSdt {
     }
 }
 
+// the ECAM regions of the MCFG table, `fields` being what comes after its header
+fn parse_mcfg(fields: &[u8]) -> Vec<McfgEntry> {
+    // 8 reserved bytes before the entries
+    fields
+        .get(8..)
+        .unwrap_or(&[])
+        .chunks_exact(core::mem::size_of::<McfgEntry>())
+        // SAFETY: the chunk is as long as an entry, which is plain data
+        .map(|entry| unsafe { (entry.as_ptr() as *const McfgEntry).read_unaligned() })
+        .collect()
+}
+
 pub(super) fn find_rsdt() -> Option<RsdtEntries> {
     // version 2 is not supported as of yet
     find_rsdp()
@@ -321,3 +426,44 @@ fn calculate_checksum(start_addr: *const u8, length: usize) -> u8 {
         .map(|i| unsafe { *start_addr.byte_add(i) })
         .fold(0, |acc, val| acc.wrapping_add(val))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn mcfg_entry(base_addr: u64, segment: u16, start_bus: u8, end_bus: u8) -> Vec<u8> {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&base_addr.to_le_bytes());
+        entry.extend_from_slice(&segment.to_le_bytes());
+        entry.extend_from_slice(&[start_bus, end_bus]);
+        entry.extend_from_slice(&[0; 4]);
+        entry
+    }
+
+    #[test]
+    fn mcfg_entries() {
+        let mut fields = vec![0; 8];
+        fields.extend(mcfg_entry(0xe000_0000, 0, 0, 0xff));
+        fields.extend(mcfg_entry(0xf000_0000, 1, 0x10, 0x1f));
+        let entries = parse_mcfg(&fields);
+        assert_eq!(entries.len(), 2);
+        let (base_addr, segment) = (entries[0].base_addr, entries[0].segment);
+        assert_eq!((base_addr, segment), (0xe000_0000, 0));
+        assert_eq!((entries[0].start_bus, entries[0].end_bus), (0, 0xff));
+        let (base_addr, segment) = (entries[1].base_addr, entries[1].segment);
+        assert_eq!((base_addr, segment), (0xf000_0000, 1));
+        assert_eq!((entries[1].start_bus, entries[1].end_bus), (0x10, 0x1f));
+    }
+
+    #[test]
+    fn mcfg_truncated() {
+        assert!(parse_mcfg(&[]).is_empty());
+        assert!(parse_mcfg(&[0; 8]).is_empty());
+        // a partial entry at the end is not read
+        let mut fields = vec![0; 8];
+        fields.extend(mcfg_entry(0xe000_0000, 0, 0, 0xff));
+        fields.extend(&mcfg_entry(0xf000_0000, 0, 0, 0xff)[..10]);
+        assert_eq!(parse_mcfg(&fields).len(), 1);
+    }
+}
@@ -0,0 +1,244 @@
+use super::vga_buffer::Colour;
+
+// parser for the ANSI/VT100 escape sequences the consoles understand
+// https://vt100.net/docs/vt100-ug/chapter3.html
+// the unknown sequences are dropped
+
+const MAX_PARAMS: usize = 8;
+
+// the ANSI colour numbers (30-37) in `Colour` order
+const ANSI_TO_VGA: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Erase {
+    // from the cursor on
+    ToEnd,
+    // up to the cursor
+    ToStart,
+    All,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Action {
+    Print(char),
+    // newline, carriage return, tab, backspace...
+    Control(char),
+    // SGR, `ESC [ n ; ... m`
+    SetGraphics(Params),
+    CursorUp(usize),
+    CursorDown(usize),
+    CursorForward(usize),
+    CursorBack(usize),
+    // 0 based
+    CursorPosition { row: usize, col: usize },
+    EraseDisplay(Erase),
+    EraseLine(Erase),
+    SaveCursor,
+    RestoreCursor,
+    ShowCursor(bool),
+}
+
+// the numeric parameters of a control sequence, the missing ones are 0
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Params {
+    values: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+impl Params {
+    const fn new() -> Self {
+        Self {
+            values: [0; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    // the `i`th parameter, `default` if it's missing or 0
+    fn get(&self, i: usize, default: u16) -> u16 {
+        match self.values[..self.len].get(i) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.values[..self.len].iter().copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    // got ESC
+    Escape,
+    // got `ESC [`
+    Csi,
+    // got `ESC [ ?`, only the cursor visibility is supported
+    CsiPrivate,
+}
+
+pub(super) struct Parser {
+    state: State,
+    params: Params,
+}
+
+impl Parser {
+    pub(super) const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Params::new(),
+        }
+    }
+
+    // feeds the next character, returns what to do once a character or a sequence is complete
+    pub(super) fn advance(&mut self, c: char) -> Option<Action> {
+        match self.state {
+            State::Ground => match c {
+                '\x1b' => {
+                    self.state = State::Escape;
+                    None
+                }
+                '\0'..='\x1f' | '\x7f' => Some(Action::Control(c)),
+                c => Some(Action::Print(c)),
+            },
+            State::Escape => {
+                self.state = State::Ground;
+                match c {
+                    '[' => {
+                        self.params = Params::new();
+                        self.state = State::Csi;
+                        None
+                    }
+                    '7' => Some(Action::SaveCursor),
+                    '8' => Some(Action::RestoreCursor),
+                    _ => None,
+                }
+            }
+            State::Csi | State::CsiPrivate => match c {
+                '0'..='9' => {
+                    if self.params.len == 0 {
+                        self.params.len = 1;
+                    }
+                    if let Some(value) = self.params.values.get_mut(self.params.len - 1) {
+                        *value = value
+                            .saturating_mul(10)
+                            .saturating_add(c as u16 - '0' as u16);
+                    }
+                    None
+                }
+                ';' => {
+                    // an empty parameter is a 0
+                    self.params.len = (self.params.len.max(1) + 1).min(MAX_PARAMS);
+                    None
+                }
+                '?' if self.params.len == 0 => {
+                    self.state = State::CsiPrivate;
+                    None
+                }
+                // the final byte
+                '\x40'..='\x7e' => {
+                    let private = self.state == State::CsiPrivate;
+                    self.state = State::Ground;
+                    if private {
+                        self.private_sequence(c)
+                    } else {
+                        self.sequence(c)
+                    }
+                }
+                // intermediate bytes and garbage
+                _ => None,
+            },
+        }
+    }
+
+    fn sequence(&self, c: char) -> Option<Action> {
+        let params = &self.params;
+        let n = params.get(0, 1) as usize;
+        let erase = || match params.get(0, 0) {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToStart),
+            2 | 3 => Some(Erase::All),
+            _ => None,
+        };
+        match c {
+            'm' => Some(Action::SetGraphics(*params)),
+            'A' => Some(Action::CursorUp(n)),
+            'B' => Some(Action::CursorDown(n)),
+            'C' => Some(Action::CursorForward(n)),
+            'D' => Some(Action::CursorBack(n)),
+            // 1 based in the sequence
+            'H' | 'f' => Some(Action::CursorPosition {
+                row: params.get(0, 1) as usize - 1,
+                col: params.get(1, 1) as usize - 1,
+            }),
+            'J' => erase().map(Action::EraseDisplay),
+            'K' => erase().map(Action::EraseLine),
+            's' => Some(Action::SaveCursor),
+            'u' => Some(Action::RestoreCursor),
+            _ => None,
+        }
+    }
+
+    fn private_sequence(&self, c: char) -> Option<Action> {
+        match (self.params.get(0, 0), c) {
+            (25, 'h') => Some(Action::ShowCursor(true)),
+            (25, 'l') => Some(Action::ShowCursor(false)),
+            _ => None,
+        }
+    }
+}
+
+// the colours of the text a console writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Attributes {
+    pub(super) fg: Colour,
+    pub(super) bg: Colour,
+    // shown as the bright variant of the foreground colour
+    bold: bool,
+}
+
+impl Attributes {
+    pub(super) const fn new(fg: Colour, bg: Colour) -> Self {
+        Self {
+            fg,
+            bg,
+            bold: false,
+        }
+    }
+
+    // applies the parameters of an SGR sequence, 0 goes back to `default`
+    pub(super) fn apply_sgr(&mut self, params: &Params, default: Attributes) {
+        if params.len == 0 {
+            *self = default;
+            return;
+        }
+        for param in params.iter() {
+            match param {
+                0 => *self = default,
+                1 => {
+                    self.bold = true;
+                    self.fg = bright(self.fg as u8);
+                }
+                22 => self.bold = false,
+                30..=37 => {
+                    let colour = ANSI_TO_VGA[param as usize - 30];
+                    self.fg = if self.bold {
+                        bright(colour)
+                    } else {
+                        Colour::from_index(colour)
+                    };
+                }
+                39 => self.fg = default.fg,
+                40..=47 => self.bg = Colour::from_index(ANSI_TO_VGA[param as usize - 40]),
+                49 => self.bg = default.bg,
+                90..=97 => self.fg = bright(ANSI_TO_VGA[param as usize - 90]),
+                100..=107 => self.bg = bright(ANSI_TO_VGA[param as usize - 100]),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn bright(colour: u8) -> Colour {
+    Colour::from_index(colour | 0x8)
+}
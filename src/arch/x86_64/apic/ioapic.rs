@@ -1,87 +1,73 @@
-use super::acpi::{self, IoApicIntSourceOverride};
+use super::{acpi, Trigger};
 use crate::arch::x86_64::paging::mmio;
 use crate::mem::PhysicalAddress;
 use log::{info, trace};
 
-pub(super) struct IoApic(acpi::IoApic);
+// redirection entry
+const ACTIVE_LOW: u64 = 1 << 13;
+const LEVEL_TRIGGERED: u64 = 1 << 15;
+const MASKED: u64 = 1 << 16;
+
+pub(super) struct IoApic {
+    table: acpi::IoApic,
+    // number of GSIs (redirection entries) it handles, starting at `table.gsib`
+    num_gsi: u32,
+}
 
 impl IoApic {
     pub(super) fn new(table: acpi::IoApic) -> Self {
-        Self(table)
+        Self { table, num_gsi: 0 }
     }
 
-    pub(super) fn init(&self, overrides: impl Iterator<Item = IoApicIntSourceOverride>) {
-        info!("Initialising IOAPIC {}", self.0.ioaid);
+    // maps the registers and masks every entry, they are unmasked once someone asks for the IRQ
+    pub(super) fn init(&mut self) {
+        info!("Initialising IOAPIC {}", self.table.ioaid);
 
         // map MMIO registers
         // just need to map the IOREGSEL and IOREGWIN registers
         // as all the other "registers" are read from / written to using the above two registers
-        let base = PhysicalAddress::new(self.0.ioapic_addr as u64);
+        let base = PhysicalAddress::new(self.table.ioapic_addr as u64);
         mmio::map(base, base.offset(0x1f));
 
-        // TODO: Use AML to discern mappings not available in overrides
-        // setting some default entries (gsi = irq), eg: keyboard
-        let default_entries = [IoApicIntSourceOverride {
-            bus_source: 0x0,
-            irq_source: 0x1,
-            gsi: 0x1,
-            flags: 0x0,
-        }];
-
         let ioapicver = self.read_reg(1);
-        let num_gsi = (ioapicver >> 16) as u8 + 1;
-        // overrides are chained to default_entries and not the other way around
-        // as I want the overrides to be fiven priority in case of common entries
-        default_entries
-            .into_iter()
-            .chain(overrides)
-            .filter(|&or| self.0.gsib <= or.gsi && or.gsi < self.0.gsib + num_gsi as u32)
-            .for_each(|or| {
-                trace!("got override: {:#x?}", or);
-                let index = or.gsi - self.0.gsib;
-                // TODO: hack for HPET interrupt routing, do it properly
-                let int_vec = or.irq_source + 0x20 + if index == 2 { 0xf } else { 0 };
-                let pin_polarity = or.flags & 0x2 != 0;
-                let trigger_mode = or.flags & 0x8 != 0;
-                // TODO: you may want to disable certain interrupts
-                let disable = false;
-                // TODO: need to change this when SMP is in play
-                let destination = 0;
-
-                let mut val = ((destination as u64) << 56) | int_vec as u64;
-                if pin_polarity {
-                    val |= 1 << 13;
-                }
-                if trigger_mode {
-                    val |= 1 << 15;
-                }
-                if disable {
-                    val |= 1 << 16;
-                }
-                self.write_ioredtbl(index, val);
-            });
-
+        self.num_gsi = ((ioapicver >> 16) & 0xff) + 1;
         trace!("    version: {}", ioapicver & 0xff);
-        trace!("    num of irqs: {}", num_gsi);
-        for index in 0..num_gsi as u32 {
-            let entry = self.read_ioredtbl(index);
-            trace!("    IRQ {}", index);
-            trace!("        vector: {}", entry as u8);
-            trace!("        delivery mode: {:#b}", (entry >> 8) & 0b111);
-            trace!("        destination mode: {}", (entry >> 11) & 1);
-            trace!("        delivery status: {}", (entry >> 12) & 1);
-            trace!("        pin polarity: {}", (entry >> 13) & 1);
-            trace!("        remote IRR: {}", (entry >> 14) & 1);
-            trace!("        trigger mode: {}", (entry >> 15) & 1);
-            trace!("        mask: {}", (entry >> 16) & 1);
-            trace!("        destination: {:#b}", (entry >> 56) as u8);
+        trace!("    num of irqs: {}", self.num_gsi);
+
+        for index in 0..self.num_gsi {
+            self.write_ioredtbl(index, MASKED);
         }
     }
 
+    pub(super) fn handles(&self, gsi: u32) -> bool {
+        let gsib = self.table.gsib;
+        gsib <= gsi && gsi < gsib + self.num_gsi
+    }
+
+    // delivers `gsi` as `vector` to the BSP
+    // TODO: need to change this when SMP is in play
+    pub(super) fn route(&self, gsi: u32, vector: u8, trigger: Trigger) {
+        let destination = 0u64;
+        let mut val = (destination << 56) | vector as u64;
+        if trigger.active_low {
+            val |= ACTIVE_LOW;
+        }
+        if trigger.level {
+            val |= LEVEL_TRIGGERED;
+        }
+        self.write_ioredtbl(gsi - self.table.gsib, val);
+    }
+
+    pub(super) fn mask(&self, gsi: u32) {
+        let index = gsi - self.table.gsib;
+        let val = self.read_ioredtbl(index);
+        self.write_ioredtbl(index, val | MASKED);
+    }
+
     #[inline]
     fn write_ioregsel(&self, index: u32) {
         unsafe {
-            let addr = PhysicalAddress::new(self.0.ioapic_addr as u64)
+            let addr = PhysicalAddress::new(self.table.ioapic_addr as u64)
                 .to_virt()
                 .unwrap()
                 .to_inner() as *mut u32;
@@ -92,7 +78,7 @@ impl IoApic {
     #[inline]
     fn write_ioregwin(&self, val: u32) {
         unsafe {
-            let addr = PhysicalAddress::new(self.0.ioapic_addr as u64 + 0x10)
+            let addr = PhysicalAddress::new(self.table.ioapic_addr as u64 + 0x10)
                 .to_virt()
                 .unwrap()
                 .to_inner() as *mut u32;
@@ -103,7 +89,7 @@ impl IoApic {
     #[inline]
     fn read_ioregwin(&self) -> u32 {
         unsafe {
-            let addr = PhysicalAddress::new(self.0.ioapic_addr as u64 + 0x10)
+            let addr = PhysicalAddress::new(self.table.ioapic_addr as u64 + 0x10)
                 .to_virt()
                 .unwrap()
                 .to_inner() as *mut u32;
@@ -139,7 +125,7 @@ impl IoApic {
     #[inline]
     fn write_ioredtbl(&self, index: u32, val: u64) {
         let lower_val = (val & 0xffffffff) as u32;
-        let higher_val = (val > 32) as u32;
+        let higher_val = (val >> 32) as u32;
 
         let lower_index = 0x10 + 2 * index;
         self.write_ioregsel(lower_index);
@@ -1,12 +1,17 @@
 use log::trace;
 
 use crate::{
-    arch::x86_64::{paging::mmio, rdmsr, timers::hpet::Hpet, wrmsr},
+    arch::x86_64::{
+        paging::mmio,
+        rdmsr,
+        timers::{clocksource, tsc},
+        wrmsr,
+    },
     mem::{PhysicalAddress, VirtualAddress},
 };
 use core::{
     arch::x86_64::CpuidResult,
-    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
 };
 
 pub const MSR_APIC_REG_BASE: u32 = 0x1b;
@@ -18,6 +23,15 @@ pub(super) const LAPIC_PRESENT: u32 = 1 << 9;
 // WARNING: Assuming that APIC timers on all LAPICs have same frequency
 static APIC_TIMER_FREQ: AtomicU64 = AtomicU64::new(0);
 static APIC_TIMER_DIVIDER: AtomicU8 = AtomicU8::new(0);
+// virtual address of the LAPIC registers, 0 till the LAPIC is initialised
+// avoids reading the MSR on hot paths (see `current_apic_id`)
+static LAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+// the one-shot timer is driven by IA32_TSC_DEADLINE instead of the initial count register
+static TSC_DEADLINE_MODE: AtomicBool = AtomicBool::new(false);
+
+const IA32_TSC_DEADLINE_MSR: u32 = 0x6e0;
+
+const ID_REG: u64 = 0x20;
 
 #[derive(Debug)]
 pub(in super::super) struct Lapic {
@@ -37,6 +51,18 @@ pub(in super::super) unsafe fn get_lapic() -> Lapic {
     }
 }
 
+// id of the Local APIC of the processor running this code
+// returns 0 till the Local APIC is initialised (only the BSP runs by then)
+pub(in super::super) fn current_apic_id() -> u8 {
+    let base = LAPIC_BASE.load(Ordering::Relaxed);
+    if base == 0 {
+        return 0;
+    }
+    // SAFETY: the registers are mapped once the Local APIC is initialised
+    let id = unsafe { core::intrinsics::volatile_load((base + ID_REG) as *const u32) };
+    (id >> 24) as u8
+}
+
 // SAFETY: only to be called once the Local APIC is initialised
 pub unsafe fn send_eoi() {
     let lapic = get_lapic();
@@ -45,7 +71,7 @@ pub unsafe fn send_eoi() {
 
 impl Lapic {
     // pub(super) fn new(table: acpi::LocalApic) -> Self {
-    pub(super) fn init(hpet: &Hpet) -> Self {
+    pub(super) fn init() -> Self {
         unsafe {
             // ensure presence of LAPIC on this core
             let CpuidResult { edx, .. } = core::arch::x86_64::__cpuid(1);
@@ -74,7 +100,9 @@ impl Lapic {
             // set spurious vector and APIC software enable flag
             lapic.write_reg(0xf0, 0xff | 0x100);
 
-            lapic.init_timer(hpet);
+            lapic.init_timer();
+
+            LAPIC_BASE.store(base.to_inner(), Ordering::Relaxed);
 
             lapic
         }
@@ -109,43 +137,34 @@ impl Lapic {
     const TIMER_LVT: u16 = 0x320;
     const TIMER_INTERRUPT: u16 = 16;
 
-    unsafe fn init_timer(&mut self, hpet: &Hpet) {
+    unsafe fn init_timer(&mut self) {
         let divider = 128;
         self.set_timer_divider(divider);
         // mask interrupts
         self.mask_timer_interrupts(true);
 
-        // start HPET for calibration
-        hpet.write_main_counter(0);
-        // 1 sec
-        let counter = hpet.ns_to_counter(10u64.pow(9));
-
         // APIC timer initial count
         self.set_timer_initial_count(u32::MAX);
 
-        // wait till you get the HPET interrupt
-        while hpet.read_main_counter() < counter {
-            core::hint::spin_loop();
-        }
+        // count the APIC timer ticks over 1 sec of the best clock available (HPET, TSC or PIT)
+        let calibration = clocksource::calibrate(10u64.pow(9), || self.get_timer_current_count());
 
         // WARNING: The method assumes that there were no wrap arounds in the Apic timer
         // during this time.
         // For this to be true, try to keep the divisor value high (64 / 128) for calibration
 
         // calculate APIC timer ticks during this time
-        let ticks_occurred_in_1_sec = u32::MAX - self.get_timer_current_count();
+        let ticks = (calibration.start - calibration.end) as u64;
+        let ticks_per_sec = ticks * 10u64.pow(9) / calibration.elapsed_ns;
         trace!(
             "APIC timer frequency {} for divider {}",
-            ticks_occurred_in_1_sec,
+            ticks_per_sec,
             divider
         );
 
         // calculate frequency and store it for further use
         let new_divider = 64;
-        APIC_TIMER_FREQ.store(
-            ticks_occurred_in_1_sec as u64 * divider as u64,
-            Ordering::Relaxed,
-        );
+        APIC_TIMER_FREQ.store(ticks_per_sec * divider as u64, Ordering::Relaxed);
         self.set_timer_divider(new_divider);
         APIC_TIMER_DIVIDER.store(new_divider, Ordering::Relaxed);
 
@@ -153,13 +172,24 @@ impl Lapic {
         // let net_freq = (ticks_occurred_in_1_sec as u64 * divider as u64) / new_divider as u64;
         // self.timer_freq_considering_divider = Some(net_freq as u32);
 
-        // unset interrupt mask, set mode to oneshot, set interrupt to 32 (IRQ0)
+        // unset interrupt mask, set mode to oneshot (or TSC deadline), set interrupt to 32 (IRQ0)
         self.set_timer_interrupt_vector(32); // IRQ0
-        self.set_timer_mode(ApicTimerMode::Oneshot);
+        self.set_timer_initial_count_in_ns(0);
+
+        // TSC deadline mode has no limit on how far ahead the timer can be armed
+        // and doesn't need converting the time into the LAPIC timer ticks
+        if tsc::supports_tsc_deadline() && tsc::is_usable() {
+            self.set_timer_mode(ApicTimerMode::TscDeadline);
+            // Intel SDM Vol3 11.5.4.1: order the LVT write before the writes to IA32_TSC_DEADLINE
+            core::arch::asm!("mfence", options(nostack));
+            TSC_DEADLINE_MODE.store(true, Ordering::Relaxed);
+            trace!("APIC timer in TSC deadline mode");
+        } else {
+            self.set_timer_mode(ApicTimerMode::Oneshot);
+        }
 
         // self.set_timer_initial_count(2 * ticks_occurred_in_1_sec);
         // self.set_timer_initial_count_in_ns(2 * 10u32.pow(9));
-        self.set_timer_initial_count_in_ns(0);
 
         self.mask_timer_interrupts(false);
     }
@@ -223,13 +253,35 @@ impl Lapic {
         self.read_reg(Self::CURRENT_COUNT)
     }
 
-    pub(in super::super) fn set_timer_initial_count_in_ns(&self, ns: u32) {
-        // cast to u64 to avoid overflow while calculating
+    // delays longer than what the counter can hold are cut short
+    // the timer users are expected to arm it again if it fires early
+    fn set_timer_initial_count_in_ns(&self, ns: u64) {
         let timer_freq_considering_divider = APIC_TIMER_FREQ.load(Ordering::Relaxed)
             / APIC_TIMER_DIVIDER.load(Ordering::Relaxed) as u64;
-        let init_count = (timer_freq_considering_divider * ns as u64) / 10u64.pow(9);
-        assert!(init_count < u32::MAX as u64);
-        self.set_timer_initial_count(init_count as u32);
+        // u128 to avoid overflow while calculating
+        let init_count = (timer_freq_considering_divider as u128 * ns as u128) / 10u128.pow(9);
+        let mut init_count = init_count.min(u32::MAX as u128) as u32;
+        // 0 stops the timer, don't let a short delay round down to it
+        if ns != 0 {
+            init_count = init_count.max(1);
+        }
+        self.set_timer_initial_count(init_count);
+    }
+
+    // arms the one-shot timer to go off `ns` from now
+    // `None` disarms it
+    pub(in super::super) fn arm_timer(&self, ns: Option<u64>) {
+        if TSC_DEADLINE_MODE.load(Ordering::Relaxed) {
+            // writing 0 disarms the timer
+            let deadline = ns.map_or(0, |ns| tsc::rdtsc() + tsc::ns_to_ticks(ns).max(1));
+            // SAFETY: TSC deadline mode is supported and enabled
+            unsafe {
+                wrmsr(IA32_TSC_DEADLINE_MSR, deadline);
+            }
+        } else {
+            // writing 0 stops the timer
+            self.set_timer_initial_count_in_ns(ns.unwrap_or(0));
+        }
     }
 }
 
@@ -262,5 +314,5 @@ impl Lapic {
 enum ApicTimerMode {
     Oneshot = 0,
     Periodic = 1,
-    // TscDeadline = 2, // not supported
+    TscDeadline = 2,
 }
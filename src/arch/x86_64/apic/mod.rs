@@ -2,17 +2,49 @@ mod ioapic;
 pub(super) mod lapic;
 
 use alloc::vec::Vec;
+use spin::Once;
 
-use super::{acpi, pic, timers::hpet::Hpet};
-use crate::arch::x86_64::smp::is_bsp;
+use super::{acpi, pic};
+use crate::{arch::x86_64::smp::is_bsp, locks::SpinLockIrq};
+use ioapic::IoApic;
 pub use lapic::send_eoi;
 
-pub(super) fn init(madt_entries: &Vec<acpi::MadtEntry>, hpet: &Hpet) {
+// the IOAPICs, the register window of each is used by one core at a time
+static IOAPICS: Once<Vec<SpinLockIrq<IoApic>>> = Once::new();
+// how the ISA IRQs are connected to the IOAPICs, where it's not the identity mapping
+static OVERRIDES: Once<Vec<acpi::IoApicIntSourceOverride>> = Once::new();
+
+// how a GSI signals an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Trigger {
+    // edge triggered otherwise
+    pub(super) level: bool,
+    pub(super) active_low: bool,
+}
+
+impl Trigger {
+    // what the ISA devices and the message signalled interrupts use
+    pub(super) const EDGE_HIGH: Self = Self {
+        level: false,
+        active_low: false,
+    };
+
+    // MPS INTI flags, bits 0-1: polarity, bits 2-3: trigger mode
+    // 0b11 is active low (level triggered), 0b00 conforms to the bus (ISA: active high, edge triggered)
+    fn from_inti_flags(flags: u16) -> Self {
+        Self {
+            level: flags & 0x8 != 0,
+            active_low: flags & 0x2 != 0,
+        }
+    }
+}
+
+pub(super) fn init(madt_entries: &[acpi::MadtEntry]) {
     unsafe {
         pic::disable();
     }
 
-    lapic::Lapic::init(hpet);
+    lapic::Lapic::init();
 
     // IoApic is common to all the cores
     // So no need to do the same work multiple times in case of SMP
@@ -20,19 +52,59 @@ pub(super) fn init(madt_entries: &Vec<acpi::MadtEntry>, hpet: &Hpet) {
         return;
     }
 
-    for entry in madt_entries {
-        match entry {
-            // MadtEntry::LocalApic(lapic) => init_lapic(lapic),
-            acpi::MadtEntry::IoApic(ioapic) => {
-                ioapic::IoApic::new(*ioapic).init(madt_entries.iter().filter_map(|entry| {
-                    if let acpi::MadtEntry::IoApicIntSourceOverride(or) = entry {
-                        Some(*or)
-                    } else {
-                        None
-                    }
-                }))
-            }
-            _ => {}
-        }
-    }
+    OVERRIDES.call_once(|| {
+        madt_entries
+            .iter()
+            .filter_map(|entry| match entry {
+                acpi::MadtEntry::IoApicIntSourceOverride(or) => Some(*or),
+                _ => None,
+            })
+            .collect()
+    });
+    IOAPICS.call_once(|| {
+        madt_entries
+            .iter()
+            .filter_map(|entry| match entry {
+                acpi::MadtEntry::IoApic(table) => {
+                    let mut ioapic = IoApic::new(*table);
+                    ioapic.init();
+                    Some(SpinLockIrq::new(ioapic))
+                }
+                _ => None,
+            })
+            .collect()
+    });
+}
+
+// the GSI the ISA IRQ `irq` is connected to
+pub(super) fn isa_gsi(irq: u8) -> u32 {
+    OVERRIDES
+        .r#try()
+        .and_then(|overrides| overrides.iter().find(|or| or.irq_source == irq))
+        .map_or(irq as u32, |or| or.gsi)
+}
+
+// how `gsi` is triggered according to the firmware, if it says anything about it
+// TODO: use AML (_PRT) to find out about the PCI interrupts
+pub(super) fn firmware_trigger(gsi: u32) -> Option<Trigger> {
+    let overrides = OVERRIDES.r#try()?;
+    let or = overrides.iter().find(|or| or.gsi == gsi)?;
+    Some(Trigger::from_inti_flags(or.flags))
+}
+
+fn with_ioapic<R>(gsi: u32, f: impl FnOnce(&IoApic) -> R) -> Option<R> {
+    let ioapic = IOAPICS
+        .r#try()?
+        .iter()
+        .find(|ioapic| ioapic.lock().handles(gsi))?;
+    Some(f(&ioapic.lock()))
+}
+
+// delivers `gsi` as `vector`, returns false if no IOAPIC handles it
+pub(super) fn route_gsi(gsi: u32, vector: u8, trigger: Trigger) -> bool {
+    with_ioapic(gsi, |ioapic| ioapic.route(gsi, vector, trigger)).is_some()
+}
+
+pub(super) fn mask_gsi(gsi: u32) {
+    with_ioapic(gsi, |ioapic| ioapic.mask(gsi));
 }
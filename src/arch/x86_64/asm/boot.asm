@@ -11,6 +11,21 @@ dd MAGIC
 dd ARCH
 dd HEADER_LENGTH
 dd CHECKSUM
+
+; framebuffer request, the bootloader picks the closest mode it has
+; optional, the kernel falls back to the VGA text buffer without it
+align 8, db 0
+framebuffer_tag_start:
+dw 0x5
+dw 0x1
+dd framebuffer_tag_end - framebuffer_tag_start
+dd 1024 ; width
+dd 768  ; height
+dd 32   ; depth
+framebuffer_tag_end:
+
+; end tag
+align 8, db 0
 dw 0x0
 dw 0x0
 dq 0x8
@@ -0,0 +1,209 @@
+use super::{Display, DisplayError, Mode};
+use crate::{
+    arch::x86_64::{
+        framebuffer::{Framebuffer, PixelFormat},
+        pci::{HeaderType, PciEnumerate},
+        port::Port,
+    },
+    mem::PhysicalAddress,
+    multiboot::ColourField,
+};
+
+// Bochs Graphics Adapter, the "standard VGA" of QEMU and Bochs
+// https://wiki.osdev.org/Bochs_VBE_Extensions
+
+const VENDOR_ID: u16 = 0x1234;
+const DEVICE_ID: u16 = 0x1111;
+
+// the dispi registers are selected through the index port and accessed through the data port
+const INDEX_PORT: u16 = 0x1ce;
+const DATA_PORT: u16 = 0x1cf;
+
+const INDEX_ID: u16 = 0x0;
+const INDEX_XRES: u16 = 0x1;
+const INDEX_YRES: u16 = 0x2;
+const INDEX_BPP: u16 = 0x3;
+const INDEX_ENABLE: u16 = 0x4;
+const INDEX_VIRT_WIDTH: u16 = 0x6;
+const INDEX_X_OFFSET: u16 = 0x8;
+const INDEX_Y_OFFSET: u16 = 0x9;
+// in 64 KiB blocks
+const INDEX_VIDEO_MEMORY_64K: u16 = 0xa;
+
+// the versions go from 0xb0c0 to 0xb0c5
+const ID_MIN: u16 = 0xb0c0;
+
+// enable register bits
+const DISABLED: u16 = 0x0;
+const ENABLED: u16 = 0x1;
+// the resolution and depth registers read as the maximum values while set
+const GET_CAPS: u16 = 0x2;
+const LFB_ENABLED: u16 = 0x40;
+
+// QEMU's default, for the old versions that can't tell
+const DEFAULT_VIDEO_MEMORY: usize = 16 * 1024 * 1024;
+
+struct Registers {
+    index: Port,
+    data: Port,
+}
+
+impl Registers {
+    fn read(&mut self, index: u16) -> u16 {
+        // SAFETY: the ports belong to the BGA, checked that it's there before creating this
+        unsafe {
+            self.index.write(index);
+            self.data.read()
+        }
+    }
+
+    fn write(&mut self, index: u16, value: u16) {
+        // SAFETY: the ports belong to the BGA, checked that it's there before creating this
+        unsafe {
+            self.index.write(index);
+            self.data.write(value);
+        }
+    }
+}
+
+pub(super) struct Bga {
+    regs: Registers,
+    // linear framebuffer, BAR0
+    lfb: PhysicalAddress,
+    video_memory: usize,
+    max_mode: Mode,
+    mode: Option<Mode>,
+    framebuffer: Option<Framebuffer>,
+}
+
+impl Bga {
+    pub(super) fn probe() -> Option<Self> {
+        let mut device =
+            PciEnumerate::new().find(|p| p.vendor_id == VENDOR_ID && p.device_id == DEVICE_ID)?;
+        let HeaderType::Type0(mut header) = device.header_type else {
+            return None;
+        };
+        let [mut bar0, ..] = header.bars(device.bus, device.device, device.function);
+        let lfb = bar0.mem_addr()?;
+        // memory space decoding
+        let command = device.command();
+        device.write_command(command | 0b10);
+
+        let mut regs = Registers {
+            index: Port::new(INDEX_PORT),
+            data: Port::new(DATA_PORT),
+        };
+        if regs.read(INDEX_ID) < ID_MIN {
+            return None;
+        }
+
+        let video_memory = match regs.read(INDEX_VIDEO_MEMORY_64K) as usize {
+            0 => DEFAULT_VIDEO_MEMORY,
+            blocks => blocks * 64 * 1024,
+        };
+
+        // GET_CAPS doesn't change the mode as long as ENABLED stays as it is
+        let enable = regs.read(INDEX_ENABLE);
+        regs.write(INDEX_ENABLE, enable | GET_CAPS);
+        let max_mode = Mode {
+            width: regs.read(INDEX_XRES) as usize,
+            height: regs.read(INDEX_YRES) as usize,
+            bpp: regs.read(INDEX_BPP) as u8,
+        };
+        regs.write(INDEX_ENABLE, enable);
+
+        let mut bga = Self {
+            regs,
+            lfb,
+            video_memory,
+            max_mode,
+            mode: None,
+            framebuffer: None,
+        };
+        // the bootloader may have set a mode already
+        if enable & ENABLED != 0 {
+            bga.mode = Some(bga.current_mode());
+            bga.framebuffer = bga.current_framebuffer();
+        }
+        Some(bga)
+    }
+
+    fn current_mode(&mut self) -> Mode {
+        Mode {
+            width: self.regs.read(INDEX_XRES) as usize,
+            height: self.regs.read(INDEX_YRES) as usize,
+            bpp: self.regs.read(INDEX_BPP) as u8,
+        }
+    }
+
+    fn current_framebuffer(&mut self) -> Option<Framebuffer> {
+        let mode = self.current_mode();
+        let bytes_per_pixel = (mode.bpp as usize).div_ceil(8);
+        let pitch = self.regs.read(INDEX_VIRT_WIDTH) as usize * bytes_per_pixel;
+        let format = match mode.bpp {
+            24 | 32 => PixelFormat {
+                red: ColourField::new(16, 8),
+                green: ColourField::new(8, 8),
+                blue: ColourField::new(0, 8),
+            },
+            16 => PixelFormat {
+                red: ColourField::new(11, 5),
+                green: ColourField::new(5, 6),
+                blue: ColourField::new(0, 5),
+            },
+            // palette modes
+            _ => return None,
+        };
+        Framebuffer::new(self.lfb, pitch, mode.width, mode.height, mode.bpp, format)
+    }
+}
+
+impl Display for Bga {
+    fn name(&self) -> &'static str {
+        "bga"
+    }
+
+    fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    fn max_mode(&self) -> Mode {
+        self.max_mode
+    }
+
+    fn set_mode(&mut self, mode: Mode) -> Result<(), DisplayError> {
+        // the framebuffer can only handle these
+        if !matches!(mode.bpp, 16 | 24 | 32)
+            || mode.width == 0
+            || mode.height == 0
+            || mode.width > self.max_mode.width
+            || mode.height > self.max_mode.height
+            || mode.bpp > self.max_mode.bpp
+            || mode.width * mode.height * (mode.bpp as usize / 8) > self.video_memory
+        {
+            return Err(DisplayError::UnsupportedMode);
+        }
+
+        // the mode registers can only be changed while disabled
+        self.regs.write(INDEX_ENABLE, DISABLED);
+        self.regs.write(INDEX_XRES, mode.width as u16);
+        self.regs.write(INDEX_YRES, mode.height as u16);
+        self.regs.write(INDEX_BPP, mode.bpp as u16);
+        self.regs.write(INDEX_X_OFFSET, 0);
+        self.regs.write(INDEX_Y_OFFSET, 0);
+        self.regs.write(INDEX_ENABLE, ENABLED | LFB_ENABLED);
+
+        // the adapter may have adjusted the values (e.g. rounded the width)
+        let set = self.current_mode();
+        self.framebuffer = self.current_framebuffer();
+        self.mode = Some(set);
+        if set != mode || self.framebuffer.is_none() {
+            return Err(DisplayError::UnsupportedMode);
+        }
+        Ok(())
+    }
+
+    fn framebuffer(&mut self) -> Option<&mut Framebuffer> {
+        self.framebuffer.as_mut()
+    }
+}
@@ -0,0 +1,95 @@
+mod bga;
+
+use super::framebuffer::{self, Framebuffer, Rgb};
+use crate::{cmdline::params, locks::SpinLock};
+use alloc::boxed::Box;
+use log::{info, warn};
+
+// there's only ever one display adapter in the machines we run on
+static DISPLAY: SpinLock<Option<Box<dyn Display>>> = SpinLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Mode {
+    pub(super) width: usize,
+    pub(super) height: usize,
+    // bits per pixel
+    pub(super) bpp: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DisplayError {
+    // the adapter can't do the resolution or the colour depth
+    UnsupportedMode,
+    // there's no display adapter driver for the machine
+    NoDisplay,
+}
+
+// a display adapter whose mode can be changed at runtime
+pub(super) trait Display: Send {
+    fn name(&self) -> &'static str;
+    // `None` in text mode
+    fn mode(&self) -> Option<Mode>;
+    // the highest resolution and colour depth the adapter supports
+    fn max_mode(&self) -> Mode;
+    // the screen is cleared
+    fn set_mode(&mut self, mode: Mode) -> Result<(), DisplayError>;
+    // the framebuffer of the current mode, `None` in text mode
+    fn framebuffer(&mut self) -> Option<&mut Framebuffer>;
+
+    // copies a `width` pixels wide rectangle of 0xRRGGBB colours, row after row
+    // nothing draws graphics yet, only the console uses the screen
+    #[allow(dead_code)]
+    fn blit(&mut self, x: usize, y: usize, width: usize, pixels: &[u32]) {
+        let Some(framebuffer) = self.framebuffer() else {
+            return;
+        };
+        if width == 0 {
+            return;
+        }
+        for (dy, row) in pixels.chunks(width).enumerate() {
+            for (dx, &rgb) in row.iter().enumerate() {
+                let [b, g, r, _] = rgb.to_le_bytes();
+                let pixel = framebuffer.encode(Rgb(r, g, b));
+                framebuffer.put_pixel(x + dx, y + dy, pixel);
+            }
+        }
+    }
+}
+
+// switches the display to `mode` and moves the console onto it
+pub(super) fn set_mode(mode: Mode) -> Result<(), DisplayError> {
+    let framebuffer = {
+        let mut display = DISPLAY.lock();
+        let display = display.as_mut().ok_or(DisplayError::NoDisplay)?;
+        display.set_mode(mode)?;
+        display.framebuffer().cloned()
+    };
+    info!("[display] switched to {:?}", mode);
+    if let Some(framebuffer) = framebuffer {
+        framebuffer::attach_console(framebuffer);
+    }
+    Ok(())
+}
+
+// looks for a display adapter we have a driver for
+// has to run after PCI is set up
+pub(super) fn init() {
+    let Some(display) = bga::Bga::probe() else {
+        info!("[display] no display adapter found");
+        return;
+    };
+    info!(
+        "[display] found {}, mode: {:?}, max: {:?}",
+        display.name(),
+        display.mode(),
+        display.max_mode()
+    );
+    *DISPLAY.lock() = Some(Box::new(display));
+
+    if let Some((width, height, bpp)) = params().video {
+        let mode = Mode { width, height, bpp };
+        if let Err(err) = set_mode(mode) {
+            warn!("[display] can't switch to {:?}: {:?}", mode, err);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::mem::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use crate::HIGHER_HALF;
 
 use super::{EntryFlags, P4Table};
+use alloc::alloc::{alloc_zeroed, Layout};
 use bitflags::bitflags;
+use core::ptr::addr_of;
 use log::info;
 
 bitflags! {
@@ -22,7 +25,6 @@ impl From<Elf64SegmentFlags> for EntryFlags {
         // we are mapping user accessible pages here
         flags |= Self::USER_ACCESSIBLE;
 
-
         // all LOAD segments should be readable?
 
         if sflags.contains(Elf64SegmentFlags::PF_R) {
@@ -41,6 +43,22 @@ impl From<Elf64SegmentFlags> for EntryFlags {
     }
 }
 
+// loads an ELF that's not in the memory the bootloader put it in (e.g. read from a file)
+// the segments are mapped in place, so the image is copied to page aligned memory that's never freed
+pub(super) fn load_elf_image(image: &[u8]) -> (VirtualAddress, P4Table) {
+    let size = (image.len().max(1) as u64).next_multiple_of(PAGE_SIZE);
+    let layout = Layout::from_size_align(size as usize, PAGE_SIZE as usize).unwrap();
+    // SAFETY: the layout is not zero sized, the copy fits in the allocation
+    let copy = unsafe {
+        let copy = alloc_zeroed(layout);
+        assert!(!copy.is_null());
+        copy.copy_from_nonoverlapping(image.as_ptr(), image.len());
+        copy
+    };
+    let start = PhysicalAddress::new(copy as u64 - unsafe { addr_of!(HIGHER_HALF) } as u64);
+    load_elf(start, image.len())
+}
+
 pub(super) fn load_elf(start: PhysicalAddress, size: usize) -> (VirtualAddress, P4Table) {
     info!("[load_elf] start: {:#x?}, size: {:#x?}", start, size);
 
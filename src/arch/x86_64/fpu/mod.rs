@@ -0,0 +1,251 @@
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use core::arch::x86_64::{__cpuid, __cpuid_count, CpuidResult};
+use log::info;
+use spin::Once;
+
+// CR0 bits
+const CR0_MP: u64 = 1 << 1; // monitor coprocessor, `wait`/`fwait` honour TS
+const CR0_EM: u64 = 1 << 2; // x87 emulation, must be clear
+const CR0_TS: u64 = 1 << 3; // task switched, next FPU/SSE instruction raises #NM
+const CR0_NE: u64 = 1 << 5; // native x87 error reporting (#MF instead of IRQ13)
+
+// CR4 bits
+const CR4_OSFXSR: u64 = 1 << 9; // fxsave/fxrstor and SSE instructions
+const CR4_OSXMMEXCPT: u64 = 1 << 10; // unmasked SSE exceptions raise #XM
+const CR4_OSXSAVE: u64 = 1 << 18; // xsave/xrstor and XCR0
+
+// XCR0 bits (state components)
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+
+// fxsave area is always 512 bytes, 16 byte aligned
+const FXSAVE_AREA_SIZE: usize = 512;
+// xsave area needs 64 byte alignment
+const XSAVE_ALIGN: usize = 64;
+// MXCSR offset within both the fxsave and the xsave area (legacy region)
+const MXCSR_OFFSET: usize = 24;
+// all exceptions masked, round to nearest (same as after reset)
+const MXCSR_DEFAULT: u32 = 0x1f80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SaveMethod {
+    Fxsave,
+    // with the mask of the state components enabled in XCR0
+    Xsave(u64),
+}
+
+#[derive(Debug)]
+struct FpuInfo {
+    method: SaveMethod,
+    // size of the save area
+    size: usize,
+    align: usize,
+    // state every new task starts with
+    initial_state: &'static [u8],
+}
+
+static FPU_INFO: Once<FpuInfo> = Once::new();
+
+unsafe fn read_cr0() -> u64 {
+    let cr0;
+    core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack));
+    cr0
+}
+
+unsafe fn write_cr0(cr0: u64) {
+    core::arch::asm!("mov cr0, {}", in(reg) cr0, options(nostack));
+}
+
+unsafe fn read_cr4() -> u64 {
+    let cr4;
+    core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack));
+    cr4
+}
+
+unsafe fn write_cr4(cr4: u64) {
+    core::arch::asm!("mov cr4, {}", in(reg) cr4, options(nostack));
+}
+
+// SAFETY: CR4.OSXSAVE has to be set
+unsafe fn write_xcr0(value: u64) {
+    core::arch::asm!(
+        "xsetbv",
+        in("ecx") 0,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nomem, nostack)
+    );
+}
+
+// the next FPU/SSE/AVX instruction raises #NM
+pub(super) fn set_task_switched() {
+    // SAFETY: only the TS bit is changed
+    unsafe {
+        write_cr0(read_cr0() | CR0_TS);
+    }
+}
+
+// FPU/SSE/AVX instructions can be used again without raising #NM
+pub(super) fn clear_task_switched() {
+    // SAFETY: `clts` only clears CR0.TS
+    unsafe {
+        core::arch::asm!("clts", options(nomem, nostack));
+    }
+}
+
+// the register state of the x87 FPU, SSE and AVX units of a task
+// kept in a buffer aligned as required by `fxsave` / `xsave`
+#[derive(Debug)]
+pub(super) struct FpuState {
+    area: *mut u8,
+}
+
+// the area is owned exclusively by the state
+unsafe impl Send for FpuState {}
+
+impl FpuState {
+    // a fresh state, as if `fninit` was run and MXCSR reset
+    pub(super) fn new() -> Self {
+        let info = FPU_INFO.r#try().expect("fpu is not initialised");
+        // SAFETY: size is non zero
+        let area = unsafe { alloc_zeroed(Self::layout(info)) };
+        assert!(!area.is_null(), "out of memory for the fpu state");
+        // SAFETY: the area is as big as the initial state
+        unsafe {
+            core::ptr::copy_nonoverlapping(info.initial_state.as_ptr(), area, info.size);
+        }
+        Self { area }
+    }
+
+    fn layout(info: &FpuInfo) -> Layout {
+        Layout::from_size_align(info.size, info.align).unwrap()
+    }
+
+    // stores the registers into this state
+    // SAFETY: CR0.TS has to be clear
+    pub(super) unsafe fn save(&mut self) {
+        match FPU_INFO.r#try().unwrap().method {
+            SaveMethod::Fxsave => {
+                core::arch::asm!("fxsave64 [{}]", in(reg) self.area, options(nostack));
+            }
+            SaveMethod::Xsave(mask) => {
+                core::arch::asm!(
+                    "xsave64 [{}]",
+                    in(reg) self.area,
+                    in("eax") mask as u32,
+                    in("edx") (mask >> 32) as u32,
+                    options(nostack)
+                );
+            }
+        }
+    }
+
+    // loads the registers from this state
+    // SAFETY: CR0.TS has to be clear
+    pub(super) unsafe fn restore(&self) {
+        match FPU_INFO.r#try().unwrap().method {
+            SaveMethod::Fxsave => {
+                core::arch::asm!("fxrstor64 [{}]", in(reg) self.area, options(nostack));
+            }
+            SaveMethod::Xsave(mask) => {
+                core::arch::asm!(
+                    "xrstor64 [{}]",
+                    in(reg) self.area,
+                    in("eax") mask as u32,
+                    in("edx") (mask >> 32) as u32,
+                    options(nostack)
+                );
+            }
+        }
+    }
+}
+
+impl Drop for FpuState {
+    fn drop(&mut self) {
+        let info = FPU_INFO.r#try().unwrap();
+        // SAFETY: allocated in `new` with the same layout
+        unsafe {
+            dealloc(self.area, Self::layout(info));
+        }
+    }
+}
+
+// enables the FPU, SSE and (if present) AVX units
+// and finds out how to save their state
+// the kernel itself is compiled without SSE, so only the userspace uses these registers
+// TODO: run on the APs as well, once they start running tasks
+pub(super) fn init() {
+    let CpuidResult { ecx, edx, .. } = unsafe { __cpuid(1) };
+    let has_fxsr = edx & (1 << 24) != 0;
+    let has_sse = edx & (1 << 25) != 0;
+    let has_xsave = ecx & (1 << 26) != 0;
+    assert!(
+        has_fxsr && has_sse,
+        "x86_64 processors must support FXSR and SSE"
+    );
+
+    // SAFETY: setting up the FPU as required by the Intel SDM Vol3 13.1
+    unsafe {
+        let cr0 = read_cr0();
+        write_cr0((cr0 & !(CR0_EM | CR0_TS)) | CR0_MP | CR0_NE);
+
+        let mut cr4 = read_cr4() | CR4_OSFXSR | CR4_OSXMMEXCPT;
+        if has_xsave {
+            cr4 |= CR4_OSXSAVE;
+        }
+        write_cr4(cr4);
+    }
+
+    let (method, size, align) = if has_xsave {
+        // CPUID.(EAX=0DH, ECX=0): EDX:EAX - state components supported in XCR0
+        let CpuidResult { eax, edx, .. } = unsafe { __cpuid_count(0xd, 0) };
+        let supported = ((edx as u64) << 32) | eax as u64;
+        let mask = supported & (XCR0_X87 | XCR0_SSE | XCR0_AVX);
+        // SAFETY: only enabling the supported components, x87 is always supported
+        unsafe {
+            write_xcr0(mask);
+        }
+        // EBX - size of the save area for the components enabled in XCR0
+        let CpuidResult { ebx, .. } = unsafe { __cpuid_count(0xd, 0) };
+        (SaveMethod::Xsave(mask), ebx as usize, XSAVE_ALIGN)
+    } else {
+        (SaveMethod::Fxsave, FXSAVE_AREA_SIZE, 16)
+    };
+
+    // capture the state right after reset as the initial state of every task
+    // the xsave header (at offset 512) is all zeros, which marks every component as being in its initial state
+    let layout = Layout::from_size_align(size, align).unwrap();
+    // SAFETY: size is non zero
+    let initial_state = unsafe { alloc_zeroed(layout) };
+    assert!(!initial_state.is_null());
+    unsafe {
+        core::arch::asm!("fninit", options(nomem, nostack));
+        match method {
+            SaveMethod::Fxsave => {
+                core::arch::asm!("fxsave64 [{}]", in(reg) initial_state, options(nostack));
+            }
+            SaveMethod::Xsave(_) => {
+                // the legacy region is not written for the components in their initial state
+                // so fill in the FPU control word and MXCSR by hand
+                core::ptr::write(initial_state as *mut u16, 0x37f);
+            }
+        }
+        core::ptr::write_unaligned(initial_state.add(MXCSR_OFFSET) as *mut u32, MXCSR_DEFAULT);
+    }
+    let initial_state = unsafe { core::slice::from_raw_parts(initial_state, size) };
+
+    info!(
+        "FPU initialised, save method: {:x?}, save area size: {:#x}",
+        method, size
+    );
+    FPU_INFO.call_once(|| FpuInfo {
+        method,
+        size,
+        align,
+        initial_state,
+    });
+
+    // no task owns the registers yet
+    set_task_switched();
+}
@@ -0,0 +1,240 @@
+use super::{font::Font, Framebuffer, Rgb};
+use crate::arch::x86_64::{
+    ansi::{Action, Attributes, Erase, Parser},
+    vga_buffer::Colour,
+};
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+const TAB_WIDTH: usize = 8;
+// the console is scaled up as long as 80x25 characters still fit
+const MIN_COLS: usize = 80;
+const MIN_ROWS: usize = 25;
+
+// the colours of the VGA text mode, indexed by `Colour`
+const PALETTE: [Rgb; 16] = [
+    Rgb(0x00, 0x00, 0x00),
+    Rgb(0x00, 0x00, 0xaa),
+    Rgb(0x00, 0xaa, 0x00),
+    Rgb(0x00, 0xaa, 0xaa),
+    Rgb(0xaa, 0x00, 0x00),
+    Rgb(0xaa, 0x00, 0xaa),
+    Rgb(0xaa, 0x55, 0x00),
+    Rgb(0xaa, 0xaa, 0xaa),
+    Rgb(0x55, 0x55, 0x55),
+    Rgb(0x55, 0x55, 0xff),
+    Rgb(0x55, 0xff, 0x55),
+    Rgb(0x55, 0xff, 0xff),
+    Rgb(0xff, 0x55, 0x55),
+    Rgb(0xff, 0x55, 0xff),
+    Rgb(0xff, 0xff, 0x55),
+    Rgb(0xff, 0xff, 0xff),
+];
+
+const DEFAULT_ATTRIBUTES: Attributes = Attributes::new(Colour::Yellow, Colour::Black);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    c: char,
+    fg: Colour,
+    bg: Colour,
+}
+
+// text console drawn with a bitmap font
+pub(super) struct Console {
+    framebuffer: Framebuffer,
+    font: Font,
+    // every font pixel is drawn as a `scale` x `scale` square
+    scale: usize,
+    cols: usize,
+    rows: usize,
+    // what's on the screen, row after row
+    cells: Vec<Cell>,
+    col: usize,
+    row: usize,
+    attributes: Attributes,
+    saved_cursor: (usize, usize),
+    // encoded `PALETTE`
+    pixels: [u32; 16],
+    // a character is drawn here first, then copied to the framebuffer in one go
+    glyph_buffer: Vec<u32>,
+    parser: Parser,
+}
+
+impl Console {
+    pub(super) fn new(mut framebuffer: Framebuffer, font: Font) -> Self {
+        let scale = (framebuffer.width() / (MIN_COLS * font.width))
+            .min(framebuffer.height() / (MIN_ROWS * font.height))
+            .max(1);
+        let cols = framebuffer.width() / (font.width * scale);
+        let rows = framebuffer.height() / (font.height * scale);
+        let pixels = PALETTE.map(|colour| framebuffer.encode(colour));
+        let glyph_buffer = vec![0; font.width * scale * font.height * scale];
+
+        let blank = Cell {
+            c: ' ',
+            fg: DEFAULT_ATTRIBUTES.fg,
+            bg: DEFAULT_ATTRIBUTES.bg,
+        };
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        framebuffer.fill_rect(0, 0, width, height, pixels[DEFAULT_ATTRIBUTES.bg as usize]);
+
+        Self {
+            framebuffer,
+            font,
+            scale,
+            cols,
+            rows,
+            cells: vec![blank; cols * rows],
+            col: 0,
+            row: 0,
+            attributes: DEFAULT_ATTRIBUTES,
+            saved_cursor: (0, 0),
+            pixels,
+            glyph_buffer,
+            parser: Parser::new(),
+        }
+    }
+
+    // in characters
+    pub(super) fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    fn handle(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.put(c),
+            Action::Control('\n') => self.new_line(),
+            Action::Control('\r') => self.col = 0,
+            Action::Control('\t') => {
+                let next = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.col < next.min(self.cols) {
+                    self.put(' ');
+                }
+            }
+            Action::Control('\x08') => self.col = self.col.saturating_sub(1),
+            Action::Control(_) => {}
+            Action::SetGraphics(params) => self.attributes.apply_sgr(&params, DEFAULT_ATTRIBUTES),
+            Action::CursorUp(n) => self.row = self.row.saturating_sub(n),
+            Action::CursorDown(n) => self.row = (self.row + n).min(self.rows - 1),
+            Action::CursorForward(n) => self.col = (self.col + n).min(self.cols - 1),
+            Action::CursorBack(n) => self.col = self.col.saturating_sub(n),
+            Action::CursorPosition { row, col } => {
+                self.row = row.min(self.rows - 1);
+                self.col = col.min(self.cols - 1);
+            }
+            Action::EraseDisplay(erase) => {
+                let cursor = self.row * self.cols + self.col;
+                let range = match erase {
+                    Erase::ToEnd => cursor..self.cells.len(),
+                    Erase::ToStart => 0..(cursor + 1).min(self.cells.len()),
+                    Erase::All => 0..self.cells.len(),
+                };
+                for i in range {
+                    self.set(i % self.cols, i / self.cols, ' ');
+                }
+            }
+            Action::EraseLine(erase) => {
+                let range = match erase {
+                    Erase::ToEnd => self.col..self.cols,
+                    Erase::ToStart => 0..(self.col + 1).min(self.cols),
+                    Erase::All => 0..self.cols,
+                };
+                for col in range {
+                    self.set(col, self.row, ' ');
+                }
+            }
+            Action::SaveCursor => self.saved_cursor = (self.row, self.col),
+            Action::RestoreCursor => (self.row, self.col) = self.saved_cursor,
+            // there's no cursor drawn
+            Action::ShowCursor(_) => {}
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        if self.col >= self.cols {
+            self.new_line();
+        }
+        self.set(self.col, self.row, c);
+        self.col += 1;
+    }
+
+    fn set(&mut self, col: usize, row: usize, c: char) {
+        let cell = Cell {
+            c,
+            fg: self.attributes.fg,
+            bg: self.attributes.bg,
+        };
+        self.cells[row * self.cols + col] = cell;
+        self.draw(col, row, cell);
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    // moves everything up by a line
+    // reading the framebuffer back is slow, so the screen is redrawn from `cells`
+    // only the cells that change are drawn
+    fn scroll(&mut self) {
+        let blank = Cell {
+            c: ' ',
+            fg: self.attributes.fg,
+            bg: self.attributes.bg,
+        };
+        for i in 0..self.cells.len() {
+            let new = self.cells.get(i + self.cols).copied().unwrap_or(blank);
+            if self.cells[i] != new {
+                self.cells[i] = new;
+                self.draw(i % self.cols, i / self.cols, new);
+            }
+        }
+    }
+
+    fn draw(&mut self, col: usize, row: usize, cell: Cell) {
+        let fg = self.pixels[cell.fg as usize];
+        let bg = self.pixels[cell.bg as usize];
+        let glyph = self.font.glyph(cell.c);
+        let bytes_per_row = self.font.bytes_per_row();
+        let width = self.font.width * self.scale;
+
+        for (y, line) in glyph
+            .chunks(bytes_per_row)
+            .take(self.font.height)
+            .enumerate()
+        {
+            for x in 0..width {
+                let font_x = x / self.scale;
+                let set = line[font_x / 8] & (0x80 >> (font_x % 8)) != 0;
+                let pixel = if set { fg } else { bg };
+                // the font row is repeated `scale` times
+                for dy in 0..self.scale {
+                    self.glyph_buffer[(y * self.scale + dy) * width + x] = pixel;
+                }
+            }
+        }
+
+        self.framebuffer.blit(
+            col * width,
+            row * self.font.height * self.scale,
+            width,
+            &self.glyph_buffer,
+        );
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if let Some(action) = self.parser.advance(c) {
+                self.handle(action);
+            }
+        }
+        Ok(())
+    }
+}
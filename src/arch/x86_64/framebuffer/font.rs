@@ -0,0 +1,73 @@
+// PC Screen Font, the format of the Linux console fonts
+// only the glyph bitmaps are used, the characters are mapped to the glyphs by their code
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x1;
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+
+// 6x8, ASCII only
+pub(super) static DEFAULT_FONT: &[u8] = include_bytes!("font.psf");
+
+pub(super) struct Font {
+    glyphs: &'static [u8],
+    num_glyphs: usize,
+    bytes_per_glyph: usize,
+    pub(super) width: usize,
+    pub(super) height: usize,
+}
+
+impl Font {
+    pub(super) fn parse(data: &'static [u8]) -> Option<Self> {
+        let read_u32 = |offset: usize| -> Option<usize> {
+            let bytes = data.get(offset..offset + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        };
+
+        let (header_size, num_glyphs, bytes_per_glyph, width, height) =
+            if data.starts_with(&PSF1_MAGIC) {
+                let mode = *data.get(2)?;
+                let height = *data.get(3)? as usize;
+                let num_glyphs = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+                // always 8 pixels wide
+                (4, num_glyphs, height, 8, height)
+            } else if data.starts_with(&PSF2_MAGIC) {
+                (
+                    read_u32(8)?,
+                    read_u32(16)?,
+                    read_u32(20)?,
+                    read_u32(28)?,
+                    read_u32(24)?,
+                )
+            } else {
+                return None;
+            };
+
+        // the rows are padded to whole bytes
+        if num_glyphs == 0 || bytes_per_glyph < width.div_ceil(8) * height {
+            return None;
+        }
+        let glyphs = data.get(header_size..header_size + num_glyphs * bytes_per_glyph)?;
+
+        Some(Self {
+            glyphs,
+            num_glyphs,
+            bytes_per_glyph,
+            width,
+            height,
+        })
+    }
+
+    // the rows of the glyph for `c`, the most significant bit is the leftmost pixel
+    // glyph 0 stands in for the characters the font doesn't have
+    pub(super) fn glyph(&self, c: char) -> &'static [u8] {
+        let index = match c as usize {
+            index if index < self.num_glyphs => index,
+            _ => 0,
+        };
+        &self.glyphs[index * self.bytes_per_glyph..(index + 1) * self.bytes_per_glyph]
+    }
+
+    pub(super) fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+}
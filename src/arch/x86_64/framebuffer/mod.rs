@@ -0,0 +1,243 @@
+mod console;
+mod font;
+
+use super::paging::mmio;
+use crate::{
+    locks::SpinLock,
+    mem::{PhysicalAddress, VirtualAddress},
+    multiboot::{ColourField, FramebufferInfo, FramebufferType, MultibootInfo},
+};
+use console::Console;
+use core::fmt;
+use font::{Font, DEFAULT_FONT};
+use log::{info, warn};
+
+static CONSOLE: SpinLock<Option<Console>> = SpinLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Rgb(pub(super) u8, pub(super) u8, pub(super) u8);
+
+// where the channels are in a pixel
+#[derive(Debug, Clone, Copy)]
+pub(super) struct PixelFormat {
+    pub(super) red: ColourField,
+    pub(super) green: ColourField,
+    pub(super) blue: ColourField,
+}
+
+impl PixelFormat {
+    // the value to write for a colour
+    fn encode(&self, Rgb(r, g, b): Rgb) -> u32 {
+        // keeps the most significant bits of each channel
+        let channel = |value: u8, field: ColourField| {
+            let value = value as u32 >> 8u8.saturating_sub(field.size);
+            value << field.position
+        };
+        channel(r, self.red) | channel(g, self.green) | channel(b, self.blue)
+    }
+}
+
+// the bytes taken by the framebuffer, `None` if the pixel size is not supported,
+// or the bootloader made up a mode with nothing in it or with lines too short for their pixels
+fn mode_size(pitch: usize, width: usize, height: usize, bpp: u8) -> Option<usize> {
+    if !matches!(bpp, 16 | 24 | 32) || width == 0 || height == 0 {
+        return None;
+    }
+    if pitch < width.checked_mul(bpp as usize / 8)? {
+        return None;
+    }
+    pitch.checked_mul(height)
+}
+
+// a linear framebuffer in direct colour mode
+// only describes where the pixels are, the copies share the memory
+#[derive(Debug, Clone)]
+pub(super) struct Framebuffer {
+    base: VirtualAddress,
+    // bytes per line
+    pitch: usize,
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    format: PixelFormat,
+}
+
+impl Framebuffer {
+    // maps the framebuffer, `None` if the mode is not supported or doesn't make sense
+    pub(super) fn new(
+        addr: PhysicalAddress,
+        pitch: usize,
+        width: usize,
+        height: usize,
+        bpp: u8,
+        format: PixelFormat,
+    ) -> Option<Self> {
+        let size = mode_size(pitch, width, height, bpp)?;
+
+        mmio::map_write_combining(addr, addr.offset(size as u64 - 1));
+
+        Some(Self {
+            // SAFETY: just mapped
+            base: unsafe { addr.to_virt().unwrap() },
+            pitch,
+            width,
+            height,
+            bytes_per_pixel: bpp as usize / 8,
+            format,
+        })
+    }
+
+    // the framebuffer described by the bootloader
+    fn from_multiboot(info: FramebufferInfo) -> Option<Self> {
+        let FramebufferType::Rgb { red, green, blue } = info.kind else {
+            return None;
+        };
+        Self::new(
+            info.addr,
+            info.pitch as usize,
+            info.width as usize,
+            info.height as usize,
+            info.bpp,
+            PixelFormat { red, green, blue },
+        )
+    }
+
+    pub(super) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(super) fn height(&self) -> usize {
+        self.height
+    }
+
+    pub(super) fn encode(&self, colour: Rgb) -> u32 {
+        self.format.encode(colour)
+    }
+
+    // `pixel` is an encoded colour, out of bounds writes are dropped
+    pub(super) fn put_pixel(&mut self, x: usize, y: usize, pixel: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let offset = y * self.pitch + x * self.bytes_per_pixel;
+        // SAFETY: within the mapped framebuffer, checked above
+        // the writes are volatile, the framebuffer is never read
+        unsafe {
+            let addr = self.base.offset(offset as u64);
+            match self.bytes_per_pixel {
+                4 => core::ptr::write_volatile(addr.as_mut_ptr::<u32>(), pixel),
+                2 => core::ptr::write_volatile(addr.as_mut_ptr::<u16>(), pixel as u16),
+                _ => {
+                    for (i, byte) in pixel.to_le_bytes()[..self.bytes_per_pixel]
+                        .iter()
+                        .enumerate()
+                    {
+                        core::ptr::write_volatile(addr.offset(i as u64).as_mut_ptr::<u8>(), *byte);
+                    }
+                }
+            }
+        }
+    }
+
+    // copies a `width` pixels wide rectangle of encoded colours, row after row
+    // the part that doesn't fit on the screen is dropped
+    pub(super) fn blit(&mut self, x: usize, y: usize, width: usize, pixels: &[u32]) {
+        if width == 0 {
+            return;
+        }
+        for (dy, row) in pixels.chunks(width).enumerate() {
+            for (dx, &pixel) in row.iter().enumerate() {
+                self.put_pixel(x + dx, y + dy, pixel);
+            }
+        }
+    }
+
+    pub(super) fn fill_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixel: u32,
+    ) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.put_pixel(x, y, pixel);
+            }
+        }
+    }
+}
+
+// takes over the screen from the VGA text buffer if the bootloader set a graphics mode
+// has to run after paging is set up
+pub(super) fn init(multiboot_info: &MultibootInfo) {
+    let Some(info) = multiboot_info.framebuffer() else {
+        info!("[framebuffer] no framebuffer, staying with the VGA text buffer");
+        return;
+    };
+    info!("[framebuffer] {:#x?}", info);
+    if info.kind == FramebufferType::EgaText {
+        // the VGA text buffer works as is
+        return;
+    }
+    let Some(framebuffer) = Framebuffer::from_multiboot(info) else {
+        warn!("[framebuffer] unsupported mode");
+        return;
+    };
+
+    attach_console(framebuffer);
+}
+
+// (re)starts the console on `framebuffer`, e.g. after a mode switch
+// what was on the screen is not kept
+pub(super) fn attach_console(framebuffer: Framebuffer) {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let font = Font::parse(DEFAULT_FONT).unwrap();
+    let console = Console::new(framebuffer, font);
+    let (cols, rows) = console.size();
+    *CONSOLE.lock() = Some(console);
+    info!(
+        "[framebuffer] console of {}x{} characters, {}x{}",
+        cols, rows, width, height
+    );
+}
+
+// columns and rows of the console, `None` if there's none
+pub(super) fn console_size() -> Option<(usize, usize)> {
+    CONSOLE.lock().as_ref().map(|console| console.size())
+}
+
+// returns false if there's no framebuffer console (yet)
+pub(super) fn _print(args: fmt::Arguments) -> bool {
+    use core::fmt::Write;
+    let mut console = CONSOLE.lock();
+    let Some(console) = console.as_mut() else {
+        return false;
+    };
+    console.write_fmt(args).unwrap();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_sizes() {
+        assert_eq!(mode_size(4096, 1024, 768, 32), Some(4096 * 768));
+        // padded lines
+        assert_eq!(mode_size(2560, 800, 600, 24), Some(2560 * 600));
+        assert_eq!(mode_size(1600, 800, 600, 16), Some(1600 * 600));
+    }
+
+    #[test]
+    fn bogus_modes() {
+        assert_eq!(mode_size(0, 1024, 768, 32), None);
+        assert_eq!(mode_size(4096, 1024, 0, 32), None);
+        assert_eq!(mode_size(4096, 0, 768, 32), None);
+        assert_eq!(mode_size(4095, 1024, 768, 32), None);
+        assert_eq!(mode_size(4096, 1024, 768, 8), None);
+        assert_eq!(mode_size(usize::MAX, usize::MAX, 2, 32), None);
+        assert_eq!(mode_size(usize::MAX, 1, 2, 32), None);
+    }
+}
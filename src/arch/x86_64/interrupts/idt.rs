@@ -113,11 +113,11 @@ fn lidt(dtp: &DescriptorTablePointer) {
 
 // #[repr(transparent)]
 #[repr(C, align(16))]
-pub(super) struct InterruptDescriptorTable([InterruptDescriptor; 48]);
+pub(super) struct InterruptDescriptorTable([InterruptDescriptor; 256]);
 
 impl InterruptDescriptorTable {
     pub(super) fn new() -> Self {
-        Self([InterruptDescriptor::missing(); 48])
+        Self([InterruptDescriptor::missing(); 256])
     }
 
     pub(super) fn add_handler(
@@ -0,0 +1,308 @@
+use super::isr::HandlerFn;
+use crate::{
+    arch::x86_64::{
+        apic::{self, Trigger},
+        process,
+    },
+    locks::SpinLockIrq,
+};
+use bitflags::bitflags;
+use log::{info, trace, warn};
+
+// the vectors handed out to the devices, the ones below are the exceptions and our fixed ones
+// 0xff is the spurious interrupt vector of the LAPIC
+pub(super) const FIRST_VECTOR: u8 = 0x30;
+pub(super) const LAST_VECTOR: u8 = 0xfe;
+const VECTOR_COUNT: usize = (LAST_VECTOR - FIRST_VECTOR) as usize + 1;
+
+// devices sharing a line
+const MAX_SHARED: usize = 4;
+
+// each vector has a stub that pushes the vector number and jumps to `irq_common`
+// the stubs are 16 bytes apart, the one for `vector` is at `irq_stubs + (vector - FIRST_VECTOR) * 16`
+// `push imm32` is spelt out, in the intel syntax a bare symbol would be read from memory
+core::arch::global_asm!(
+    ".global irq_stubs",
+    ".align 16",
+    "irq_stubs:",
+    ".set irq_vector, {first}",
+    ".rept {count}",
+    ".align 16",
+    ".byte 0x68",
+    ".long irq_vector",
+    "jmp irq_common",
+    ".set irq_vector, irq_vector + 1",
+    ".endr",
+    "irq_common:",
+    // from the userspace, switch to the kernel GS (per CPU data)
+    // the vector is on top of what the CPU pushed
+    "test byte ptr [rsp + 16], 3",
+    "jz 2f",
+    "swapgs",
+    "2:",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    // the vector
+    "mov rdi, [rsp + 9*8]",
+    // the code segment the CPU pushed, after the vector and the return address
+    "mov rsi, [rsp + 11*8]",
+    // the stack is 16 byte aligned before the CPU pushes its 5 registers
+    // with the vector and the 9 above, a call needs 8 more bytes
+    "sub rsp, 8",
+    "call {dispatch}",
+    "add rsp, 8",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    // the vector
+    "add rsp, 8",
+    // back to the userspace, switch back to its GS
+    "test byte ptr [rsp + 8], 3",
+    "jz 3f",
+    "swapgs",
+    "3:",
+    "iretq",
+    first = const FIRST_VECTOR,
+    count = const VECTOR_COUNT,
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    fn irq_stubs();
+}
+
+// where the IDT entry of `vector` points to
+pub(super) fn stub(vector: u8) -> HandlerFn {
+    let addr = irq_stubs as usize + (vector - FIRST_VECTOR) as usize * 16;
+    // SAFETY: the stub is code that ends with `iretq`, the way the other handlers do
+    unsafe { core::mem::transmute(addr) }
+}
+
+bitflags! {
+    pub(crate) struct IrqFlags: u32 {
+        // others may use the line too, all of them have to agree on it
+        const SHARED     = 1 << 0;
+        // how the line is triggered if the firmware doesn't say, edge triggered and active high otherwise
+        // e.g. for the PCI interrupts (level triggered, active low)
+        const LEVEL      = 1 << 1;
+        const ACTIVE_LOW = 1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IrqReturn {
+    // the interrupt didn't come from the device, e.g. someone else on a shared line
+    None,
+    Handled,
+}
+
+// run in the interrupt context with the interrupts disabled
+// `data` is what was passed to `request_irq`, e.g. to tell the devices apart
+pub(crate) type IrqHandler = fn(data: usize) -> IrqReturn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IrqError {
+    // in use by someone who doesn't share it, or with a different trigger mode
+    Busy,
+    NoFreeVector,
+    // no IOAPIC handles the GSI
+    InvalidGsi,
+}
+
+#[derive(Clone, Copy)]
+struct Action {
+    handler: IrqHandler,
+    data: usize,
+}
+
+#[derive(Clone, Copy)]
+struct IrqLine {
+    // `None` for the message signalled interrupts
+    gsi: Option<u32>,
+    shared: bool,
+    trigger: Trigger,
+    actions: [Option<Action>; MAX_SHARED],
+}
+
+// the lines by their vector, `None` for the free vectors
+static LINES: SpinLockIrq<[Option<IrqLine>; VECTOR_COUNT]> = SpinLockIrq::new([None; VECTOR_COUNT]);
+
+// calls `handler` whenever the device on `gsi` raises an interrupt
+// the IOAPIC entry is set up (and unmasked) by the first one to ask for the GSI
+// the trigger mode and the polarity come from the MADT overrides if there are any, from `flags` otherwise
+// returns the vector the GSI is delivered on
+pub(crate) fn request_irq(
+    gsi: u32,
+    handler: IrqHandler,
+    data: usize,
+    flags: IrqFlags,
+) -> Result<u8, IrqError> {
+    let action = Action { handler, data };
+    let shared = flags.contains(IrqFlags::SHARED);
+    let trigger = apic::firmware_trigger(gsi).unwrap_or(Trigger {
+        level: flags.contains(IrqFlags::LEVEL),
+        active_low: flags.contains(IrqFlags::ACTIVE_LOW),
+    });
+    let mut lines = LINES.lock();
+
+    // shared with someone
+    if let Some(index) = lines
+        .iter()
+        .position(|line| line.is_some_and(|line| line.gsi == Some(gsi)))
+    {
+        let line = lines[index].as_mut().unwrap();
+        if !shared || !line.shared || line.trigger != trigger {
+            return Err(IrqError::Busy);
+        }
+        let slot = line
+            .actions
+            .iter_mut()
+            .find(|action| action.is_none())
+            .ok_or(IrqError::Busy)?;
+        *slot = Some(action);
+        let vector = FIRST_VECTOR + index as u8;
+        info!("[irq] GSI {} shared, vector {:#x}", gsi, vector);
+        return Ok(vector);
+    }
+
+    let index = lines
+        .iter()
+        .position(|line| line.is_none())
+        .ok_or(IrqError::NoFreeVector)?;
+    let vector = FIRST_VECTOR + index as u8;
+    let mut actions = [None; MAX_SHARED];
+    actions[0] = Some(action);
+    // in place before the first interrupt can come
+    lines[index] = Some(IrqLine {
+        gsi: Some(gsi),
+        shared,
+        trigger,
+        actions,
+    });
+    if !apic::route_gsi(gsi, vector, trigger) {
+        lines[index] = None;
+        return Err(IrqError::InvalidGsi);
+    }
+    info!("[irq] GSI {} on vector {:#x}, {:?}", gsi, vector, trigger);
+    Ok(vector)
+}
+
+// removes the handler `request_irq` was called with
+// the line is masked once no one uses it
+pub(crate) fn free_irq(gsi: u32, handler: IrqHandler, data: usize) {
+    let mut lines = LINES.lock();
+    let Some(entry) = lines
+        .iter_mut()
+        .find(|line| line.is_some_and(|line| line.gsi == Some(gsi)))
+    else {
+        warn!("[irq] GSI {} freed, but it was not requested", gsi);
+        return;
+    };
+    let line = entry.as_mut().unwrap();
+
+    let Some(slot) = line.actions.iter_mut().find(|action| {
+        action.is_some_and(|action| {
+            action.handler as usize == handler as usize && action.data == data
+        })
+    }) else {
+        warn!("[irq] GSI {} freed by someone not using it", gsi);
+        return;
+    };
+    *slot = None;
+
+    if line.actions.iter().all(|action| action.is_none()) {
+        apic::mask_gsi(gsi);
+        *entry = None;
+        info!("[irq] GSI {} released", gsi);
+    }
+}
+
+// allocates a vector for each of `handlers`, for message signalled interrupts
+// the vectors are consecutive, the first one a multiple of `align`
+// (with multiple message MSI the device puts the message number in the low bits of the vector)
+// nothing is routed through the IOAPICs, the device is set up by the caller
+// returns the first vector
+pub(crate) fn request_msi(handlers: &[(IrqHandler, usize)], align: usize) -> Result<u8, IrqError> {
+    let count = handlers.len();
+    if count == 0 || count > VECTOR_COUNT {
+        return Err(IrqError::NoFreeVector);
+    }
+    let mut lines = LINES.lock();
+    let start = (0..=VECTOR_COUNT - count)
+        .filter(|&index| (FIRST_VECTOR as usize + index) % align == 0)
+        .find(|&index| {
+            lines[index..index + count]
+                .iter()
+                .all(|line| line.is_none())
+        })
+        .ok_or(IrqError::NoFreeVector)?;
+
+    for (line, &(handler, data)) in lines[start..start + count].iter_mut().zip(handlers) {
+        let mut actions = [None; MAX_SHARED];
+        actions[0] = Some(Action { handler, data });
+        *line = Some(IrqLine {
+            gsi: None,
+            shared: false,
+            trigger: Trigger::EDGE_HIGH,
+            actions,
+        });
+    }
+    let vector = FIRST_VECTOR + start as u8;
+    info!(
+        "[irq] MSI on vectors {:#x}..={:#x}",
+        vector,
+        vector as usize + count - 1
+    );
+    Ok(vector)
+}
+
+// releases the vectors `request_msi` returned
+// the device has to be stopped from sending the messages first
+pub(crate) fn free_msi(vector: u8, count: usize) {
+    let start = (vector - FIRST_VECTOR) as usize;
+    for line in LINES.lock()[start..start + count].iter_mut() {
+        *line = None;
+    }
+}
+
+// the common part of the stubs
+// `cs` is the code segment of the interrupted code
+extern "C" fn dispatch(vector: u64, cs: u64) {
+    let index = vector as usize - FIRST_VECTOR as usize;
+    // the handlers are not run with the lock held, so that they can (un)register others
+    let line = LINES.lock()[index];
+
+    let mut handled = false;
+    if let Some(line) = line {
+        for action in line.actions.iter().flatten() {
+            handled |= (action.handler)(action.data) == IrqReturn::Handled;
+        }
+    }
+    if !handled {
+        trace!("[irq] unhandled interrupt on vector {:#x}", vector);
+    }
+
+    // SAFETY: the LAPIC is initialised, the IOAPIC routes nothing before that
+    unsafe {
+        apic::send_eoi();
+    }
+
+    // a process that never makes a syscall is interrupted (^C) here
+    // quite likely by the very interrupt that brought us here, the keyboard's
+    if cs & 3 == 3 {
+        process::exit_if_interrupted();
+    }
+}
@@ -1,5 +1,3 @@
-use crate::arch::x86_64::{apic, port::Port};
-
 pub(super) type HandlerFn = extern "C" fn() -> !;
 
 #[derive(Debug)]
@@ -12,6 +10,13 @@ pub(super) struct InterruptStackFrame {
     ss: u64, // padded
 }
 
+impl InterruptStackFrame {
+    // the requested privilege level of the code segment
+    fn is_user_mode(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
 // #[naked]
 // pub(super) extern "C" fn breakpoint_handler() -> ! {
 //     unsafe {
@@ -62,6 +67,8 @@ pub fn is_int_enabled() -> bool {
 
 #[inline]
 pub fn enable_interrupts() {
+    // the host tests run in user mode, where it faults
+    #[cfg(not(test))]
     unsafe {
         core::arch::asm!("sti");
     }
@@ -69,6 +76,8 @@ pub fn enable_interrupts() {
 
 #[inline]
 pub fn disable_interrupts() {
+    // the host tests run in user mode, where it faults
+    #[cfg(not(test))]
     unsafe {
         core::arch::asm!("cli");
     }
@@ -112,6 +121,11 @@ pub(super) extern "C" fn invalid_opcode(isf: &InterruptStackFrame) {
     crate::println!("EXCEPTION: INVALID OPCODE @ {:#x}", isf.ip);
 }
 
+pub(super) extern "C" fn device_not_available(_isf: &InterruptStackFrame) {
+    // lazy FPU switching, load the registers of the current task
+    crate::arch::x86_64::process::device_not_available_handler();
+}
+
 pub(super) extern "C" fn page_fault(isf: &InterruptStackFrame, error_code: u64) -> ! {
     crate::println!("{:?}", isf);
     crate::println!(
@@ -122,7 +136,7 @@ pub(super) extern "C" fn page_fault(isf: &InterruptStackFrame, error_code: u64)
     crate::println!("{:#b}", error_code);
     loop {}
 }
-pub(super) extern "C" fn timer(_isf: &InterruptStackFrame) {
+pub(super) extern "C" fn timer(isf: &InterruptStackFrame) {
     without_interrupts(|| unsafe {
         crate::print!(".");
         // pic::send_eoi(0);
@@ -133,48 +147,9 @@ pub(super) extern "C" fn timer(_isf: &InterruptStackFrame) {
         // WARNING: need to change the place where EOI is called, if the above statement is no longer true
         crate::arch::x86_64::process::timer_interrupt_handler();
     });
-}
-pub(super) extern "C" fn hpet(_isf: &InterruptStackFrame) {
-    without_interrupts(|| unsafe {
-        crate::println!("!");
-        apic::send_eoi();
-    });
-}
-pub(super) extern "C" fn keyboard(_isf: &InterruptStackFrame) {
-    without_interrupts(|| unsafe {
-        crate::print!("{:x}", Port::new(0x60).read::<u8>());
-        // pic::send_eoi(1);
-        apic::send_eoi();
-    });
-}
 
-pub(super) fn rx_handler() {
-    let base_addr = 0xc000u16;
-    unsafe {
-        // 0030h-0033h R/W RBSTART Receive (Rx) Buffer Start Address
-        crate::println!("RBSTART: {:#x}", Port::new(base_addr + 0x30).read::<u32>());
-        // 0037h R/W CR Command Register
-        crate::println!("COMMAND: {:#b}", Port::new(base_addr + 0x37).read::<u8>());
-        // 0038h-0039h R/W CAPR Current Address of Packet Read
-        crate::println!("CAPR: {:#x}", Port::new(base_addr + 0x38).read::<u16>());
-        // 003Ah-003Bh R CBR Current Buffer Address:
-        crate::println!("CBA: {:#x}", Port::new(base_addr + 0x3a).read::<u16>());
-        // 003Ch-003Dh R/W IMR Interrupt Mask Register
-        crate::println!("IMR: {:#b}", Port::new(base_addr + 0x3c).read::<u16>());
-        // 003Eh-003Fh R/W ISR Interrupt Status Register
-        crate::println!("ISR: {:#b}", Port::new(base_addr + 0x3e).read::<u16>());
-        // 0044h-0047h R/W RCR Receive (Rx) Configuration Register
-        crate::println!("RCR: {:#x}", Port::new(base_addr + 0x44).read::<u32>());
-    }
-}
-
-pub(super) fn syscall() {
-    let task: u64;
-    unsafe {
-        core::arch::asm!(
-            "mov {task}, r11",
-            task = out(reg) task,
-        );
+    // the interrupted processes exit on their way back to the userspace
+    if isf.is_user_mode() {
+        crate::arch::x86_64::process::exit_if_interrupted();
     }
-    log::info!("SYSCALL handler: {:#x}", task);
 }
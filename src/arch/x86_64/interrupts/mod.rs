@@ -1,10 +1,14 @@
 mod idt;
+mod irq;
 mod isr;
 
 use idt::InterruptDescriptorTable;
 use lazy_static::lazy_static;
 use paste::paste;
 
+pub(crate) use irq::{
+    free_irq, free_msi, request_irq, request_msi, IrqError, IrqFlags, IrqHandler, IrqReturn,
+};
 use isr::*;
 pub use isr::{disable_interrupts, enable_interrupts, is_int_enabled};
 
@@ -15,6 +19,12 @@ macro_rules! handler {
             extern "C" fn [<stub_$int>]() -> ! {
                 unsafe {
                     core::arch::asm!(
+                        // from the userspace, switch to the kernel GS (per CPU data)
+                        "test byte ptr [rsp + 8], 3",
+                        "jz 2f",
+                        "swapgs",
+                        "2:",
+
                         "push rax",
                         "push rcx",
                         "push rdx",
@@ -39,6 +49,11 @@ macro_rules! handler {
                         "pop rcx",
                         "pop rax",
 
+                        // back to the userspace, switch back to its GS
+                        "test byte ptr [rsp + 8], 3",
+                        "jz 3f",
+                        "swapgs",
+                        "3:",
                         "iretq",
                         sym $int,
                         options(noreturn),
@@ -62,6 +77,12 @@ macro_rules! handler_with_error_code {
                     core::arch::asm!(
                         "pop rsi",
 
+                        // from the userspace, switch to the kernel GS (per CPU data)
+                        "test byte ptr [rsp + 8], 3",
+                        "jz 2f",
+                        "swapgs",
+                        "2:",
+
                         "push rax",
                         "push rcx",
                         "push rdx",
@@ -86,6 +107,11 @@ macro_rules! handler_with_error_code {
                         "pop rcx",
                         "pop rax",
 
+                        // back to the userspace, switch back to its GS
+                        "test byte ptr [rsp + 8], 3",
+                        "jz 3f",
+                        "swapgs",
+                        "3:",
                         "iretq",
                         sym $int,
                         options(noreturn),
@@ -106,15 +132,17 @@ lazy_static! {
         idt.add_handler(0x0, handler!(divide_by_zero), 0, 0);
         idt.add_handler(0x3, handler!(breakpoint), 0, 0);
         idt.add_handler(0x6, handler!(invalid_opcode), 0, 0);
+        idt.add_handler(0x7, handler!(device_not_available), 0, 0);
         idt.add_handler(0xe, handler_with_error_code!(page_fault), 0, 0);
 
         idt.add_handler(0x20, handler!(timer), 0, 0);
-        idt.add_handler(0x21, handler!(keyboard), 0, 0);
-        idt.add_handler(0x2b, handler!(rx_handler), 0, 0);
 
-        idt.add_handler(SYSCALL_HANDLER, handler!(syscall), 0, 3);
+        idt.add_handler(SYSCALL_HANDLER, super::syscall::syscall_int_handler, 0, 3);
 
-        idt.add_handler(0x2f, handler!(hpet), 0, 0);
+        // the devices, see `request_irq`
+        for vector in irq::FIRST_VECTOR..=irq::LAST_VECTOR {
+            idt.add_handler(vector as usize, irq::stub(vector), 0, 0);
+        }
         idt
     };
 }
@@ -1,6 +1,10 @@
 mod acpi;
+mod ansi;
 mod apic;
+mod display;
 mod elf;
+mod fpu;
+mod framebuffer;
 mod gdt;
 mod interrupts;
 mod paging;
@@ -8,39 +12,65 @@ mod pci;
 mod pic;
 mod port;
 mod process;
+mod ps2;
+mod rtl8139;
+mod serial;
 mod smp;
 mod syscall;
 mod timers;
+mod tty;
 mod userspace;
 mod vga_buffer;
 
 use crate::multiboot::MultibootInfo;
-use alloc::sync::Arc;
 use log::info;
 
 pub(crate) use interrupts::{disable_interrupts, enable_interrupts, is_int_enabled};
 pub(crate) use paging::{entry::EntryFlags, get_cur_page_table_start, P4Table, ACTIVE_PAGETABLE};
-pub(crate) use vga_buffer::_print;
+pub(crate) use process::{init_this_cpu, preempt_disable, preempt_enable, PreemptGuard, WaitQueue};
+pub(crate) use timers::monotonic_ns;
 
 pub(crate) fn init(multiboot_info: &MultibootInfo) {
     gdt::init();
     paging::init(multiboot_info);
+    framebuffer::init(multiboot_info);
     interrupts::init();
+    fpu::init();
     pic::init();
-    pci::init();
+    display::init();
 
     let rsdt = acpi::find_rsdt().unwrap();
     let madt_entries = rsdt.find_madt().unwrap();
     info!("found madt: {:x?}", madt_entries);
 
-    let hpet = timers::init(&rsdt);
+    timers::init(&rsdt);
 
-    apic::init(&madt_entries, &hpet);
-    smp::init_ap(&madt_entries);
+    apic::init(&madt_entries);
+    // the drivers request their IRQs, so they come once the IOAPICs are set up
+    timers::init_irq();
+    pci::init(&rsdt);
+    rtl8139::init();
+    ps2::init();
+    serial::init();
+    tty::init();
+    if crate::cmdline::params().smp {
+        smp::init_ap(&madt_entries);
+    }
 
     syscall::init();
     // userspace::run_userpace_code();
-    process::init(multiboot_info, Arc::new(hpet));
+    process::init(multiboot_info);
+}
+
+pub(crate) fn _print_serial(args: core::fmt::Arguments) {
+    serial::_print(args);
+}
+
+// the framebuffer console once it's set up, the VGA text buffer till then (or if there's no framebuffer)
+pub(crate) fn _print(args: core::fmt::Arguments) {
+    if !framebuffer::_print(args) {
+        vga_buffer::_print(args);
+    }
 }
 
 unsafe fn rdmsr(msr: u32) -> u64 {
@@ -50,17 +50,20 @@ use super::{EntryFlags, ACTIVE_PAGETABLE};
 
 pub(crate) fn map(start: PhysicalAddress, end: PhysicalAddress) {
     trace!("MMIO mapping...");
-    let mut guard = ACTIVE_PAGETABLE.lock();
+    // PWT + PCD selects PAT entry 3, uncacheable
+    map_with_flags(start, end, EntryFlags::WRITE_THROUGH | EntryFlags::NO_CACHE);
+}
 
-    // if there's already a mapping for the address, we are gonna assume that this range has been mapped
-    // A fair assumption as this function will be used to map the MMIO devices
-    // No two devices will be mapped to address ranges that share the same start physical address
-    if guard
-        .translate(unsafe { start.to_virt().unwrap() })
-        .is_some()
-    {
-        return;
-    }
+// for memory that is only written to in bulk, like framebuffers
+// the writes are buffered and combined, reading it back is slow
+pub(crate) fn map_write_combining(start: PhysicalAddress, end: PhysicalAddress) {
+    trace!("MMIO mapping (write combining)...");
+    // PWT alone selects PAT entry 1, set to WC in `paging::init` (if there's PAT, WT otherwise)
+    map_with_flags(start, end, EntryFlags::WRITE_THROUGH);
+}
+
+fn map_with_flags(start: PhysicalAddress, end: PhysicalAddress, caching: EntryFlags) {
+    let mut guard = ACTIVE_PAGETABLE.lock();
 
     let start = Frame::containing_address(start);
     let end = Frame::containing_address(end);
@@ -68,13 +71,16 @@ pub(crate) fn map(start: PhysicalAddress, end: PhysicalAddress) {
     for frame in Frame::range_inclusive(&start, &end) {
         let phys_addr = frame.start_address();
         let virt_addr = unsafe { phys_addr.to_virt().unwrap() };
+        // if there's already a mapping for the page, we are gonna assume that it's been mapped for this device
+        // A fair assumption as this function will be used to map the MMIO devices
+        // a device can grow its range (e.g. a framebuffer after a mode switch), the new pages still get mapped
+        if guard.translate(virt_addr).is_some() {
+            continue;
+        }
         guard.map_4KiB(
             virt_addr,
             phys_addr,
-            EntryFlags::PRESENT
-                | EntryFlags::WRITABLE
-                | EntryFlags::WRITE_THROUGH
-                | EntryFlags::NO_CACHE,
+            EntryFlags::PRESENT | EntryFlags::WRITABLE | caching,
         );
     }
 }
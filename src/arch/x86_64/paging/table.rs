@@ -264,6 +264,15 @@ impl P4Table {
         self.traverse(virt_addr).map(|(phys_addr, _)| phys_addr)
     }
 
+    // along with the flags of the leaf entry, to tell who can access the page and how
+    pub fn translate_with_flags(
+        &mut self,
+        virt_addr: VirtualAddress,
+    ) -> Option<(PhysicalAddress, EntryFlags)> {
+        self.traverse(virt_addr)
+            .map(|(phys_addr, entry)| (phys_addr, entry.flags()))
+    }
+
     pub fn unmap(&mut self, virt_addr: VirtualAddress) -> bool {
         if let Some((_, entry)) = self.traverse(virt_addr) {
             entry.unset_flags(EntryFlags::PRESENT);
@@ -0,0 +1,182 @@
+use alloc::vec::Vec;
+
+use super::{read, write, BaseAddrReg, BaseAddrRegType};
+
+// command register, the BARs are not decoded while they're sized
+const IO_SPACE: u32 = 1 << 0;
+const MEMORY_SPACE: u32 = 1 << 1;
+const COMMAND: u16 = 0x4;
+
+// a BAR that's in use, with how much it decodes
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Bar {
+    // 0-5, which of the registers (the first one of a 64 bit BAR)
+    pub(super) index: usize,
+    pub(super) addr: u64,
+    pub(super) size: u64,
+    pub(super) kind: BarKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BarKind {
+    Io,
+    Mem { prefetchable: bool, is_64: bool },
+}
+
+impl BaseAddrReg {
+    // the size of what the BAR decodes, 0 if it's not implemented (reads as 0 and stays so)
+    // all ones are written to it, the address bits the device doesn't decode stay 0
+    // the decoding is turned off meanwhile, so that the device doesn't answer at the bogus address
+    pub(super) fn size(&mut self) -> u64 {
+        let (bus, device, function) = (self.bus, self.device, self.function);
+        // the status register (the upper half) is left as is by writing 0s to it
+        let command = read(bus, device, function, COMMAND) & 0xffff;
+        write(
+            bus,
+            device,
+            function,
+            COMMAND,
+            command & !(IO_SPACE | MEMORY_SPACE),
+        );
+
+        let size = match self.read() {
+            BaseAddrRegType::Io(_) => probed_size(BarKind::Io, self.probe(self.offset), 0),
+            BaseAddrRegType::Mem {
+                prefetchable,
+                is_64,
+                ..
+            } => {
+                let low = self.probe(self.offset);
+                let high = if is_64 {
+                    self.probe(self.offset + 4)
+                } else {
+                    0
+                };
+                let kind = BarKind::Mem {
+                    prefetchable,
+                    is_64,
+                };
+                probed_size(kind, low, high)
+            }
+        };
+
+        write(bus, device, function, COMMAND, command);
+        size
+    }
+
+    // writes all ones to the register at `offset`, returns what's read back
+    // and puts the old value back
+    fn probe(&mut self, offset: u16) -> u32 {
+        let (bus, device, function) = (self.bus, self.device, self.function);
+        let orig = read(bus, device, function, offset);
+        write(bus, device, function, offset, u32::MAX);
+        let mask = read(bus, device, function, offset);
+        write(bus, device, function, offset, orig);
+        mask
+    }
+}
+
+// the size of what a BAR decodes, out of what it reads back as with all ones written to it
+// `high` is what the upper register of a 64 bit BAR reads back as
+fn probed_size(kind: BarKind, low: u32, high: u32) -> u64 {
+    match kind {
+        BarKind::Io => {
+            let mask = low & !0b11;
+            // the upper half of the IO BARs may read as 0s, there are only 64 KiB of ports
+            let mask = if mask & 0xffff_0000 == 0 {
+                mask | 0xffff_0000
+            } else {
+                mask
+            };
+            (!mask).wrapping_add(1) as u64
+        }
+        BarKind::Mem { is_64: false, .. } => (!(low & !0xf)).wrapping_add(1) as u64,
+        BarKind::Mem { is_64: true, .. } => {
+            let mask = (high as u64) << 32 | (low & !0xf) as u64;
+            (!mask).wrapping_add(1)
+        }
+    }
+}
+
+// the BARs in use out of `bars`, a 64 bit BAR takes the register after it too
+pub(super) fn decode(bars: &mut [BaseAddrReg]) -> Vec<Bar> {
+    let mut decoded = Vec::new();
+    let count = bars.len();
+    let mut index = 0;
+    while index < count {
+        let bar = &mut bars[index];
+        let (addr, kind) = match bar.read() {
+            BaseAddrRegType::Io(port) => (port as u64, BarKind::Io),
+            BaseAddrRegType::Mem {
+                addr,
+                prefetchable,
+                is_64,
+            } => {
+                // the upper half is in the next register, if there is one
+                let high = if is_64 && index + 1 < count {
+                    read(bar.bus, bar.device, bar.function, bar.offset + 4)
+                } else {
+                    0
+                };
+                let kind = BarKind::Mem {
+                    prefetchable,
+                    is_64,
+                };
+                ((high as u64) << 32 | addr as u64, kind)
+            }
+        };
+        // the upper half of a 64 bit BAR in the last register, there's no such thing
+        let is_64 = matches!(kind, BarKind::Mem { is_64: true, .. });
+        if is_64 && index + 1 == count {
+            break;
+        }
+        let size = bar.size();
+        if size != 0 {
+            decoded.push(Bar {
+                index,
+                addr,
+                size,
+                kind,
+            });
+        }
+        index += if is_64 { 2 } else { 1 };
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEM_32: BarKind = BarKind::Mem {
+        prefetchable: false,
+        is_64: false,
+    };
+    const MEM_64: BarKind = BarKind::Mem {
+        prefetchable: true,
+        is_64: true,
+    };
+
+    #[test]
+    fn io_size() {
+        assert_eq!(probed_size(BarKind::Io, 0xffff_ff01, 0), 0x100);
+        // the upper half reads as 0s
+        assert_eq!(probed_size(BarKind::Io, 0x0000_ffe1, 0), 0x20);
+    }
+
+    #[test]
+    fn mem_32_size() {
+        assert_eq!(probed_size(MEM_32, 0xffff_f000, 0), 0x1000);
+        assert_eq!(probed_size(MEM_32, 0xfff0_0008, 0), 0x10_0000);
+        // not implemented
+        assert_eq!(probed_size(MEM_32, 0, 0), 0);
+    }
+
+    #[test]
+    fn mem_64_size() {
+        assert_eq!(probed_size(MEM_64, 0xfff0_000c, 0xffff_ffff), 0x10_0000);
+        // larger than the lower half can tell
+        assert_eq!(probed_size(MEM_64, 0x0000_000c, 0xffff_fffe), 0x2_0000_0000);
+        assert_eq!(probed_size(MEM_64, 0, 0), 0);
+    }
+}
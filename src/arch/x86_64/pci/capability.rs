@@ -0,0 +1,125 @@
+use super::{config, PciDevice};
+
+// the capabilities are a linked list in the config space, starting at the pointer at 0x34
+// each one starts with its ID, followed by the offset of the next one (0 for the last one)
+// https://wiki.osdev.org/PCI#Capabilities_List
+// the extended capabilities of PCIe are another list, after the first 256 bytes (ECAM only)
+// each one starts with a dword: the ID in bits 0-15, the version in 16-19 and the next one in 20-31
+
+// capability IDs
+pub(super) const MSI: u16 = 0x05;
+pub(super) const MSIX: u16 = 0x11;
+
+// extended capability IDs
+// Advanced Error Reporting
+pub(super) const AER: u16 = 0x0001;
+// Single Root I/O Virtualization
+pub(super) const SRIOV: u16 = 0x0010;
+
+// the status register says whether there's a list at all
+const CAPABILITIES_LIST: u16 = 1 << 4;
+const CAPABILITIES_POINTER: u16 = 0x34;
+
+// the capabilities start after the header, a pointer into it means the list is broken
+const HEADER_END: u16 = 0x40;
+// the first extended capability, always there if there are any
+const EXTENDED_START: u16 = 0x100;
+// enough for every capability in the config space, stops a looping list
+const MAX_CAPABILITIES: usize = 48;
+const MAX_EXTENDED_CAPABILITIES: usize = 960;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Capability {
+    pub(super) id: u16,
+    // where it is in the config space
+    pub(super) offset: u16,
+}
+
+pub(super) struct Capabilities {
+    device: PciDevice,
+    next: u16,
+    left: usize,
+    extended: bool,
+}
+
+impl Iterator for Capabilities {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        let offset = self.next;
+
+        if self.extended {
+            if !(EXTENDED_START..config::EXTENDED_SIZE).contains(&offset) {
+                return None;
+            }
+            let header = self.device.read_at_offset(offset);
+            // nothing there, or an empty list
+            if header == u32::MAX || header == 0 {
+                return None;
+            }
+            self.next = (header >> 20) as u16 & !0b11;
+            Some(Capability {
+                id: header as u16,
+                offset,
+            })
+        } else {
+            if !(HEADER_END..EXTENDED_START).contains(&offset) {
+                return None;
+            }
+            let [id, next] = self.device.read_word(offset).to_le_bytes();
+            self.next = next as u16 & !0b11;
+            Some(Capability {
+                id: id as u16,
+                offset,
+            })
+        }
+    }
+}
+
+impl PciDevice {
+    pub(super) fn capabilities(&mut self) -> Capabilities {
+        let next = if self.status() & CAPABILITIES_LIST != 0 {
+            self.read_at_offset(CAPABILITIES_POINTER) as u16 & 0xfc
+        } else {
+            0
+        };
+        Capabilities {
+            device: *self,
+            next,
+            left: MAX_CAPABILITIES,
+            extended: false,
+        }
+    }
+
+    // empty without ECAM, the config space stops at 256 bytes then
+    pub(super) fn extended_capabilities(&mut self) -> Capabilities {
+        let next = if config::has_extended() {
+            EXTENDED_START
+        } else {
+            0
+        };
+        Capabilities {
+            device: *self,
+            next,
+            left: MAX_EXTENDED_CAPABILITIES,
+            extended: true,
+        }
+    }
+
+    // the offset of the capability `id`
+    pub(super) fn find_capability(&mut self, id: u16) -> Option<u16> {
+        self.capabilities()
+            .find(|cap| cap.id == id)
+            .map(|cap| cap.offset)
+    }
+
+    pub(super) fn find_extended_capability(&mut self, id: u16) -> Option<u16> {
+        self.extended_capabilities()
+            .find(|cap| cap.id == id)
+            .map(|cap| cap.offset)
+    }
+}
@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use spin::Once;
+
+use super::super::{acpi, paging::mmio, port::Port};
+use crate::{locks::SpinLockIrq, mem::PhysicalAddress};
+
+// the config space of the functions
+// through the memory mapped ECAM regions of PCIe (4 KiB a function) if the MCFG table lists them,
+// through the 0xcf8/0xcfc ports otherwise (only the first 256 bytes)
+// https://wiki.osdev.org/PCI_Express
+
+// the size of the config space of a function through the ports
+const LEGACY_SIZE: u16 = 0x100;
+// with ECAM, the extended capabilities come after the first 256 bytes
+pub(super) const EXTENDED_SIZE: u16 = 0x1000;
+
+// what's read where nothing answers
+const NOTHING: u32 = u32::MAX;
+
+struct LegacyPorts {
+    addr: Port,
+    data: Port,
+}
+
+lazy_static! {
+    // the address and the data port are used in pairs, so they're locked together
+    static ref LEGACY: SpinLockIrq<LegacyPorts> = SpinLockIrq::new(LegacyPorts {
+        addr: Port::new(0xcf8),
+        data: Port::new(0xcfc), // addr + 4
+    });
+}
+
+static ECAM: Once<Vec<acpi::McfgEntry>> = Once::new();
+
+// maps the ECAM regions of the PCI segment 0, the only one that's enumerated
+pub(super) fn init(rsdt: &acpi::RsdtEntries) {
+    let Some(entries) = rsdt.find_mcfg() else {
+        info!("[pci] no MCFG table, using the legacy config space access");
+        return;
+    };
+    let mut regions = Vec::new();
+    for entry in entries {
+        let (base_addr, segment) = (entry.base_addr, entry.segment);
+        if segment != 0 {
+            warn!("[pci] ignoring the ECAM region of segment {}", segment);
+            continue;
+        }
+        let base = PhysicalAddress::new(base_addr);
+        // each bus takes 1 MiB: 32 devices, 8 functions a device
+        let size = (entry.end_bus as u64 - entry.start_bus as u64 + 1) << 20;
+        mmio::map(base, base.offset(size - 1));
+        info!(
+            "[pci] ECAM for buses {:#x}..={:#x} at {:#x}",
+            entry.start_bus, entry.end_bus, base_addr
+        );
+        regions.push(entry);
+    }
+    ECAM.call_once(|| regions);
+}
+
+// whether the whole 4 KiB of the config space are there
+pub(super) fn has_extended() -> bool {
+    ECAM.r#try().is_some_and(|entries| !entries.is_empty())
+}
+
+// where the dword at `offset` of the function is mapped, if there's an ECAM region for its bus
+fn ecam_address(bus: u8, device: u8, function: u8, offset: u16) -> Option<*mut u32> {
+    let entry = ECAM
+        .r#try()?
+        .iter()
+        .find(|entry| entry.start_bus <= bus && bus <= entry.end_bus)?;
+    let addr = PhysicalAddress::new(entry.base_addr).offset(
+        ((bus - entry.start_bus) as u64) << 20
+            | ((device as u64 & 0x1f) << 15)
+            | ((function as u64 & 0x07) << 12)
+            | (offset as u64 & 0xffc), // align: 4 byte
+    );
+    // SAFETY: mapped in `init`
+    Some(unsafe { addr.to_virt().unwrap().as_mut_ptr() })
+}
+
+fn get_geo_addr(bus: u8, device: u8, function: u8, reg_offset: u16) -> u32 {
+    (0x1u32 << 31)
+        | ((bus as u32) << 16)
+        | ((device as u32 & 0x1f) << 11)
+        | ((function as u32 & 0x07) << 8)
+        | (reg_offset as u32 & 0xfc) // align: 4 byte
+}
+
+// the dword containing `reg_offset`, shifted so that the byte at `reg_offset` is the lowest one
+pub(super) fn read(bus: u8, device: u8, function: u8, reg_offset: u16) -> u32 {
+    let data = if let Some(addr) = ecam_address(bus, device, function, reg_offset) {
+        // SAFETY: the config space of the function, mapped as MMIO
+        unsafe { core::intrinsics::volatile_load(addr) }
+    } else if reg_offset < LEGACY_SIZE {
+        let geo_addr = get_geo_addr(bus, device, function, reg_offset);
+        let mut ports = LEGACY.lock();
+        unsafe {
+            ports.addr.write(geo_addr);
+            ports.data.read()
+        }
+    } else {
+        NOTHING
+    };
+    data >> (8 * (reg_offset % 4))
+}
+
+// writes the dword containing `reg_offset`
+pub(super) fn write(bus: u8, device: u8, function: u8, reg_offset: u16, data: u32) {
+    if let Some(addr) = ecam_address(bus, device, function, reg_offset) {
+        // SAFETY: the config space of the function, mapped as MMIO
+        unsafe { core::intrinsics::volatile_store(addr, data) }
+    } else if reg_offset < LEGACY_SIZE {
+        let geo_addr = get_geo_addr(bus, device, function, reg_offset);
+        let mut ports = LEGACY.lock();
+        unsafe {
+            ports.addr.write(geo_addr);
+            ports.data.write(data);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use alloc::vec::Vec;
+use log::{info, warn};
+
+use super::{msi::MsiError, tree, PciDevice};
+use crate::{arch::x86_64::interrupts::IrqError, locks::SpinLock};
+
+// the drivers are matched against the functions by their vendor and device IDs
+// a function is offered to the matching drivers in the order they registered in, till one takes it
+// the drivers register from their `init`, before or after the buses are scanned
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in super::super) struct PciId {
+    pub(in super::super) vendor: u16,
+    pub(in super::super) device: u16,
+}
+
+impl PciId {
+    pub(in super::super) const fn new(vendor: u16, device: u16) -> Self {
+        Self { vendor, device }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in super::super) enum ProbeError {
+    // a BAR the driver needs is not there, or not of the right kind
+    MissingBar,
+    Irq(IrqError),
+    Msi(MsiError),
+    // the device didn't do what it was told to
+    Device,
+}
+
+pub(in super::super) trait PciDriver: Sync {
+    fn name(&self) -> &'static str;
+    // the functions the driver can handle
+    fn ids(&self) -> &'static [PciId];
+    // sets up the function, it's the driver's from then on if it succeeds
+    // returns what tells the driver which of its functions `remove` is about
+    fn probe(&self, device: &mut PciDevice) -> Result<usize, ProbeError>;
+    // stops the function and releases what `probe` took, `data` is what it returned
+    fn remove(&self, device: &mut PciDevice, data: usize);
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    device: PciDevice,
+    // with what its `probe` returned
+    driver: Option<(&'static dyn PciDriver, usize)>,
+}
+
+static DRIVERS: SpinLock<Vec<&'static dyn PciDriver>> = SpinLock::new(Vec::new());
+// every function found, with the driver that took it
+static DEVICES: SpinLock<Vec<Binding>> = SpinLock::new(Vec::new());
+
+// the functions of the tree, offered to the drivers registered so far
+pub(super) fn init() {
+    *DEVICES.lock() = tree::functions()
+        .into_iter()
+        .map(|function| Binding {
+            device: function.device,
+            driver: None,
+        })
+        .collect();
+    bind_all();
+}
+
+pub(in super::super) fn register_driver(driver: &'static dyn PciDriver) {
+    DRIVERS.lock().push(driver);
+    bind_all();
+}
+
+// offers the functions without a driver to the drivers
+// the probes run without the locks held, the drivers are registered one at a time during boot
+fn bind_all() {
+    let drivers = DRIVERS.lock().clone();
+    let unbound = DEVICES
+        .lock()
+        .iter()
+        .filter(|binding| binding.driver.is_none())
+        .map(|binding| binding.device)
+        .collect::<Vec<_>>();
+
+    for mut device in unbound {
+        let id = PciId::new(device.vendor_id, device.device_id);
+        for driver in matching(&drivers, id) {
+            match driver.probe(&mut device) {
+                Ok(data) => {
+                    bind(&mut device, driver, data);
+                    break;
+                }
+                Err(err) => warn!(
+                    "[pci] {:02x}:{:02x}.{} probe by {} failed: {:?}",
+                    device.bus,
+                    device.device,
+                    device.function,
+                    driver.name(),
+                    err
+                ),
+            }
+        }
+    }
+}
+
+// the drivers that can handle the functions with `id`, in the order they registered in
+fn matching<'a>(
+    drivers: &'a [&'static dyn PciDriver],
+    id: PciId,
+) -> impl Iterator<Item = &'static dyn PciDriver> + 'a {
+    drivers
+        .iter()
+        .copied()
+        .filter(move |driver| driver.ids().contains(&id))
+}
+
+// records `driver` as the one of the function it probed
+// the function is looked up again, the list may have changed while the lock was not held,
+// if it's gone or another driver took it meanwhile, `driver` lets go of it
+fn bind(device: &mut PciDevice, driver: &'static dyn PciDriver, data: usize) {
+    let bound = {
+        let mut devices = DEVICES.lock();
+        let function = (device.bus, device.device, device.function);
+        let binding = devices.iter_mut().find(|binding| {
+            let found = binding.device;
+            (found.bus, found.device, found.function) == function
+        });
+        match binding {
+            Some(binding) if binding.driver.is_none() => {
+                binding.driver = Some((driver, data));
+                true
+            }
+            _ => false,
+        }
+    };
+    if bound {
+        info!(
+            "[pci] {:02x}:{:02x}.{} bound to {}",
+            device.bus,
+            device.device,
+            device.function,
+            driver.name()
+        );
+    } else {
+        warn!(
+            "[pci] {:02x}:{:02x}.{} taken meanwhile, released by {}",
+            device.bus,
+            device.device,
+            device.function,
+            driver.name()
+        );
+        driver.remove(device, data);
+    }
+}
+
+// the functions, with the name of the driver of each
+// nothing asks yet, there's no lspci
+#[allow(dead_code)]
+pub(in super::super) fn devices() -> Vec<(PciDevice, Option<&'static str>)> {
+    DEVICES
+        .lock()
+        .iter()
+        .map(|binding| {
+            let driver = binding.driver.map(|(driver, _)| driver.name());
+            (binding.device, driver)
+        })
+        .collect()
+}
+
+// the function is going away, its driver lets go of it first
+// nothing is hot plugged yet
+#[allow(dead_code)]
+pub(in super::super) fn remove_device(bus: u8, device: u8, function: u8) {
+    let binding = {
+        let mut devices = DEVICES.lock();
+        let Some(index) = devices.iter().position(|binding| {
+            let found = binding.device;
+            (found.bus, found.device, found.function) == (bus, device, function)
+        }) else {
+            return;
+        };
+        devices.remove(index)
+    };
+    if let Some((driver, data)) = binding.driver {
+        let mut found = binding.device;
+        driver.remove(&mut found, data);
+        info!(
+            "[pci] {:02x}:{:02x}.{} removed from {}",
+            bus,
+            device,
+            function,
+            driver.name()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    struct Fake(&'static str, &'static [PciId]);
+
+    impl PciDriver for Fake {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn ids(&self) -> &'static [PciId] {
+            self.1
+        }
+
+        fn probe(&self, _: &mut PciDevice) -> Result<usize, ProbeError> {
+            Ok(0)
+        }
+
+        fn remove(&self, _: &mut PciDevice, _: usize) {}
+    }
+
+    static NIC: Fake = Fake(
+        "nic",
+        &[PciId::new(0x10ec, 0x8139), PciId::new(0x10ec, 0x8169)],
+    );
+    static OTHER_NIC: Fake = Fake("other-nic", &[PciId::new(0x10ec, 0x8139)]);
+    static DISK: Fake = Fake("disk", &[PciId::new(0x8086, 0x2922)]);
+
+    fn names(drivers: &[&'static dyn PciDriver], id: PciId) -> Vec<&'static str> {
+        matching(drivers, id).map(|driver| driver.name()).collect()
+    }
+
+    #[test]
+    fn matching_ids() {
+        let drivers: [&'static dyn PciDriver; 3] = [&NIC, &DISK, &OTHER_NIC];
+        assert_eq!(names(&drivers, PciId::new(0x10ec, 0x8169)), vec!["nic"]);
+        assert_eq!(names(&drivers, PciId::new(0x8086, 0x2922)), vec!["disk"]);
+        // the vendor and the device both have to match
+        assert!(names(&drivers, PciId::new(0x8086, 0x8139)).is_empty());
+        assert!(names(&drivers, PciId::new(0x10ec, 0x2922)).is_empty());
+    }
+
+    #[test]
+    fn matching_order() {
+        let drivers: [&'static dyn PciDriver; 3] = [&OTHER_NIC, &DISK, &NIC];
+        let id = PciId::new(0x10ec, 0x8139);
+        assert_eq!(names(&drivers, id), vec!["other-nic", "nic"]);
+        assert!(names(&[], id).is_empty());
+    }
+}
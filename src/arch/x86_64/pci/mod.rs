@@ -1,15 +1,21 @@
-use alloc::{boxed::Box, collections::VecDeque};
+mod bar;
+mod capability;
+mod config;
+mod driver;
+mod msi;
+mod tree;
+
+use alloc::vec::Vec;
 use core::mem::transmute;
-use lazy_static::lazy_static;
 use paste::paste;
 
-use super::port::Port;
-use spin::Mutex;
+use super::acpi;
+use crate::mem::PhysicalAddress;
+use config::{read, write};
+use log::warn;
 
-lazy_static! {
-    static ref CONFIG_ADDR: Mutex<Port> = Mutex::new(Port::new(0xCF8));
-    static ref CONFIG_DATA: Mutex<Port> = Mutex::new(Port::new(0xCFC)); // CONFIG_ADDR + 4
-}
+pub(super) use driver::{register_driver, PciDriver, PciId, ProbeError};
+pub(super) use msi::{MsiError, MsiVectors};
 
 // Register	Offset	Bits 31-24	Bits 23-16	Bits 15-8	 Bits 7-0
 // 0x0	     0x0	Device ID	            Vendor ID
@@ -51,7 +57,7 @@ macro_rules! write_word {
 }
 
 impl PciDevice {
-    fn read_at_offset(&mut self, offset: u8) -> u32 {
+    fn read_at_offset(&mut self, offset: u16) -> u32 {
         read(self.bus, self.device, self.function, offset)
     }
     pub(super) fn status(&mut self) -> u16 {
@@ -85,10 +91,22 @@ impl PciDevice {
         self.read_at_offset(0xc).to_le_bytes()[0]
     }
 
-    fn write_at_offset(&mut self, offset: u8, value: u32) {
+    fn write_at_offset(&mut self, offset: u16, value: u32) {
         write(self.bus, self.device, self.function, offset, value);
     }
 
+    // `offset` is 2 byte aligned
+    fn read_word(&mut self, offset: u16) -> u16 {
+        self.read_at_offset(offset) as u16
+    }
+
+    fn write_word(&mut self, offset: u16, value: u16) {
+        let aligned = offset & !0b11;
+        let shift = 8 * (offset % 4);
+        let cur = self.read_at_offset(aligned) & !(0xffff << shift);
+        self.write_at_offset(aligned, cur | (value as u32) << shift);
+    }
+
     // write_word!(status, 0x4, 1);
     write_word!(command, 0x4, 0);
     write_byte!(class_code, 0x8, 3);
@@ -104,9 +122,10 @@ impl PciDevice {
 #[derive(Debug, Clone, Copy)]
 pub(super) enum HeaderType {
     Type0(Type0),
-    // unimplemented
+    // PCI-to-PCI bridge
+    Type1(Type1),
+    // CardBus bridge, unimplemented
     // not interesting for what I've in mind at the moment
-    Type1,
     Type2,
 }
 
@@ -136,9 +155,9 @@ impl Type0 {
     pub(super) fn erba(&mut self, bus: u8, device: u8, function: u8) -> u32 {
         read(bus, device, function, 0x30)
     }
+    // the offset of the first capability, see `PciDevice::capabilities`
     pub(super) fn capabilities(&mut self, bus: u8, device: u8, function: u8) -> u8 {
-        read(bus, device, function, 0x34).to_le_bytes()[0];
-        todo!()
+        read(bus, device, function, 0x34).to_le_bytes()[0] & !0b11
     }
     pub(super) fn max_latency(&mut self, bus: u8, device: u8, function: u8) -> u8 {
         read(bus, device, function, 0x3c).to_le_bytes()[3]
@@ -154,17 +173,48 @@ impl Type0 {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Type1 {}
+
+impl Type1 {
+    pub(super) fn bars(&mut self, bus: u8, device: u8, function: u8) -> [BaseAddrReg; 2] {
+        [
+            BaseAddrReg::new(bus, device, function, 0x10),
+            BaseAddrReg::new(bus, device, function, 0x14),
+        ]
+    }
+    pub(super) fn primary_bus(&mut self, bus: u8, device: u8, function: u8) -> u8 {
+        read(bus, device, function, 0x18).to_le_bytes()[0]
+    }
+    // the bus right behind the bridge
+    pub(super) fn secondary_bus(&mut self, bus: u8, device: u8, function: u8) -> u8 {
+        read(bus, device, function, 0x18).to_le_bytes()[1]
+    }
+    // the highest bus behind the bridge
+    pub(super) fn subordinate_bus(&mut self, bus: u8, device: u8, function: u8) -> u8 {
+        read(bus, device, function, 0x18).to_le_bytes()[2]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(super) enum BaseAddrRegType {
     Io(u32),
-    // not completely implemented yet
-    Mem,
+    // the lower half of the address if it's a 64 bit BAR, the upper half is in the next BAR
+    Mem {
+        addr: u32,
+        prefetchable: bool,
+        is_64: bool,
+    },
 }
 
 impl From<u32> for BaseAddrRegType {
     fn from(value: u32) -> Self {
         if value & 0x1 == 0 {
-            Self::Mem
+            Self::Mem {
+                addr: value & !0xf,
+                prefetchable: value & (1 << 3) != 0,
+                is_64: (value >> 1) & 0b11 == 0b10,
+            }
         } else {
             Self::Io(value & !0b11)
         }
@@ -175,7 +225,11 @@ impl Into<u32> for BaseAddrRegType {
     fn into(self) -> u32 {
         match self {
             Self::Io(port) => port | 0b1,
-            Self::Mem => unimplemented!(),
+            Self::Mem {
+                addr,
+                prefetchable,
+                is_64,
+            } => addr | (prefetchable as u32) << 3 | if is_64 { 0b10 << 1 } else { 0 },
         }
     }
 }
@@ -185,11 +239,11 @@ pub(super) struct BaseAddrReg {
     bus: u8,
     device: u8,
     function: u8,
-    offset: u8,
+    offset: u16,
 }
 
 impl BaseAddrReg {
-    fn new(bus: u8, device: u8, function: u8, offset: u8) -> Self {
+    fn new(bus: u8, device: u8, function: u8, offset: u16) -> Self {
         Self {
             bus,
             device,
@@ -202,6 +256,19 @@ impl BaseAddrReg {
         read(self.bus, self.device, self.function, self.offset).into()
     }
 
+    // the address of a memory BAR, with the upper half if it's a 64 bit one
+    pub(super) fn mem_addr(&mut self) -> Option<PhysicalAddress> {
+        let BaseAddrRegType::Mem { addr, is_64, .. } = self.read() else {
+            return None;
+        };
+        let high = if is_64 {
+            read(self.bus, self.device, self.function, self.offset + 4)
+        } else {
+            0
+        };
+        Some(PhysicalAddress::new((high as u64) << 32 | addr as u64))
+    }
+
     pub(super) fn write(&mut self, new: BaseAddrRegType) {
         write(
             self.bus,
@@ -212,56 +279,16 @@ impl BaseAddrReg {
         )
     }
 }
-pub(super) struct PciEnumerate {
-    // geographical addresses
-    cur_bus: u8,      // 256 buses   - 8 bits
-    cur_device: u8,   // 32 devices  - 5 bits
-    cur_function: u8, // 8 functions - 3 bits
-    next_buses: VecDeque<u8>,
-}
+// every function, the buses are walked from the host bridges down, see `tree`
+pub(super) struct PciEnumerate(alloc::vec::IntoIter<PciDevice>);
 
 impl PciEnumerate {
     pub(super) fn new() -> Self {
-        Self {
-            cur_bus: 0,
-            cur_device: 0,
-            cur_function: 0,
-            next_buses: VecDeque::new(),
-        }
-    }
-
-    fn incr(&mut self) -> bool {
-        self.incr_function() && self.incr_device() && self.incr_bus()
-    }
-
-    fn incr_function(&mut self) -> bool {
-        let mut max_functions = 8;
-        if self.cur_function == 0 && !has_multiple_functions(self.cur_bus, self.cur_device) {
-            max_functions = 1;
-        }
-
-        self.cur_function += 1;
-        if self.cur_function >= max_functions {
-            self.cur_function = 0;
-            true
-        } else {
-            false
-        }
-    }
-    fn incr_device(&mut self) -> bool {
-        self.cur_device += 1;
-        if self.cur_device >= 32 {
-            self.cur_device = 0;
-            true
-        } else {
-            false
-        }
-    }
-    fn incr_bus(&mut self) -> bool {
-        self.next_buses.pop_front().map_or(true, |b| {
-            self.cur_bus = b;
-            false
-        })
+        let devices = tree::functions()
+            .into_iter()
+            .map(|function| function.device)
+            .collect::<Vec<_>>();
+        Self(devices.into_iter())
     }
 }
 
@@ -269,57 +296,36 @@ impl Iterator for PciEnumerate {
     type Item = PciDevice;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.incr() {
-                return None;
-            }
-
-            let [vendor_id, device_id]: [u16; 2] =
-                unsafe { transmute(read(self.cur_bus, self.cur_device, self.cur_function, 0)) };
-            if device_id == 0x0000
-                || device_id == 0xffff
-                || vendor_id == 0x0000
-                || vendor_id == 0xffff
-            {
-                continue;
-            }
-            let header_type = read(self.cur_bus, self.cur_device, self.cur_function, 12)
-                .to_le_bytes()[2]
-                & !(1 << 7);
-            let header_type = match header_type {
-                0x0 => HeaderType::Type0(Type0 {}),
-                0x1 => HeaderType::Type1,
-                0x2 => HeaderType::Type2,
-                _ => unreachable!(),
-            };
-            return Some(PciDevice {
-                bus: self.cur_bus,
-                device: self.cur_device,
-                function: self.cur_function,
-                device_id,
-                vendor_id,
-                header_type,
-            });
-        }
+        self.0.next()
     }
 }
 
-fn get_geo_addr(bus: u8, device: u8, function: u8, reg_offset: u8) -> u32 {
-    let a = (0x1u32 << 31)
-        | ((bus as u32) << 16)
-        | ((device as u32 & 0x1f) << 11)
-        | ((function as u32 & 0x07) << 8)
-        | (reg_offset as u32 & 0xfc); // align: 4 byte
-    a
-}
-
-fn read(bus: u8, device: u8, function: u8, reg_offset: u8) -> u32 {
-    let geo_addr = get_geo_addr(bus, device, function, reg_offset);
-    let data: u32 = unsafe {
-        CONFIG_ADDR.lock().write(geo_addr);
-        CONFIG_DATA.lock().read()
+// the function at the address, if there's one
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let [vendor_id, device_id]: [u16; 2] = unsafe { transmute(read(bus, device, function, 0)) };
+    if device_id == 0x0000 || device_id == 0xffff || vendor_id == 0x0000 || vendor_id == 0xffff {
+        return None;
+    }
+    let header_type = match read(bus, device, function, 12).to_le_bytes()[2] & !(1 << 7) {
+        0x0 => HeaderType::Type0(Type0 {}),
+        0x1 => HeaderType::Type1(Type1 {}),
+        0x2 => HeaderType::Type2,
+        other => {
+            warn!(
+                "[pci] {:02x}:{:02x}.{} unknown header type {:#x}",
+                bus, device, function, other
+            );
+            return None;
+        }
     };
-    data >> (8 * (reg_offset % 4))
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        device_id,
+        vendor_id,
+        header_type,
+    })
 }
 
 fn has_multiple_functions(bus: u8, device: u8) -> bool {
@@ -327,113 +333,9 @@ fn has_multiple_functions(bus: u8, device: u8) -> bool {
     header_type & (1 << 7) != 0
 }
 
-fn write(bus: u8, device: u8, function: u8, reg_offset: u8, data: u32) {
-    let geo_addr = get_geo_addr(bus, device, function, reg_offset);
-    unsafe {
-        CONFIG_ADDR.lock().write(geo_addr);
-        CONFIG_DATA.lock().write(data);
-    }
-}
-
-pub(super) fn init() {
-    for mut p in PciEnumerate::new().filter(|p| p.device_id == 0x8139 && p.vendor_id == 0x10ec) {
-        //  data master
-        let cur = p.command();
-        p.write_command(cur | 0b100);
-        crate::println!("{:016b}", p.command());
-
-        if let HeaderType::Type0(mut t) = p.header_type {
-            t.bars(p.bus, p.device, p.function)
-                .into_iter()
-                .for_each(|mut b| {
-                    if let BaseAddrRegType::Io(orig) = b.read() {
-                        crate::println!("{:x?}", orig);
-                        let orig = orig as u16;
-                        unsafe {
-                            Port::new(orig + 0x52).write::<u8>(0x00);
-                            crate::println!("successfully turned it on");
-
-                            let mut cmd = Port::new(orig + 0x37);
-                            cmd.write::<u8>(0x10);
-                            crate::println!("wrote 0x10 to cmd reg");
-                            while cmd.read::<u8>() & 0x10 != 0 {}
-                            crate::println!("passed while test");
-
-                            let mut cmd = Port::new(orig + 0x37);
-                            cmd.write(0x0cu8);
-                            crate::println!("Rx and Tx enabled: {:#b}", cmd.read::<u8>());
-
-                            // let rx_buffer = Box::new([0u8; 8192+16+1500]);
-                            let rx_buffer = Box::new([0x0u8; 8192 + 16]);
-                            let rx_buffer = Box::into_raw(rx_buffer);
-                            crate::println!("rx_buffer: {:#x?}", rx_buffer);
-                            let mut rx_buffer_port = Port::new(orig + 0x30);
-                            rx_buffer_port.write(rx_buffer as u32);
-                            crate::println!(
-                                "rx buffer registered @ {:#x}",
-                                rx_buffer_port.read::<u32>()
-                            );
-
-                            let mut imr = Port::new(orig + 0x3c);
-                            imr.write(0x0005u16);
-                            crate::println!("IMR set to {:#b}", imr.read::<u16>());
-
-                            let mut rcr_port = Port::new(orig + 0x44);
-                            // rcr_port.write::<u32>(0xf | (1 << 7));
-                            rcr_port.write::<u32>(0xf);
-                            crate::println!("RCR configured to {:#x}", rcr_port.read::<u32>());
-
-                            // let mut capr = Port::new(orig + 0x38);
-                            // capr.write::<u16>(0);
-                            // crate::println!("CAPR configured to {:#x}", capr.read::<u32>());
-
-                            crate::println!("rtl8159 command: {:b}", cmd.read::<u8>());
-
-                            let base_addr = orig;
-                            // 0030h-0033h R/W RBSTART Receive (Rx) Buffer Start Address
-                            crate::println!(
-                                "RBSTART: {:#x}",
-                                Port::new(base_addr + 0x30).read::<u32>()
-                            );
-                            // 0037h R/W CR Command Register
-                            crate::println!(
-                                "COMMAND: {:#b}",
-                                Port::new(base_addr + 0x37).read::<u8>()
-                            );
-                            // 0038h-0039h R/W CAPR Current Address of Packet Read
-                            crate::println!(
-                                "CAPR: {:#x}",
-                                Port::new(base_addr + 0x38).read::<u16>()
-                            );
-                            // 003Ah-003Bh R CBR Current Buffer Address:
-                            crate::println!(
-                                "CBA: {:#x}",
-                                Port::new(base_addr + 0x3a).read::<u16>()
-                            );
-                            // 003Ch-003Dh R/W IMR Interrupt Mask Register
-                            crate::println!(
-                                "IMR: {:#b}",
-                                Port::new(base_addr + 0x3c).read::<u16>()
-                            );
-                            // 003Eh-003Fh R/W ISR Interrupt Status Register
-                            crate::println!(
-                                "ISR: {:#b}",
-                                Port::new(base_addr + 0x3e).read::<u16>()
-                            );
-                            // 0044h-0047h R/W RCR Receive (Rx) Configuration Register
-                            crate::println!(
-                                "RCR: {:#x}",
-                                Port::new(base_addr + 0x44).read::<u32>()
-                            );
-                        }
-
-                        // b.write(BaseAddrRegType::Io(u32::MAX));
-                        // crate::println!("{:x?}", b.read());
-                        // b.write(orig);
-                        // crate::println!("{:x?}", b.read());
-                    }
-                });
-        }
-        crate::println!("pci command: {:b}", p.command());
-    }
+// the drivers are matched against the functions as they register, see `driver`
+pub(super) fn init(rsdt: &acpi::RsdtEntries) {
+    config::init(rsdt);
+    tree::log();
+    driver::init();
 }
@@ -0,0 +1,319 @@
+use super::{capability, HeaderType, PciDevice};
+use crate::{
+    arch::x86_64::{
+        apic::lapic,
+        interrupts::{free_msi, request_msi, IrqError, IrqHandler},
+        paging::mmio,
+    },
+    mem::VirtualAddress,
+};
+use log::info;
+
+// message signalled interrupts: the device interrupts by writing `data` to `address`,
+// which is picked up by the LAPICs, there are no IOAPICs or shared lines involved
+// https://wiki.osdev.org/PCI#Message_Signaled_Interrupts
+
+// command register, INTx is off while the messages are used
+const INTERRUPT_DISABLE: u16 = 1 << 10;
+
+// the LAPICs listen on this range, the destination APIC ID is in bits 12-19
+// the data is the vector, fixed delivery and edge triggered
+const MSI_ADDRESS: u64 = 0xfee0_0000;
+
+// MSI capability, offsets from its start
+const MSI_CONTROL: u16 = 0x2;
+const MSI_ADDRESS_LOW: u16 = 0x4;
+const MSI_ADDRESS_HIGH: u16 = 0x8;
+// the data (and the mask bits) come 4 bytes later with 64 bit addresses
+const MSI_DATA_32: u16 = 0x8;
+const MSI_DATA_64: u16 = 0xc;
+const MSI_MASK_32: u16 = 0xc;
+const MSI_MASK_64: u16 = 0x10;
+
+// MSI message control
+const MSI_ENABLE: u16 = 1 << 0;
+// log2 of the messages the device can send
+const MSI_MULTIPLE_CAPABLE_SHIFT: u16 = 1;
+// log2 of the messages it's allowed to send
+const MSI_MULTIPLE_ENABLE_SHIFT: u16 = 4;
+const MSI_MULTIPLE_MASK: u16 = 0b111;
+const MSI_64BIT: u16 = 1 << 7;
+const MSI_PER_VECTOR_MASK: u16 = 1 << 8;
+
+// MSI-X capability, offsets from its start
+const MSIX_CONTROL: u16 = 0x2;
+// the BAR of the table in bits 0-2, the offset into it in the rest
+const MSIX_TABLE: u16 = 0x4;
+
+// MSI-X message control
+// the size of the table - 1
+const MSIX_TABLE_SIZE: u16 = 0x7ff;
+// masks all the vectors, whatever their entries say
+const MSIX_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_ENABLE: u16 = 1 << 15;
+
+// MSI-X table entries
+const ENTRY_SIZE: u64 = 16;
+const ENTRY_ADDRESS_LOW: u64 = 0x0;
+const ENTRY_ADDRESS_HIGH: u64 = 0x4;
+const ENTRY_DATA: u64 = 0x8;
+const ENTRY_CONTROL: u64 = 0xc;
+const ENTRY_MASKED: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in super::super) enum MsiError {
+    // neither MSI nor MSI-X
+    NotCapable,
+    // more vectors than the device has, or not a power of two with MSI
+    TooMany,
+    // the MSI-X table is not in a memory BAR
+    BadTable,
+    // not one of the messages that were set up
+    BadIndex,
+    Irq(IrqError),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Msi {
+        cap: u16,
+        // where the mask bits are, if the device can mask the vectors
+        mask: Option<u16>,
+    },
+    MsiX {
+        cap: u16,
+        table: VirtualAddress,
+    },
+}
+
+// the vectors a device interrupts with, see `PciDevice::request_msi`
+#[derive(Debug)]
+pub(in super::super) struct MsiVectors {
+    device: PciDevice,
+    kind: Kind,
+    first: u8,
+    count: usize,
+}
+
+impl PciDevice {
+    // sets up the device to interrupt with a vector for each of `handlers`
+    // with MSI-X if the device has it, with MSI otherwise (which takes a power of two vectors)
+    // the handlers are run with `data` as with `request_irq`
+    // the vectors start masked, they're to be unmasked once the device is ready to interrupt
+    // (MSI without per-vector masking can't be masked, the messages go through right away)
+    pub(in super::super) fn request_msi(
+        &mut self,
+        handlers: &[(IrqHandler, usize)],
+    ) -> Result<MsiVectors, MsiError> {
+        if let Some(cap) = self.find_capability(capability::MSIX) {
+            self.enable_msix(cap, handlers)
+        } else if let Some(cap) = self.find_capability(capability::MSI) {
+            self.enable_msi(cap, handlers)
+        } else {
+            Err(MsiError::NotCapable)
+        }
+    }
+
+    fn enable_msi(
+        &mut self,
+        cap: u16,
+        handlers: &[(IrqHandler, usize)],
+    ) -> Result<MsiVectors, MsiError> {
+        let control = self.read_word(cap + MSI_CONTROL);
+        let capable = 1 << ((control >> MSI_MULTIPLE_CAPABLE_SHIFT) & MSI_MULTIPLE_MASK);
+        let count = handlers.len();
+        if !count.is_power_of_two() || count > capable {
+            return Err(MsiError::TooMany);
+        }
+        // the device ORs the message number into the data, so the vectors are aligned
+        let first = request_msi(handlers, count).map_err(MsiError::Irq)?;
+
+        let is_64 = control & MSI_64BIT != 0;
+        let (data, mask) = if is_64 {
+            (MSI_DATA_64, MSI_MASK_64)
+        } else {
+            (MSI_DATA_32, MSI_MASK_32)
+        };
+        let address = msi_address();
+        self.write_at_offset(cap + MSI_ADDRESS_LOW, address as u32);
+        if is_64 {
+            self.write_at_offset(cap + MSI_ADDRESS_HIGH, (address >> 32) as u32);
+        }
+        self.write_word(cap + data, first as u16);
+        let mask = (control & MSI_PER_VECTOR_MASK != 0).then_some(cap + mask);
+        if let Some(mask) = mask {
+            // a bit for each message, there are at most 32 of them
+            self.write_at_offset(mask, u32::MAX >> (32 - count));
+        }
+
+        self.disable_intx();
+        let enabled = control & !(MSI_MULTIPLE_MASK << MSI_MULTIPLE_ENABLE_SHIFT)
+            | (count.trailing_zeros() as u16) << MSI_MULTIPLE_ENABLE_SHIFT
+            | MSI_ENABLE;
+        self.write_word(cap + MSI_CONTROL, enabled);
+
+        info!(
+            "[pci] {:02x}:{:02x}.{} MSI, {} vector(s) from {:#x}",
+            self.bus, self.device, self.function, count, first
+        );
+        Ok(MsiVectors {
+            device: *self,
+            kind: Kind::Msi { cap, mask },
+            first,
+            count,
+        })
+    }
+
+    fn enable_msix(
+        &mut self,
+        cap: u16,
+        handlers: &[(IrqHandler, usize)],
+    ) -> Result<MsiVectors, MsiError> {
+        let control = self.read_word(cap + MSIX_CONTROL);
+        let size = (control & MSIX_TABLE_SIZE) as usize + 1;
+        let count = handlers.len();
+        if count > size {
+            return Err(MsiError::TooMany);
+        }
+
+        let table = self.read_at_offset(cap + MSIX_TABLE);
+        let bir = (table & 0b111) as usize;
+        let HeaderType::Type0(mut header) = self.header_type else {
+            return Err(MsiError::BadTable);
+        };
+        let bar = header
+            .bars(self.bus, self.device, self.function)
+            .get_mut(bir)
+            .and_then(|bar| bar.mem_addr())
+            .ok_or(MsiError::BadTable)?;
+        let start = bar.offset((table & !0b111) as u64);
+        mmio::map(start, start.offset(size as u64 * ENTRY_SIZE - 1));
+        // SAFETY: mapped above
+        let table = unsafe { start.to_virt().unwrap() };
+
+        let first = request_msi(handlers, 1).map_err(MsiError::Irq)?;
+        let vectors = MsiVectors {
+            device: *self,
+            kind: Kind::MsiX { cap, table },
+            first,
+            count,
+        };
+
+        // the entries are written with everything masked, they are not read till then
+        self.disable_intx();
+        self.write_word(
+            cap + MSIX_CONTROL,
+            control | MSIX_ENABLE | MSIX_FUNCTION_MASK,
+        );
+        let address = msi_address();
+        // the ones past `count` stay masked for good, the others till they're unmasked
+        for index in 0..size {
+            // SAFETY: `index` is less than the size of the table
+            unsafe { write_entry(table, index, ENTRY_CONTROL, ENTRY_MASKED) };
+        }
+        for index in 0..count {
+            let vector = vectors.vector(index)?;
+            // SAFETY: `index` is less than `count`, which is at most the size of the table
+            unsafe {
+                write_entry(table, index, ENTRY_ADDRESS_LOW, address as u32);
+                write_entry(table, index, ENTRY_ADDRESS_HIGH, (address >> 32) as u32);
+                write_entry(table, index, ENTRY_DATA, vector as u32);
+            }
+        }
+        self.write_word(
+            cap + MSIX_CONTROL,
+            (control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK,
+        );
+
+        info!(
+            "[pci] {:02x}:{:02x}.{} MSI-X, {} of {} vector(s) from {:#x}",
+            self.bus, self.device, self.function, count, size, first
+        );
+        Ok(vectors)
+    }
+
+    fn disable_intx(&mut self) {
+        let command = self.command();
+        self.write_command(command | INTERRUPT_DISABLE);
+    }
+
+    fn enable_intx(&mut self) {
+        let command = self.command();
+        self.write_command(command & !INTERRUPT_DISABLE);
+    }
+}
+
+// the messages go to the LAPIC of the one setting them up, the BSP
+fn msi_address() -> u64 {
+    MSI_ADDRESS | (lapic::current_apic_id() as u64) << 12
+}
+
+impl MsiVectors {
+    // the vector the message `index` is delivered on
+    pub(in super::super) fn vector(&self, index: usize) -> Result<u8, MsiError> {
+        if index >= self.count {
+            return Err(MsiError::BadIndex);
+        }
+        Ok(self.first + index as u8)
+    }
+
+    // the device holds on to the messages of a masked vector, they are sent once it's unmasked
+    // MSI without per-vector masking can't be masked, nothing happens
+    pub(in super::super) fn mask(&mut self, index: usize) -> Result<(), MsiError> {
+        self.set_masked(index, true)
+    }
+
+    pub(in super::super) fn unmask(&mut self, index: usize) -> Result<(), MsiError> {
+        self.set_masked(index, false)
+    }
+
+    fn set_masked(&mut self, index: usize, masked: bool) -> Result<(), MsiError> {
+        if index >= self.count {
+            return Err(MsiError::BadIndex);
+        }
+        match self.kind {
+            Kind::Msi {
+                mask: Some(mask), ..
+            } => {
+                let bits = self.device.read_at_offset(mask);
+                let bits = if masked {
+                    bits | 1 << index
+                } else {
+                    bits & !(1 << index)
+                };
+                self.device.write_at_offset(mask, bits);
+            }
+            Kind::Msi { mask: None, .. } => {}
+            Kind::MsiX { table, .. } => {
+                let control = if masked { ENTRY_MASKED } else { 0 };
+                // SAFETY: `index` is less than `count`, which is at most the size of the table
+                unsafe { write_entry(table, index, ENTRY_CONTROL, control) };
+            }
+        }
+        Ok(())
+    }
+
+    // turns the messages off, the device goes back to INTx
+    pub(in super::super) fn free(mut self) {
+        match self.kind {
+            Kind::Msi { cap, .. } => {
+                let control = self.device.read_word(cap + MSI_CONTROL);
+                self.device
+                    .write_word(cap + MSI_CONTROL, control & !MSI_ENABLE);
+            }
+            Kind::MsiX { cap, .. } => {
+                let control = self.device.read_word(cap + MSIX_CONTROL);
+                self.device
+                    .write_word(cap + MSIX_CONTROL, control & !MSIX_ENABLE);
+            }
+        }
+        self.device.enable_intx();
+        free_msi(self.first, self.count);
+    }
+}
+
+// SAFETY: `table` is the mapped MSI-X table, and `index` is less than its size
+unsafe fn write_entry(table: VirtualAddress, index: usize, reg: u64, value: u32) {
+    let addr = table.offset(index as u64 * ENTRY_SIZE + reg).as_mut_ptr();
+    core::intrinsics::volatile_store(addr, value)
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+use log::{trace, warn};
+use spin::Once;
+
+use super::{
+    bar::{self, Bar},
+    capability, has_multiple_functions, probe, HeaderType, PciDevice,
+};
+
+// the PCI hierarchy: the buses of the host bridges, with the functions on each of them
+// the bridges lead to the buses below them, through the bus numbers the firmware assigned
+// https://wiki.osdev.org/PCI#Recursive_Scan
+
+#[derive(Debug)]
+pub(super) struct Bus {
+    pub(super) number: u8,
+    pub(super) functions: Vec<Function>,
+}
+
+#[derive(Debug)]
+pub(super) struct Function {
+    pub(super) device: PciDevice,
+    pub(super) class: u8,
+    pub(super) subclass: u8,
+    pub(super) prog_if: u8,
+    pub(super) bars: Vec<Bar>,
+    // the bus behind it, for the PCI-to-PCI bridges
+    pub(super) bridge: Option<Bridge>,
+}
+
+#[derive(Debug)]
+pub(super) struct Bridge {
+    pub(super) secondary: Bus,
+    // the highest bus number below the bridge
+    pub(super) subordinate: u8,
+}
+
+// the buses of the host bridges, scanned the first time someone asks for the devices
+static TREE: Once<Vec<Bus>> = Once::new();
+
+pub(super) fn tree() -> &'static [Bus] {
+    TREE.call_once(scan)
+}
+
+// every function, parents before the functions below them
+pub(super) fn functions() -> Vec<&'static Function> {
+    fn collect<'a>(bus: &'a Bus, all: &mut Vec<&'a Function>) {
+        for function in &bus.functions {
+            all.push(function);
+            if let Some(bridge) = &function.bridge {
+                collect(&bridge.secondary, all);
+            }
+        }
+    }
+
+    let mut all = Vec::new();
+    for bus in tree() {
+        collect(bus, &mut all);
+    }
+    all
+}
+
+fn scan() -> Vec<Bus> {
+    // buses are only scanned once, in case a bridge points to one that's been seen
+    let mut scanned = [false; 256];
+    // with a multifunction host bridge, function n is the host bridge of bus n
+    let hosts = if has_multiple_functions(0, 0) { 8 } else { 1 };
+    (0..hosts)
+        .filter(|&function| function == 0 || probe(0, 0, function).is_some())
+        .map(|function| scan_bus(function, &mut scanned))
+        .collect()
+}
+
+fn scan_bus(number: u8, scanned: &mut [bool; 256]) -> Bus {
+    scanned[number as usize] = true;
+    let mut functions = Vec::new();
+    for device in 0..32 {
+        if probe(number, device, 0).is_none() {
+            continue;
+        }
+        let count = if has_multiple_functions(number, device) {
+            8
+        } else {
+            1
+        };
+        for function in 0..count {
+            if let Some(found) = probe(number, device, function) {
+                functions.push(scan_function(found, scanned));
+            }
+        }
+    }
+    Bus { number, functions }
+}
+
+fn scan_function(mut device: PciDevice, scanned: &mut [bool; 256]) -> Function {
+    let (bus, dev, function) = (device.bus, device.device, device.function);
+    let (bars, bridge) = match device.header_type {
+        HeaderType::Type0(mut header) => (bar::decode(&mut header.bars(bus, dev, function)), None),
+        HeaderType::Type1(mut header) => {
+            let bars = bar::decode(&mut header.bars(bus, dev, function));
+            let primary = header.primary_bus(bus, dev, function);
+            let secondary = header.secondary_bus(bus, dev, function);
+            let subordinate = header.subordinate_bus(bus, dev, function);
+            // the bus numbers are left to the firmware, a bridge it didn't set up leads nowhere
+            let bridge = if primary != bus || secondary <= bus || scanned[secondary as usize] {
+                warn!(
+                    "[pci] {:02x}:{:02x}.{} bridge to bus {:#x} skipped",
+                    bus, dev, function, secondary
+                );
+                None
+            } else {
+                Some(Bridge {
+                    secondary: scan_bus(secondary, scanned),
+                    subordinate,
+                })
+            };
+            (bars, bridge)
+        }
+        HeaderType::Type2 => (Vec::new(), None),
+    };
+
+    Function {
+        class: device.class_code(),
+        subclass: device.subclass(),
+        prog_if: device.prog_if(),
+        device,
+        bars,
+        bridge,
+    }
+}
+
+// the hierarchy, a bus below the bridge leading to it
+pub(super) fn log() {
+    fn log_bus(bus: &Bus, depth: usize) {
+        let indent = depth * 2;
+        trace!("[pci] {:indent$}bus {:#x}", "", bus.number);
+        for function in &bus.functions {
+            let mut device = function.device;
+            let capabilities = device.capabilities().map(|cap| cap.id).collect::<Vec<_>>();
+            let aer = device.find_extended_capability(capability::AER).is_some();
+            let sriov = device.find_extended_capability(capability::SRIOV).is_some();
+            trace!(
+                "[pci] {:indent$}  {:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}.{:02x}.{:02x}, \
+                 capabilities: {:x?}, AER: {}, SR-IOV: {}",
+                "",
+                device.bus,
+                device.device,
+                device.function,
+                device.vendor_id,
+                device.device_id,
+                function.class,
+                function.subclass,
+                function.prog_if,
+                capabilities,
+                aer,
+                sriov,
+            );
+            for bar in &function.bars {
+                trace!(
+                    "[pci] {:indent$}    BAR{} {:?} at {:#x}, {:#x} bytes",
+                    "",
+                    bar.index,
+                    bar.kind,
+                    bar.addr,
+                    bar.size
+                );
+            }
+            if let Some(bridge) = &function.bridge {
+                trace!(
+                    "[pci] {:indent$}    buses {:#x}..={:#x}",
+                    "",
+                    bridge.secondary.number,
+                    bridge.subordinate
+                );
+                log_bus(&bridge.secondary, depth + 2);
+            }
+        }
+    }
+
+    for bus in tree() {
+        log_bus(bus, 0);
+    }
+}
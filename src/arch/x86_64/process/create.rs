@@ -1,8 +1,4 @@
-use super::{
-    pid::*,
-    process::{Process, State},
-    SCHEDULER_LOCK,
-};
+use super::{pid::*, process::Process, thread::Thread, IrqState, SCHEDULER_LOCK};
 use crate::{
     arch::{x86_64::gdt, EntryFlags, P4Table},
     mem::{frame::Frame, PhysicalAddress, VirtualAddress, PAGE_SIZE},
@@ -24,6 +20,9 @@ extern "C" fn user_task_init() {
             // SAFETY: the only way to get here is when `task_switch` transfers control to this new task
             // `SCHEDULER_LOCK` is locked before `task_switch` is run and is not unlocked before next statement
             "call scheduler_unlock",
+            // the userspace GS, with the interrupts off till `iretq` (they'd see the kernel running with it)
+            "cli",
+            "swapgs",
             // jump to userspace
             "iretq",
             options(noreturn)
@@ -42,7 +41,7 @@ extern "C" fn kernel_task_init() {
     // SAFETY: the only way to get here is when `task_switch` transfers control to this new task
     // `SCHEDULER_LOCK` is locked before `task_switch` is run and is not unlocked before next statement
     unsafe {
-        SCHEDULER_LOCK.unlock();
+        SCHEDULER_LOCK.unlock(IrqState::ENABLED);
     }
 }
 
@@ -163,7 +162,10 @@ fn map_task_page_table(
     }
 }
 
-pub(super) fn create_user_task2(us_task_code_virt_start: VirtualAddress, mut task_page_table: P4Table) -> Process {
+pub(super) fn create_user_task2(
+    us_task_code_virt_start: VirtualAddress,
+    mut task_page_table: P4Table,
+) -> (Process, Thread) {
     // allocate a kernel stack for the task and map it in the page table
     let (kernel_stack_bottom, kernel_stack_top) = create_stack(KERNEL_STACK_SIZE, None);
     // TODO: should I map kernel stack in the original kernel page table and then copy it into the new prog page table?
@@ -205,16 +207,19 @@ pub(super) fn create_user_task2(us_task_code_virt_start: VirtualAddress, mut tas
         us_stack_virt_base.offset(user_stack_size)
     };
 
-    Process {
-        stack_top: user_stack_top,
+    let process = Process::new(get_new_pid(), task_page_table.forget());
+    let main_thread = Thread::new(
+        get_new_tid(),
+        process.id,
+        process.cr3,
+        user_stack_top,
         kernel_stack_top,
-        cr3: task_page_table.forget(),
-        id: get_new_pid(),
-        state: State::Ready,
-    }
+        us_stack_virt_base.offset(USER_STACK_SIZE as u64),
+    );
+    (process, main_thread)
 }
 
-pub(super) fn create_user_task(task: *const ()) -> Process {
+pub(super) fn create_user_task(task: *const ()) -> (Process, Thread) {
     let (task_code_start, task_code_end) = load_task_code(task);
 
     // create a new page table for the new task
@@ -280,16 +285,19 @@ pub(super) fn create_user_task(task: *const ()) -> Process {
         us_stack_virt_base.offset(user_stack_size)
     };
 
-    Process {
-        stack_top: user_stack_top,
+    let process = Process::new(get_new_pid(), task_page_table.forget());
+    let main_thread = Thread::new(
+        get_new_tid(),
+        process.id,
+        process.cr3,
+        user_stack_top,
         kernel_stack_top,
-        cr3: task_page_table.forget(),
-        id: get_new_pid(),
-        state: State::Ready,
-    }
+        us_stack_virt_base.offset(USER_STACK_SIZE as u64),
+    );
+    (process, main_thread)
 }
 
-pub(super) fn create_kernel_task(task: *const ()) -> Process {
+pub(super) fn create_kernel_task(task: *const ()) -> (Process, Thread) {
     let (task_code_start, task_code_end) = (
         VirtualAddress::new(task as u64),
         VirtualAddress::new(task as u64 + PAGE_SIZE),
@@ -312,11 +320,60 @@ pub(super) fn create_kernel_task(task: *const ()) -> Process {
         );
     }
 
-    Process {
-        stack_top: user_stack_top,
+    let process = Process::new(get_new_pid(), PhysicalAddress::new(cr3));
+    let main_thread = Thread::new(
+        get_new_tid(),
+        process.id,
+        process.cr3,
+        user_stack_top,
         kernel_stack_top,
-        cr3: PhysicalAddress::new(cr3),
-        id: get_new_pid(),
-        state: State::Ready,
+        VirtualAddress::new(0),
+    );
+    (process, main_thread)
+}
+
+// creates a new thread in the address space of an existing process
+// `frame` is placed at the top of the new kernel stack, just above the state `task_switch` expects
+// so that `thread_init` can use it to jump to the userspace
+pub(super) fn create_user_thread<T: Copy>(
+    pid: Pid,
+    cr3: PhysicalAddress,
+    thread_init: extern "C" fn() -> !,
+    frame: T,
+    user_stack_top: VirtualAddress,
+) -> Thread {
+    let stack = vec![0u8; KERNEL_STACK_SIZE];
+    let stack_bottom = stack.as_ptr();
+    // 16 byte align
+    let kernel_stack_top =
+        (unsafe { stack_bottom.byte_add(KERNEL_STACK_SIZE) } as u64 & !0xf) as *const u8;
+
+    let mut stack_top = kernel_stack_top;
+    unsafe {
+        stack_top = ((stack_top.byte_sub(core::mem::size_of::<T>()) as u64) & !0xf) as *const u8;
+        core::ptr::write(stack_top as *mut T, frame);
+
+        stack_top = stack_top.byte_sub(8);
+        core::ptr::write(stack_top as *mut u64, thread_init as *const () as u64);
+
+        // matching the initial stack with what the `task_switch` expects to see
+        // callee saved registers - rbp, rbx, r12, r13, r14, r15
+        for _ in 0..6 {
+            stack_top = stack_top.byte_sub(8);
+            core::ptr::write(stack_top as *mut u64, 0);
+        }
     }
+    core::mem::forget(stack);
+
+    // no need to map the kernel stack in the page table of the process
+    // the higher half (kernel heap included) is shared with the kernel page table
+
+    Thread::new(
+        get_new_tid(),
+        pid,
+        cr3,
+        VirtualAddress::new(stack_top as u64),
+        VirtualAddress::new(kernel_stack_top as u64),
+        user_stack_top,
+    )
 }
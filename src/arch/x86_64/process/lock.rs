@@ -1,26 +1,34 @@
-use crate::arch::{disable_interrupts, enable_interrupts};
-use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::arch::{disable_interrupts, enable_interrupts, is_int_enabled};
 
-pub(super) struct Lock {
-    irq_disable_counter: AtomicUsize,
+pub(super) struct Lock;
+
+// whether the interrupts were enabled when the lock was taken, to be handed back to `unlock`
+// it's kept by the one who locked (on its stack), not in the lock
+// as the tasks are switched with the lock held, and each one unlocks what it locked
+#[must_use]
+pub(super) struct IrqState(bool);
+
+impl IrqState {
+    // new tasks start with the interrupts enabled
+    pub(super) const ENABLED: Self = Self(true);
 }
 
 impl Lock {
     pub(super) const fn new() -> Self {
-        Self {
-            irq_disable_counter: AtomicUsize::new(0),
-        }
+        Self
     }
 
-    pub(super) fn lock(&self) {
+    pub(super) fn lock(&self) -> IrqState {
+        let enabled = is_int_enabled();
         disable_interrupts();
-        self.irq_disable_counter.fetch_add(1, Ordering::Relaxed);
+        IrqState(enabled)
     }
 
     // make sure that `unlock` is called only after locking the lock
-    pub(super) unsafe fn unlock(&self) {
-        let old = self.irq_disable_counter.fetch_sub(1, Ordering::Relaxed);
-        if old == 1 {
+    // the interrupts are enabled again only if they were when it was locked,
+    // so that an interrupt handler doesn't get interrupted by unlocking
+    pub(super) unsafe fn unlock(&self, state: IrqState) {
+        if state.0 {
             enable_interrupts();
         }
     }
@@ -1,95 +1,115 @@
 mod create;
-mod delay;
 mod lock;
 mod pid;
+mod preempt;
 mod process;
 mod scheduler;
+mod thread;
+mod wait_queue;
 
 use self::{
     create::{create_kernel_task, create_user_task},
-    lock::Lock,
-    pid::{get_new_pid, Pid},
-    process::{Process, State},
+    lock::{IrqState, Lock},
+    pid::{get_new_pid, get_new_tid, Pid, Tid},
+    process::Process,
     scheduler::Scheduler,
+    thread::{State, Thread},
 };
-use super::{apic, timers::hpet::Hpet};
+use super::{apic, timers, wrmsr};
 use crate::{
-    arch::get_cur_page_table_start,
+    arch::{disable_interrupts, enable_interrupts, get_cur_page_table_start, P4Table},
+    cmdline::params,
+    fs::{self, FdTable, FsResult, OpenFlags},
+    locks::{SpinLock, SpinLockIrq},
     mem::{PhysicalAddress, VirtualAddress},
     multiboot::MultibootInfo,
 };
-use alloc::sync::Arc;
+use alloc::{collections::BTreeSet, sync::Arc, vec};
 use core::mem::MaybeUninit;
-use log::info;
+use log::{info, warn};
+
+pub(crate) use preempt::{init_this_cpu, preempt_disable, preempt_enable, PreemptGuard};
+pub(crate) use wait_queue::WaitQueue;
+
+// how long to wait before checking again if a task that can't be preempted right now is done
+// normally, `preempt_enable` reschedules as soon as it's possible, this is only a fallback
+const PREEMPT_RETRY_NS: u64 = 10u64.pow(6);
 
 static SCHEDULER_LOCK: Lock = Lock::new();
 static mut SCHEDULER: MaybeUninit<Scheduler> = MaybeUninit::uninit();
 
+// the processes interrupted from their terminal (^C), they exit on their way back to the userspace
+// filled from the interrupt handlers, which can't take the locks of the processes
+static INTERRUPTED: SpinLockIrq<BTreeSet<Pid>> = SpinLockIrq::new(BTreeSet::new());
+
 // to be used from within the task init functions
 #[no_mangle]
 unsafe fn scheduler_unlock() {
-    SCHEDULER_LOCK.unlock();
+    SCHEDULER_LOCK.unlock(IrqState::ENABLED);
 }
 
 #[no_mangle]
 fn schedule() {
     let scheduler = unsafe { SCHEDULER.assume_init_mut() };
 
-    SCHEDULER_LOCK.lock();
+    let irq = SCHEDULER_LOCK.lock();
     // SAFETY: locking disables interrupts
     unsafe {
         scheduler.schedule();
     }
     // SAFETY: SCHEDULER_LOCK is locked just above
     unsafe {
-        SCHEDULER_LOCK.unlock();
+        SCHEDULER_LOCK.unlock(irq);
     }
 }
 
+// blocks the current thread for (at least) `delay_ns`
 #[no_mangle]
-fn delay(delay_ns: u64) {
+pub(super) fn delay(delay_ns: u64) {
     let scheduler = unsafe { SCHEDULER.assume_init_mut() };
 
-    SCHEDULER_LOCK.lock();
+    let irq = SCHEDULER_LOCK.lock();
 
+    let tid = scheduler.cur_thread;
     scheduler.block_current();
-    scheduler.delays.add(scheduler.cur_proc, delay_ns);
+    let timer = timers::add_hrtimer(delay_ns, move || unblock(tid.0 as u64));
 
     // SAFETY: locking disables interrupts
     unsafe {
         scheduler.schedule();
     }
+    // in case something other than the timer woke us up
+    timer.cancel();
+
     // SAFETY: SCHEDULER_LOCK is locked just above
     unsafe {
-        SCHEDULER_LOCK.unlock();
+        SCHEDULER_LOCK.unlock(irq);
     }
 }
 
+// only marks the thread as ready to run, it runs once the scheduler picks it
+// used from the timer callbacks, which run in the timer interrupt handler
+// and that one reschedules itself on its way out (if it can)
 #[no_mangle]
-fn unblock(pid: u64) {
-    let pid = Pid(pid as u32);
+fn unblock(tid: u64) {
+    let tid = Tid(tid as u32);
 
     let scheduler = unsafe { SCHEDULER.assume_init_mut() };
 
-    SCHEDULER_LOCK.lock();
+    let irq = SCHEDULER_LOCK.lock();
 
-    // info!("scheduler: {:#x?}", scheduler);
-    scheduler.unblock(pid);
+    scheduler.unblock(tid);
 
-    // SAFETY: locking disables interrupts
-    unsafe {
-        scheduler.schedule();
-    }
     // SAFETY: SCHEDULER_LOCK is locked just above
     unsafe {
-        SCHEDULER_LOCK.unlock();
+        SCHEDULER_LOCK.unlock(irq);
     }
 }
 
 pub(super) fn timer_interrupt_handler() {
     let scheduler = unsafe { SCHEDULER.assume_init_mut() };
 
-    SCHEDULER_LOCK.lock();
+    let irq = SCHEDULER_LOCK.lock();
 
     // SAFETY: ensures that the EOI is sent to LAPIC
     // the goal is to send EOI once the interrupts are disabled
@@ -99,31 +119,207 @@ pub(super) fn timer_interrupt_handler() {
         apic::send_eoi();
     }
 
-    for pid in scheduler.delays.get_expired_timers() {
-        scheduler.unblock(pid);
+    timers::run_expired();
+
+    if preempt::preemptible() {
+        // SAFETY: locking disables interrupts
+        unsafe {
+            scheduler.schedule();
+        }
+    } else {
+        // the interrupted kernel code holds a spinlock or asked not to be preempted
+        // switch once it's done (`preempt_enable`)
+        preempt::set_need_resched();
+        timers::add_timer(PREEMPT_RETRY_NS, || {});
     }
 
+    // tickless, the next interrupt comes when there's something to do
+    timers::program_next_event();
+
+    // SAFETY: SCHEDULER_LOCK is locked just above
+    unsafe {
+        SCHEDULER_LOCK.unlock(irq);
+    }
+}
+
+// #NM handler
+pub(super) fn device_not_available_handler() {
+    let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+    let irq = SCHEDULER_LOCK.lock();
+
     // SAFETY: locking disables interrupts
     unsafe {
-        scheduler.schedule();
+        scheduler.take_fpu();
     }
 
     // SAFETY: SCHEDULER_LOCK is locked just above
     unsafe {
-        SCHEDULER_LOCK.unlock();
+        SCHEDULER_LOCK.unlock(irq);
     }
 }
 
-pub(super) fn init(multiboot_info: &MultibootInfo, hpet: Arc<Hpet>) {
-    let mut init = Process {
-        id: get_new_pid(),
-        // SAFETY: paging enabled by the time we get here
-        cr3: unsafe { get_cur_page_table_start() },
-        stack_top: VirtualAddress::new(0),
-        kernel_stack_top: VirtualAddress::new(0), // doesn't use this
-        state: State::Running,
+const IA32_FS_BASE_MSR: u32 = 0xC0000100;
+
+pub(super) fn current_tid() -> u32 {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    scheduler.cur_thread.0
+}
+
+pub(super) fn current_pid() -> u32 {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    scheduler.current_thread().lock().pid.0
+}
+
+// creates a new thread in the current process
+// `frame` is placed on the kernel stack of the new thread and `thread_init` is run first when it's scheduled
+// `thread_init` is expected to call `scheduler_unlock` (see `task_switch`)
+// returns the thread id of the new thread
+pub(super) fn spawn_user_thread<T: Copy>(
+    thread_init: extern "C" fn() -> !,
+    frame: T,
+    user_stack_top: VirtualAddress,
+    fs_base: Option<u64>,
+    clear_child_tid: Option<VirtualAddress>,
+) -> u32 {
+    let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+    let irq = SCHEDULER_LOCK.lock();
+
+    let (pid, cr3, parent_fs_base) = {
+        let cur = scheduler.current_thread().lock();
+        (cur.pid, cur.cr3, cur.fs_base)
     };
-    init.stack_top = unsafe {
+    let mut thread = create::create_user_thread(pid, cr3, thread_init, frame, user_stack_top);
+    thread.fs_base = fs_base.unwrap_or(parent_fs_base);
+    thread.clear_child_tid = clear_child_tid;
+    let tid = thread.id;
+    info!("[thread] new thread {:?} in process {:?}", tid, pid);
+    scheduler.add_thread(thread);
+
+    // SAFETY: SCHEDULER_LOCK is locked just above
+    unsafe {
+        SCHEDULER_LOCK.unlock(irq);
+    }
+
+    tid.0
+}
+
+// file descriptors of the current process
+pub(super) fn current_files() -> Arc<SpinLock<FdTable>> {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    let pid = scheduler.current_thread().lock().pid;
+    scheduler.processes.get(&pid).unwrap().lock().files.clone()
+}
+
+pub(super) fn set_fs_base(base: u64) {
+    let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+    let irq = SCHEDULER_LOCK.lock();
+    scheduler.current_thread().lock().fs_base = base;
+    // SAFETY: IA32_FS_BASE is present on all x86_64 processors
+    // and `task_switch` loads it on every switch, so the value stays with the thread
+    unsafe {
+        wrmsr(IA32_FS_BASE_MSR, base);
+    }
+    // SAFETY: SCHEDULER_LOCK is locked just above
+    unsafe {
+        SCHEDULER_LOCK.unlock(irq);
+    }
+}
+
+pub(super) fn fs_base() -> u64 {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    scheduler.current_thread().lock().fs_base
+}
+
+pub(super) fn set_clear_child_tid(addr: Option<VirtualAddress>) {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    scheduler.current_thread().lock().clear_child_tid = addr;
+}
+
+pub(super) fn take_clear_child_tid() -> Option<VirtualAddress> {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+    scheduler.current_thread().lock().clear_child_tid.take()
+}
+
+pub(super) fn process_exists(pid: u32) -> bool {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+
+    let irq = SCHEDULER_LOCK.lock();
+    let exists = scheduler.processes.contains_key(&Pid(pid));
+    // SAFETY: SCHEDULER_LOCK is locked just above
+    unsafe {
+        SCHEDULER_LOCK.unlock(irq);
+    }
+    exists
+}
+
+// marks the process as interrupted, returns false if there's no such process
+// its sleeping threads are woken up, so that they can see it
+// safe to call from within an interrupt handler
+pub(super) fn interrupt_process(pid: u32) -> bool {
+    let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+    // the process can't exit in the meantime, `exit_current` runs with the lock held
+    let irq = SCHEDULER_LOCK.lock();
+    let exists = scheduler.processes.contains_key(&Pid(pid));
+    if exists {
+        INTERRUPTED.lock().insert(Pid(pid));
+        scheduler.unblock_process(Pid(pid));
+    }
+    // SAFETY: SCHEDULER_LOCK is locked just above
+    unsafe {
+        SCHEDULER_LOCK.unlock(irq);
+    }
+    exists
+}
+
+// whether the current process has been interrupted
+pub(super) fn interrupt_pending() -> bool {
+    INTERRUPTED.lock().contains(&Pid(current_pid()))
+}
+
+// ^C from the terminal, there are no signal handlers so the process is terminated
+// each thread goes on its way back to the userspace (from a syscall or an interrupt)
+pub(super) fn exit_if_interrupted() {
+    if interrupt_pending() {
+        exit_thread();
+    }
+}
+
+// terminates the current thread
+// the process is gone once its last thread exits
+pub(super) fn exit_thread() -> ! {
+    let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+    let _irq = SCHEDULER_LOCK.lock();
+
+    scheduler.exit_current();
+
+    // SAFETY: locking disables interrupts
+    unsafe {
+        scheduler.schedule();
+    }
+
+    // the init thread never exits and is always ready to run
+    // so there's always someone to switch to
+    unreachable!("dead thread scheduled again");
+}
+
+pub(super) fn init(multiboot_info: &MultibootInfo) {
+    // SAFETY: paging enabled by the time we get here
+    let init = Process::new(get_new_pid(), unsafe { get_cur_page_table_start() });
+    let mut init_thread = Thread::new(
+        get_new_tid(),
+        init.id,
+        init.cr3,
+        VirtualAddress::new(0),
+        VirtualAddress::new(0), // doesn't use this
+        VirtualAddress::new(0),
+    );
+    init_thread.state = State::Running;
+    init_thread.stack_top = unsafe {
         let stack_top: u64;
         core::arch::asm!(
             "mov {tmp}, rsp",
@@ -133,11 +329,12 @@ pub(super) fn init(multiboot_info: &MultibootInfo, hpet: Arc<Hpet>) {
         VirtualAddress::new(stack_top)
     };
 
-    let mut scheduler = Scheduler::new(init, hpet);
+    let mut scheduler = Scheduler::new(init, init_thread);
 
-    let p0 = create_kernel_task(func0 as _);
-    info!("p0: {:#x?}", p0);
-    scheduler.add(p0);
+    let (p0, t0) = create_kernel_task(func0 as _);
+    info!("p0: {:#x?}, t0: {:#x?}", p0, t0);
+    scheduler.add_process(p0);
+    scheduler.add_thread(t0);
     // let p1 = create_user_task(func1 as _);
     // info!("p1: {:#x?}", p1);
     // scheduler.add(p1);
@@ -145,14 +342,37 @@ pub(super) fn init(multiboot_info: &MultibootInfo, hpet: Arc<Hpet>) {
     // info!("p2: {:#x?}", p2);
     // scheduler.add(p2);
 
-    for module in multiboot_info.multiboot_modules() {
-        info!("[scheduler init] module: {:#x?}", module);
-        let (entry, page_table) = super::elf::load_elf(
-            PhysicalAddress::new(module.mod_start as u64),
-            (module.mod_end - module.mod_start) as usize
-        );
-        let proc = create::create_user_task2(entry, page_table);
-        scheduler.add(proc);
+    if multiboot_info
+        .multiboot_modules()
+        .any(|module| module.is_initrd())
+    {
+        // the initrd is in the root filesystem by now, the rest of the userland is started by init
+        let init = params().init;
+        match load_init(init) {
+            Ok((entry, page_table)) => {
+                info!("[scheduler init] starting {}", init);
+                let (proc, main_thread) = create::create_user_task2(entry, page_table);
+                scheduler.add_process(proc);
+                scheduler.add_thread(main_thread);
+            }
+            Err(err) => warn!("[scheduler init] can't start {}: {:?}", init, err),
+        }
+    } else {
+        // no initrd, every module is a program
+        for module in multiboot_info.multiboot_modules() {
+            info!(
+                "[scheduler init] module {:?}: {:#x?}",
+                module.name(),
+                module
+            );
+            let (entry, page_table) = super::elf::load_elf(
+                PhysicalAddress::new(module.mod_start as u64),
+                (module.mod_end - module.mod_start) as usize,
+            );
+            let (proc, main_thread) = create::create_user_task2(entry, page_table);
+            scheduler.add_process(proc);
+            scheduler.add_thread(main_thread);
+        }
     }
 
     info!("scheduler: {:#x?}", scheduler);
@@ -160,8 +380,47 @@ pub(super) fn init(multiboot_info: &MultibootInfo, hpet: Arc<Hpet>) {
     unsafe {
         SCHEDULER.write(scheduler);
     }
+
+    // SAFETY: lapic initialised by the time we get here
+    // and the scheduler is ready to handle the timer interrupts
+    unsafe {
+        timers::enable_events();
+    }
+
+    // the init thread doubles as the idle thread
     loop {
         schedule();
+        idle();
+    }
+}
+
+fn load_init(path: &str) -> FsResult<(VirtualAddress, P4Table)> {
+    let file = fs::open(path, OpenFlags::READ, 0)?;
+    let mut image = vec![0u8; file.stat()?.size as usize];
+    let mut read = 0;
+    while read < image.len() {
+        match file.read(&mut image[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(super::elf::load_elf_image(&image[..read]))
+}
+
+// sleeps till the next interrupt if there's nothing to run
+// tickless, so this can be a long time
+fn idle() {
+    let scheduler = unsafe { SCHEDULER.assume_init_ref() };
+
+    disable_interrupts();
+    if scheduler.ready_to_run == 0 {
+        // `sti` takes effect only after the next instruction
+        // so a wake up can't slip in between the check and `hlt`
+        unsafe {
+            core::arch::asm!("sti", "hlt");
+        }
+    } else {
+        enable_interrupts();
     }
 }
 
@@ -208,6 +467,11 @@ unsafe extern "sysv64" fn task_switch() {
         "mov dword ptr [rdx + 0x04], eax", // lower 32 bits
         "shr rax, 32",
         "mov dword ptr [rdx + 0x08], eax", // higher 32 bits
+        // thread local storage (IA32_FS_BASE MSR)
+        "mov ecx, 0xC0000100",
+        "mov eax, dword ptr [rsi + 0x18]", // lower 32 bits
+        "mov edx, dword ptr [rsi + 0x1c]", // higher 32 bits
+        "wrmsr",
         "pop r15",
         "pop r14",
         "pop r13",
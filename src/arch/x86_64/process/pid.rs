@@ -1,11 +1,20 @@
 use core::sync::atomic::{AtomicU32, Ordering};
 
 static PID_COUNTER: AtomicU32 = AtomicU32::new(0);
+static TID_COUNTER: AtomicU32 = AtomicU32::new(0);
 
 pub(super) fn get_new_pid() -> Pid {
     let pid = PID_COUNTER.fetch_add(1, Ordering::Relaxed);
     Pid(pid)
 }
 
+pub(super) fn get_new_tid() -> Tid {
+    let tid = TID_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Tid(tid)
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub(super) struct Pid(pub(super) u32);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub(super) struct Tid(pub(super) u32);
@@ -0,0 +1,132 @@
+use super::super::wrmsr;
+use crate::arch::is_int_enabled;
+use core::{
+    arch::x86_64::{__cpuid, CpuidResult},
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+// one slot for every possible xAPIC id
+const MAX_CPUS: usize = 256;
+
+const IA32_GS_BASE_MSR: u32 = 0xC0000101;
+// swapped with IA32_GS_BASE by `swapgs` on the way in from and out to the userspace
+const IA32_KERNEL_GS_BASE_MSR: u32 = 0xC0000102;
+
+// GS points at the slot of the CPU while in the kernel
+#[repr(C)]
+struct PerCpu {
+    // the slot itself, as the GS base can't be read without the MSR
+    // has to be the first field, see `this_cpu`
+    this: AtomicPtr<PerCpu>,
+    // number of reasons the task running on this CPU can't be preempted right now
+    // (spinlocks held, explicit `preempt_disable` calls)
+    preempt_count: AtomicUsize,
+    // the timer went off while preemption was disabled
+    // checked when the count drops back to 0
+    need_resched: AtomicBool,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            this: AtomicPtr::new(null_mut()),
+            preempt_count: AtomicUsize::new(0),
+            need_resched: AtomicBool::new(false),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const PER_CPU_INIT: PerCpu = PerCpu::new();
+static PER_CPU: [PerCpu; MAX_CPUS] = [PER_CPU_INIT; MAX_CPUS];
+
+// points GS at the slot of this CPU
+// has to run before anything disables preemption (takes a lock)
+// SAFETY: to be run once on every CPU, first thing on its way into the kernel
+pub(crate) unsafe fn init_this_cpu() {
+    // the initial APIC id, the Local APIC isn't mapped this early
+    let CpuidResult { ebx, .. } = __cpuid(1);
+    let cpu = &PER_CPU[(ebx >> 24) as usize];
+    let addr = cpu as *const PerCpu as *mut PerCpu;
+    cpu.this.store(addr, Ordering::Relaxed);
+    wrmsr(IA32_GS_BASE_MSR, addr as u64);
+    // the userspace starts with a GS base of 0
+    wrmsr(IA32_KERNEL_GS_BASE_MSR, 0);
+}
+
+// tasks don't migrate between CPUs, so the slot can't change under our feet
+#[cfg(not(test))]
+fn this_cpu() -> &'static PerCpu {
+    let cpu: *const PerCpu;
+    // SAFETY: GS points at the slot of this CPU while in the kernel (see `init_this_cpu`)
+    // and the slot starts with its own address
+    unsafe {
+        core::arch::asm!(
+            "mov {}, qword ptr gs:[0]",
+            out(reg) cpu,
+            options(nostack, readonly, preserves_flags)
+        );
+        &*cpu
+    }
+}
+
+// the host tests don't go through `init_this_cpu`, they share the first slot
+#[cfg(test)]
+fn this_cpu() -> &'static PerCpu {
+    &PER_CPU[0]
+}
+
+pub(crate) fn preempt_disable() {
+    this_cpu().preempt_count.fetch_add(1, Ordering::Relaxed);
+}
+
+// the pending reschedule (if any) happens right here, once the count drops to 0
+// unless the interrupts are disabled, in which case the timer takes care of it
+pub(crate) fn preempt_enable() {
+    let cpu = this_cpu();
+    let old = cpu.preempt_count.fetch_sub(1, Ordering::Relaxed);
+    debug_assert!(old != 0, "unbalanced preempt_enable");
+
+    if old == 1 && cpu.need_resched.load(Ordering::Relaxed) && is_int_enabled() {
+        super::schedule();
+    }
+}
+
+pub(super) fn preemptible() -> bool {
+    this_cpu().preempt_count.load(Ordering::Relaxed) == 0
+}
+
+// the count goes with the task when it's switched out, see `Scheduler::schedule`
+pub(super) fn count() -> usize {
+    this_cpu().preempt_count.load(Ordering::Relaxed)
+}
+
+pub(super) fn set_count(count: usize) {
+    this_cpu().preempt_count.store(count, Ordering::Relaxed);
+}
+
+pub(super) fn set_need_resched() {
+    this_cpu().need_resched.store(true, Ordering::Relaxed);
+}
+
+pub(super) fn clear_need_resched() {
+    this_cpu().need_resched.store(false, Ordering::Relaxed);
+}
+
+// disables preemption till it goes out of scope
+#[must_use]
+pub(crate) struct PreemptGuard;
+
+impl PreemptGuard {
+    pub(crate) fn new() -> Self {
+        preempt_disable();
+        Self
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        preempt_enable();
+    }
+}
@@ -1,29 +1,26 @@
-use super::pid::Pid;
-use crate::mem::{PhysicalAddress, VirtualAddress};
+use super::pid::{Pid, Tid};
+use crate::{fs::FdTable, locks::SpinLock, mem::PhysicalAddress};
+use alloc::{sync::Arc, vec::Vec};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(super) enum State {
-    Ready,
-    Running,
-    Waiting,
-}
-
-// needed as we attempt to access the fields from inline assembly (`switch`)
-#[repr(C, packed)]
+// resources shared by all the threads of a process
 #[derive(Debug)]
 pub(super) struct Process {
-    // the fields accessed by inline assembly are placed at the top (for easy offset calculation lol)
-
-    // virtual address as per page table pointed to by the `cr3` field
-    pub(super) stack_top: VirtualAddress,
-    // used when privilege levels change from CPL3 to CPL0
-    // stored in the TSS.RSP0 field
-    pub(super) kernel_stack_top: VirtualAddress,
-    pub(super) cr3: PhysicalAddress,
-
     pub(super) id: Pid,
-    pub(super) state: State,
-    // scheduling policy
+    // physical address of the P4 table (address space)
+    // copied into every thread, as `task_switch` needs it
+    pub(super) cr3: PhysicalAddress,
+    pub(super) threads: Vec<Tid>,
+    // a separate lock, so that a thread blocked on a file doesn't keep the others from their descriptors
+    pub(super) files: Arc<SpinLock<FdTable>>,
+}
 
-    // statistics
+impl Process {
+    pub(super) fn new(id: Pid, cr3: PhysicalAddress) -> Self {
+        Self {
+            id,
+            cr3,
+            threads: Vec::new(),
+            files: Arc::new(SpinLock::new(FdTable::new())),
+        }
+    }
 }
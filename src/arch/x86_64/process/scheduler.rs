@@ -1,112 +1,138 @@
 use super::{
-    delay::Delays,
-    pid::Pid,
-    process::{Process, State},
+    pid::{Pid, Tid},
+    preempt,
+    process::Process,
+    thread::{State, Thread},
+    INTERRUPTED,
 };
 use crate::{
-    arch::x86_64::{apic::lapic::get_lapic, gdt, timers::hpet::Hpet},
+    arch::x86_64::{
+        fpu, gdt,
+        timers::{self, TimerHandle},
+    },
+    cmdline::params,
     locks::SpinLock,
 };
 use alloc::sync::Arc;
 use hashbrown::HashMap;
 use log::{info, trace};
 
-// 100 ms in ns
-const SCHEDULER_FREQ: u32 = 10u32.pow(8);
-
 #[derive(Debug)]
 pub(super) struct Scheduler {
     // processes: BTreeMap<Pid, Arc<Process>>,
     // processes: IndexMap<Pid, Arc<Process>>,
     pub(super) processes: HashMap<Pid, Arc<SpinLock<Process>>>,
-    pub(super) cur_proc: Pid,
+    // threads of all the processes
+    pub(super) threads: HashMap<Tid, Arc<SpinLock<Thread>>>,
+    pub(super) cur_thread: Tid,
     pub(super) ready_to_run: usize,
-    pub(super) delays: Delays,
+    // ends the time slice of the current thread
+    // only armed if there are other threads waiting for their turn
+    slice_timer: Option<TimerHandle>,
+    // thread whose state is currently loaded in the FPU/SSE/AVX registers
+    // the registers are saved only when another thread wants to use them
+    pub(super) fpu_owner: Option<Tid>,
 }
 
 impl Scheduler {
-    pub(super) fn new(init: Process, hpet: Arc<Hpet>) -> Self {
-        let pid = init.id;
-        // let mut processes = BTreeMap::new();
-        let mut processes = HashMap::new();
-        // let mut processes = IndexMap::new();
-        processes.insert(pid, Arc::new(SpinLock::new(init)));
-
-        Self {
-            processes,
-            cur_proc: pid,
+    pub(super) fn new(init: Process, init_thread: Thread) -> Self {
+        let mut scheduler = Self {
+            processes: HashMap::new(),
+            threads: HashMap::new(),
+            cur_thread: init_thread.id,
             ready_to_run: 0,
-            delays: Delays::new(hpet),
-        }
+            slice_timer: None,
+            fpu_owner: None,
+        };
+
+        let tid = init_thread.id;
+        scheduler.add_process(init);
+        scheduler
+            .threads
+            .insert(tid, Arc::new(SpinLock::new(init_thread)));
+        scheduler
+    }
+
+    pub(super) fn add_process(&mut self, proc: Process) {
+        assert!(proc.threads.is_empty());
+        self.processes.insert(proc.id, Arc::new(SpinLock::new(proc)));
     }
 
-    pub(super) fn add(&mut self, proc: Process) {
-        let pid = proc.id;
-        assert_eq!(proc.state, State::Ready);
+    pub(super) fn add_thread(&mut self, thread: Thread) {
+        let tid = thread.id;
+        assert_eq!(thread.state, State::Ready);
+        self.processes
+            .get(&thread.pid)
+            .unwrap()
+            .lock()
+            .threads
+            .push(tid);
         self.ready_to_run += 1;
-        self.processes.insert(pid, Arc::new(SpinLock::new(proc)));
+        self.threads.insert(tid, Arc::new(SpinLock::new(thread)));
+    }
+
+    pub(super) fn current_thread(&self) -> &Arc<SpinLock<Thread>> {
+        self.threads.get(&self.cur_thread).unwrap()
     }
 
     // SAFETY: should be called only one interrupts are disabled
     #[inline(never)]
     pub(super) unsafe fn schedule(&mut self) {
-        let next_pid = {
-            let mut iter = self.processes.iter();
+        preempt::clear_need_resched();
+        self.reap_dead_threads();
+
+        let next_tid = {
+            let mut iter = self.threads.iter();
             // find stops when it finds the entry (short circuits)
-            let _ = iter.find(|(pid, _)| &self.cur_proc == *pid);
+            let _ = iter.find(|(tid, _)| &self.cur_thread == *tid);
             iter.filter(|(_, task)| task.lock().state == State::Ready)
                 .next()
                 .or(self
-                    .processes
+                    .threads
                     .iter()
                     .filter(|(_, task)| task.lock().state == State::Ready)
                     .next()) // TODO: wraparound
         };
-        if let Some((next_pid, _)) = next_pid
-            && *next_pid != self.cur_proc
+        if let Some((next_tid, _)) = next_tid
+            && *next_tid != self.cur_thread
         {
-            trace!("changing {:x?} --> {:x?}", self.cur_proc, next_pid);
+            trace!("changing {:x?} --> {:x?}", self.cur_thread, next_tid);
+
+            // taken before locking the thread, the lock counts too
+            let preempt_count = preempt::count();
 
             let old_task = {
-                let mut old_task = self.processes.get(&self.cur_proc).unwrap().lock();
+                let mut old_task = self.threads.get(&self.cur_thread).unwrap().lock();
 
                 if old_task.state == State::Running {
                     old_task.state = State::Ready;
                 }
+                old_task.preempt_count = preempt_count;
 
                 old_task.get_val_addr() as u64
             };
 
-            let new_task = {
-                let mut new_task = self.processes.get(next_pid).unwrap().lock();
+            let (new_task, preempt_count) = {
+                let mut new_task = self.threads.get(next_tid).unwrap().lock();
 
                 new_task.state = State::Running;
 
-                self.cur_proc = new_task.id;
+                self.cur_thread = new_task.id;
 
-                new_task.get_val_addr() as u64
+                (new_task.get_val_addr() as u64, new_task.preempt_count)
             };
+            // once the lock of the new thread is released
+            preempt::set_count(preempt_count);
 
-            // set scheduler wakeup interrupt
-            let int_delay = self
-                .delays
-                .get_smallest_delay()
-                .map(|delay| core::cmp::min(delay, SCHEDULER_FREQ as u64) as u32)
-                .unwrap_or(SCHEDULER_FREQ);
+            // the new thread gets a whole time slice
+            self.update_slice_timer(true);
 
-            // WARNING: avoid setting int_delay to 0
-            // add some buffer so that `task_switch` can complete
-            // TODO: Understand the underlying problem better. How to better deal with this?
-            // maybe I can remove the expired timers
-            // but that's just pushing the problem one step further
-            // during the time required to remove expired timers, other timers can expire
-
-            let int_delay = core::cmp::max(int_delay, 1000);
-            trace!("[schedule] int_delay: {int_delay}");
-
-            // SAFETY: lapic initialised by the time we get here
-            unsafe {
-                get_lapic().set_timer_initial_count_in_ns(int_delay);
+            // the first FPU instruction of any thread other than the owner traps (#NM)
+            // and the registers are swapped then, see `take_fpu`
+            if self.fpu_owner == Some(self.cur_thread) {
+                fpu::clear_task_switched();
+            } else {
+                fpu::set_task_switched();
             }
 
             unsafe {
@@ -118,20 +144,115 @@ impl Scheduler {
                     clobber_abi("C")
                 );
             }
+        } else {
+            self.update_slice_timer(false);
+        }
+    }
+
+    // time slicing is needed only while there are more threads ready to run than CPUs (just the one for now)
+    // otherwise, no timer is armed for the scheduler (tickless)
+    // `new_slice` restarts the time slice even if it hasn't run out yet
+    fn update_slice_timer(&mut self, new_slice: bool) {
+        if self.ready_to_run > 1 {
+            let running = self
+                .slice_timer
+                .as_ref()
+                .is_some_and(|timer| timer.is_pending());
+            if new_slice || !running {
+                if let Some(timer) = self.slice_timer.take() {
+                    timer.cancel();
+                }
+                // nothing to do in the callback, the timer interrupt handler reschedules anyway
+                self.slice_timer = Some(timers::add_hrtimer(params().sched_quantum_ns, || {}));
+            }
+        } else if let Some(timer) = self.slice_timer.take() {
+            timer.cancel();
         }
     }
 
     pub(super) fn block_current(&mut self) {
-        let pid = &self.cur_proc;
-        let task = self.processes.get(pid).unwrap();
+        let task = self.current_thread();
         task.lock().state = State::Waiting;
         self.ready_to_run -= 1;
     }
 
-    pub(super) fn unblock(&mut self, pid: Pid) {
-        let mut task = self.processes.get(&pid).unwrap().lock();
+    pub(super) fn unblock(&mut self, tid: Tid) {
+        // the thread might have exited in the meantime
+        let Some(task) = self.threads.get(&tid) else {
+            return;
+        };
+        let mut task = task.lock();
+        // the task might have been woken up already by someone else
+        if task.state != State::Waiting {
+            return;
+        }
         task.state = State::Ready;
+        drop(task);
 
         self.ready_to_run += 1;
+        self.update_slice_timer(false);
+    }
+
+    // wakes up the sleeping threads of the process
+    pub(super) fn unblock_process(&mut self, pid: Pid) {
+        let Some(process) = self.processes.get(&pid) else {
+            return;
+        };
+        let threads = process.lock().threads.clone();
+        for tid in threads {
+            self.unblock(tid);
+        }
+    }
+
+    // marks the current thread as dead
+    // the process goes away along with its last thread
+    // the thread is cleaned up once the scheduler switches away from it
+    pub(super) fn exit_current(&mut self) {
+        let tid = self.cur_thread;
+        let pid = {
+            let mut task = self.current_thread().lock();
+            task.state = State::Dead;
+            task.pid
+        };
+        self.ready_to_run -= 1;
+        // no one cares about the registers of a dead thread
+        if self.fpu_owner == Some(tid) {
+            self.fpu_owner = None;
+        }
+
+        let process = self.processes.get(&pid).unwrap().clone();
+        let mut process = process.lock();
+        process.threads.retain(|thread| *thread != tid);
+        if process.threads.is_empty() {
+            info!("process {:?} exited", pid);
+            // TODO: free the address space
+            self.processes.remove(&pid);
+            INTERRUPTED.lock().remove(&pid);
+        }
+    }
+
+    fn reap_dead_threads(&mut self) {
+        // can't free the current thread, we are still running on its kernel stack
+        let cur_thread = self.cur_thread;
+        self.threads.retain(|tid, task| {
+            // TODO: free the kernel stack
+            *tid == cur_thread || task.lock().state != State::Dead
+        });
+    }
+
+    // called on #NM (device not available)
+    // the current thread used the FPU for the first time since it was switched in
+    // SAFETY: should be called only once interrupts are disabled
+    pub(super) unsafe fn take_fpu(&mut self) {
+        fpu::clear_task_switched();
+
+        if self.fpu_owner == Some(self.cur_thread) {
+            return;
+        }
+        if let Some(owner) = self.fpu_owner.and_then(|tid| self.threads.get(&tid)) {
+            owner.lock().fpu.save();
+        }
+        self.current_thread().lock().fpu.restore();
+        self.fpu_owner = Some(self.cur_thread);
     }
 }
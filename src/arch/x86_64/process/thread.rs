@@ -0,0 +1,74 @@
+use super::pid::{Pid, Tid};
+use crate::{
+    arch::x86_64::fpu::FpuState,
+    mem::{PhysicalAddress, VirtualAddress},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum State {
+    Ready,
+    Running,
+    Waiting,
+    // exited, waiting to be cleaned up by the scheduler
+    Dead,
+}
+
+// the unit of scheduling
+// needed as we attempt to access the fields from inline assembly (`switch`)
+#[repr(C)]
+#[derive(Debug)]
+pub(super) struct Thread {
+    // the fields accessed by inline assembly are placed at the top (for easy offset calculation lol)
+
+    // virtual address as per page table pointed to by the `cr3` field
+    // the rest of the register state is saved on this stack
+    pub(super) stack_top: VirtualAddress,
+    // used when privilege levels change from CPL3 to CPL0
+    // stored in the TSS.RSP0 field
+    pub(super) kernel_stack_top: VirtualAddress,
+    // same for all the threads of a process
+    pub(super) cr3: PhysicalAddress,
+    // thread local storage, loaded into IA32_FS_BASE
+    pub(super) fs_base: u64,
+
+    pub(super) id: Tid,
+    pub(super) pid: Pid,
+    pub(super) state: State,
+    // 0 for kernel threads
+    pub(super) user_stack_top: VirtualAddress,
+    // set by `clone` (CLONE_CHILD_CLEARTID)
+    // zeroed and woken up (futex) when the thread exits
+    pub(super) clear_child_tid: Option<VirtualAddress>,
+    // x87 / SSE / AVX registers, swapped in lazily (see `Scheduler::take_fpu`)
+    pub(super) fpu: FpuState,
+    // the preemption count of the CPU, saved here while the thread is switched out
+    pub(super) preempt_count: usize,
+    // scheduling policy
+
+    // statistics
+}
+
+impl Thread {
+    pub(super) fn new(
+        id: Tid,
+        pid: Pid,
+        cr3: PhysicalAddress,
+        stack_top: VirtualAddress,
+        kernel_stack_top: VirtualAddress,
+        user_stack_top: VirtualAddress,
+    ) -> Self {
+        Self {
+            stack_top,
+            kernel_stack_top,
+            cr3,
+            fs_base: 0,
+            id,
+            pid,
+            state: State::Ready,
+            user_stack_top,
+            clear_child_tid: None,
+            fpu: FpuState::new(),
+            preempt_count: 0,
+        }
+    }
+}
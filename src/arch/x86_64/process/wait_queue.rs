@@ -0,0 +1,151 @@
+use super::{super::timers, pid::Tid, unblock, SCHEDULER, SCHEDULER_LOCK};
+use crate::locks::SpinLock;
+use alloc::collections::VecDeque;
+use log::trace;
+
+// A list of tasks sleeping on some event
+// Building block for the sleeping locks (Mutex, Semaphore, Condvar, RwLock)
+// Tasks put to sleep here are marked as `Waiting` and are skipped by the scheduler until woken up
+#[derive(Debug)]
+pub(crate) struct WaitQueue {
+    waiters: SpinLock<VecDeque<Tid>>,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waiters: SpinLock::new(VecDeque::new()),
+        }
+    }
+
+    // puts the current task to sleep if `condition` returns true
+    // `condition` is run with the `SCHEDULER_LOCK` held (interrupts disabled),
+    // so a wake up can't slip in between checking the condition and going to sleep.
+    // Also used to release a resource "atomically" with going to sleep (see Condvar)
+    // The task might be woken up even if the condition it waits for doesn't hold anymore,
+    // so the callers are expected to check it again (and loop if necessary)
+    pub(crate) fn wait_while(&self, condition: impl FnOnce() -> bool) {
+        let irq = SCHEDULER_LOCK.lock();
+
+        if condition() {
+            // only sleeping needs the scheduler, the locks are taken during boot before it's set up
+            let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+            let tid = scheduler.cur_thread;
+            trace!("[wait queue] {:?} going to sleep", tid);
+            self.waiters.lock().push_back(tid);
+            scheduler.block_current();
+
+            // SAFETY: locking disables interrupts
+            unsafe {
+                scheduler.schedule();
+            }
+
+            // woken up by something other than the queue (the process was interrupted)
+            // the entry would take a wake up meant for someone else otherwise
+            let mut waiters = self.waiters.lock();
+            if let Some(pos) = waiters.iter().position(|waiter| *waiter == tid) {
+                waiters.remove(pos);
+            }
+        }
+
+        // SAFETY: SCHEDULER_LOCK is locked just above
+        unsafe {
+            SCHEDULER_LOCK.unlock(irq);
+        }
+    }
+
+    // same as `wait_while`, but the task is woken up anyway once `timeout_ns` passes
+    // returns false if the task was woken up because of the timeout
+    pub(crate) fn wait_timeout_while(
+        &self,
+        timeout_ns: u64,
+        condition: impl FnOnce() -> bool,
+    ) -> bool {
+        let scheduler = unsafe { SCHEDULER.assume_init_mut() };
+
+        let irq = SCHEDULER_LOCK.lock();
+
+        let mut woken_up = true;
+        if condition() {
+            let tid = scheduler.cur_thread;
+            trace!(
+                "[wait queue] {:?} going to sleep for at most {} ns",
+                tid,
+                timeout_ns
+            );
+            self.waiters.lock().push_back(tid);
+            scheduler.block_current();
+            let timer = timers::add_hrtimer(timeout_ns, move || unblock(tid.0 as u64));
+
+            // SAFETY: locking disables interrupts
+            unsafe {
+                scheduler.schedule();
+            }
+
+            // if we are still in the queue, no one woke us up. It was the timer
+            // otherwise, the timer is still pending and has to be cancelled
+            let mut waiters = self.waiters.lock();
+            if let Some(pos) = waiters.iter().position(|waiter| *waiter == tid) {
+                waiters.remove(pos);
+                woken_up = false;
+            } else {
+                timer.cancel();
+            }
+        }
+
+        // SAFETY: SCHEDULER_LOCK is locked just above
+        unsafe {
+            SCHEDULER_LOCK.unlock(irq);
+        }
+
+        woken_up
+    }
+
+    // wakes up the task that has been waiting for the longest time
+    // returns false if there was no one to wake up
+    // The woken up task is only marked as ready, it runs when the scheduler picks it
+    // So, it's safe to call from within an interrupt handler
+    // The scheduler is left alone if there's no one waiting, the sleeping locks are used
+    // during boot (by the VFS) before it's set up
+    pub(crate) fn wake_one(&self) -> bool {
+        let irq = SCHEDULER_LOCK.lock();
+
+        let tid = self.waiters.lock().pop_front();
+        if let Some(tid) = tid {
+            trace!("[wait queue] waking up {:?}", tid);
+            // there's someone waiting, so the scheduler is up
+            unsafe { SCHEDULER.assume_init_mut() }.unblock(tid);
+        }
+
+        // SAFETY: SCHEDULER_LOCK is locked just above
+        unsafe {
+            SCHEDULER_LOCK.unlock(irq);
+        }
+
+        tid.is_some()
+    }
+
+    // wakes up all the waiting tasks
+    // returns the number of tasks woken up
+    pub(crate) fn wake_all(&self) -> usize {
+        let irq = SCHEDULER_LOCK.lock();
+
+        let tids = core::mem::take(&mut *self.waiters.lock());
+        for tid in tids.iter() {
+            trace!("[wait queue] waking up {:?}", tid);
+            // there's someone waiting, so the scheduler is up
+            unsafe { SCHEDULER.assume_init_mut() }.unblock(*tid);
+        }
+
+        // SAFETY: SCHEDULER_LOCK is locked just above
+        unsafe {
+            SCHEDULER_LOCK.unlock(irq);
+        }
+
+        tids.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+}
@@ -0,0 +1,567 @@
+use super::{
+    keymap::{self, Keymap},
+    queue::EventQueue,
+    read_reply, send_to_device, write_data, Ps2Error, Ps2Port, Ps2Result, ACK, RESEND,
+};
+use crate::{
+    arch::x86_64::{process, tty, vga_buffer, WaitQueue},
+    fs::{self, CharDevice, FsError, FsResult},
+    locks::SpinLockIrq,
+};
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use log::{info, warn};
+
+// keyboard commands
+const SET_LEDS: u8 = 0xed;
+const SCANCODE_SET: u8 = 0xf0;
+const ENABLE_SCANNING: u8 = 0xf4;
+const RESET: u8 = 0xff;
+
+const SELF_TEST_PASSED: u8 = 0xaa;
+
+// LED bits
+const SCROLL_LOCK_LED: u8 = 1 << 0;
+const NUM_LOCK_LED: u8 = 1 << 1;
+const CAPS_LOCK_LED: u8 = 1 << 2;
+
+// prefixes of the scancodes
+const EXTENDED: u8 = 0xe0;
+// only used by Pause
+const EXTENDED2: u8 = 0xe1;
+// set 2, the next code is a release
+const RELEASE: u8 = 0xf0;
+// set 1, the release of a key is its make code with this bit set
+const BREAK_BIT: u8 = 0x80;
+
+// events kept till someone reads them, the new ones are dropped once it's full
+const QUEUE_LEN: usize = 128;
+
+// identifies a key, not the character it types (that's up to the keymap)
+// the values are the ones of Linux (`input-event-codes.h`), which match the make codes of set 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct KeyCode(pub(super) u16);
+
+#[allow(dead_code)]
+impl KeyCode {
+    pub(super) const ESC: Self = Self(1);
+    pub(super) const LEFT_CTRL: Self = Self(29);
+    pub(super) const LEFT_SHIFT: Self = Self(42);
+    pub(super) const RIGHT_SHIFT: Self = Self(54);
+    pub(super) const KP_ASTERISK: Self = Self(55);
+    pub(super) const LEFT_ALT: Self = Self(56);
+    pub(super) const SPACE: Self = Self(57);
+    pub(super) const CAPS_LOCK: Self = Self(58);
+    pub(super) const NUM_LOCK: Self = Self(69);
+    pub(super) const SCROLL_LOCK: Self = Self(70);
+    pub(super) const KP_7: Self = Self(71);
+    pub(super) const KP_8: Self = Self(72);
+    pub(super) const KP_9: Self = Self(73);
+    pub(super) const KP_MINUS: Self = Self(74);
+    pub(super) const KP_4: Self = Self(75);
+    pub(super) const KP_5: Self = Self(76);
+    pub(super) const KP_6: Self = Self(77);
+    pub(super) const KP_PLUS: Self = Self(78);
+    pub(super) const KP_1: Self = Self(79);
+    pub(super) const KP_2: Self = Self(80);
+    pub(super) const KP_3: Self = Self(81);
+    pub(super) const KP_0: Self = Self(82);
+    pub(super) const KP_DOT: Self = Self(83);
+    pub(super) const KP_ENTER: Self = Self(96);
+    pub(super) const RIGHT_CTRL: Self = Self(97);
+    pub(super) const KP_SLASH: Self = Self(98);
+    pub(super) const SYSRQ: Self = Self(99);
+    pub(super) const RIGHT_ALT: Self = Self(100);
+    pub(super) const HOME: Self = Self(102);
+    pub(super) const UP: Self = Self(103);
+    pub(super) const PAGE_UP: Self = Self(104);
+    pub(super) const LEFT: Self = Self(105);
+    pub(super) const RIGHT: Self = Self(106);
+    pub(super) const END: Self = Self(107);
+    pub(super) const DOWN: Self = Self(108);
+    pub(super) const PAGE_DOWN: Self = Self(109);
+    pub(super) const INSERT: Self = Self(110);
+    pub(super) const DELETE: Self = Self(111);
+    pub(super) const PAUSE: Self = Self(119);
+    pub(super) const LEFT_META: Self = Self(125);
+    pub(super) const RIGHT_META: Self = Self(126);
+    pub(super) const COMPOSE: Self = Self(127);
+
+    // the largest code + 1
+    const COUNT: usize = 128;
+
+    // from a set 1 make code
+    fn from_set1(code: u8, extended: bool) -> Option<Self> {
+        if !extended {
+            // everything up to F12 is the same
+            return matches!(code, 0x01..=0x58).then_some(Self(code as u16));
+        }
+        let key = match code {
+            0x1c => Self::KP_ENTER,
+            0x1d => Self::RIGHT_CTRL,
+            0x35 => Self::KP_SLASH,
+            0x37 => Self::SYSRQ,
+            0x38 => Self::RIGHT_ALT,
+            0x47 => Self::HOME,
+            0x48 => Self::UP,
+            0x49 => Self::PAGE_UP,
+            0x4b => Self::LEFT,
+            0x4d => Self::RIGHT,
+            0x4f => Self::END,
+            0x50 => Self::DOWN,
+            0x51 => Self::PAGE_DOWN,
+            0x52 => Self::INSERT,
+            0x53 => Self::DELETE,
+            0x5b => Self::LEFT_META,
+            0x5c => Self::RIGHT_META,
+            0x5d => Self::COMPOSE,
+            // including the fake shifts sent around Print Screen and the arrows
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+bitflags! {
+    pub(super) struct Modifiers: u8 {
+        const SHIFT       = 1 << 0;
+        const CTRL        = 1 << 1;
+        const ALT         = 1 << 2;
+        // right alt
+        const ALTGR       = 1 << 3;
+        const META        = 1 << 4;
+        const CAPS_LOCK   = 1 << 5;
+        const NUM_LOCK    = 1 << 6;
+        const SCROLL_LOCK = 1 << 7;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct KeyEvent {
+    pub(super) key: KeyCode,
+    // false when released
+    pub(super) pressed: bool,
+    // as they were after the key went down (or up)
+    pub(super) modifiers: Modifiers,
+    // what the key types with the current keymap, only set when pressed
+    pub(super) ch: Option<char>,
+}
+
+impl KeyEvent {
+    // how the events are read from `/dev/keyboard`
+    // key: u16, pressed: u8, modifiers: u8, ch: u32 (0 for none), all little endian
+    const SIZE: usize = 8;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.key.0.to_le_bytes());
+        bytes[2] = self.pressed as u8;
+        bytes[3] = self.modifiers.bits();
+        bytes[4..8].copy_from_slice(&(self.ch.unwrap_or('\0') as u32).to_le_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+// where a scancode sequence is at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    None,
+    Extended,
+    // Pause, the number of bytes left to drop
+    Pause(u8),
+}
+
+// the LED command is sent from the interrupt handler, byte by byte as the ACKs come in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedState {
+    Idle,
+    // sent `SET_LEDS`
+    WaitCommandAck(u8),
+    // sent the LED bits
+    WaitValueAck(u8),
+}
+
+struct Keyboard {
+    set: ScancodeSet,
+    prefix: Prefix,
+    // set 2 only, got `RELEASE`
+    release: bool,
+    // bitmap of the keys held down
+    held: [u64; KeyCode::COUNT / 64],
+    // the toggles
+    locks: Modifiers,
+    leds: LedState,
+    // what's on the LEDs, or about to be
+    leds_sent: u8,
+    keymap: &'static Keymap,
+    events: EventQueue<KeyEvent, QUEUE_LEN>,
+}
+
+static KEYBOARD: SpinLockIrq<Keyboard> = SpinLockIrq::new(Keyboard::new());
+// the tasks waiting for an event
+static EVENTS_READY: WaitQueue = WaitQueue::new();
+
+impl Keyboard {
+    const fn new() -> Self {
+        Self {
+            set: ScancodeSet::Set2,
+            prefix: Prefix::None,
+            release: false,
+            held: [0; KeyCode::COUNT / 64],
+            locks: Modifiers::empty(),
+            leds: LedState::Idle,
+            leds_sent: 0,
+            keymap: &keymap::US,
+            events: EventQueue::new(),
+        }
+    }
+
+    // a byte from the keyboard, returns the key event it completes
+    fn receive(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.handle_reply(byte) {
+            return None;
+        }
+        match self.set {
+            ScancodeSet::Set1 => self.decode_set1(byte),
+            ScancodeSet::Set2 => {
+                if byte == RELEASE {
+                    self.release = true;
+                    return None;
+                }
+                // set 2 is decoded through set 1, like the controller does when translating
+                let mut code = match byte {
+                    EXTENDED | EXTENDED2 => byte,
+                    _ => SET2_TO_SET1.get(byte as usize).copied().unwrap_or(0),
+                };
+                if core::mem::take(&mut self.release) {
+                    code |= BREAK_BIT;
+                }
+                self.decode_set1(code)
+            }
+        }
+    }
+
+    // the replies to the LED commands, returns false if `byte` is not one
+    fn handle_reply(&mut self, byte: u8) -> bool {
+        match (self.leds, byte) {
+            (LedState::WaitCommandAck(leds), ACK) => {
+                self.leds = LedState::WaitValueAck(leds);
+                let _ = write_data(leds);
+            }
+            (LedState::WaitValueAck(_), ACK) => {
+                self.leds = LedState::Idle;
+                // the locks may have changed in the meantime
+                self.update_leds();
+            }
+            (LedState::WaitCommandAck(_), RESEND) => {
+                let _ = write_data(SET_LEDS);
+            }
+            (LedState::WaitValueAck(leds), RESEND) => {
+                let _ = write_data(leds);
+            }
+            // stray replies
+            (_, ACK | RESEND) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn decode_set1(&mut self, code: u8) -> Option<KeyEvent> {
+        let (key, pressed) = match self.prefix {
+            Prefix::Pause(left) => {
+                // E1 1D 45 when pressed, E1 9D C5 when released
+                self.prefix = if left > 1 {
+                    Prefix::Pause(left - 1)
+                } else {
+                    Prefix::None
+                };
+                if left != 2 {
+                    return None;
+                }
+                (KeyCode::PAUSE, code & BREAK_BIT == 0)
+            }
+            Prefix::None if code == EXTENDED => {
+                self.prefix = Prefix::Extended;
+                return None;
+            }
+            Prefix::None if code == EXTENDED2 => {
+                self.prefix = Prefix::Pause(2);
+                return None;
+            }
+            prefix => {
+                self.prefix = Prefix::None;
+                let key = KeyCode::from_set1(code & !BREAK_BIT, prefix == Prefix::Extended)?;
+                (key, code & BREAK_BIT == 0)
+            }
+        };
+        Some(self.key_event(key, pressed))
+    }
+
+    fn key_event(&mut self, key: KeyCode, pressed: bool) -> KeyEvent {
+        let (word, bit) = (key.0 as usize / 64, 1 << (key.0 % 64));
+        // the locks toggle when pressed, not on the typematic repeats
+        let repeat = self.held[word] & bit != 0;
+        if pressed {
+            self.held[word] |= bit;
+        } else {
+            self.held[word] &= !bit;
+        }
+
+        if pressed && !repeat {
+            let lock = match key {
+                KeyCode::CAPS_LOCK => Modifiers::CAPS_LOCK,
+                KeyCode::NUM_LOCK => Modifiers::NUM_LOCK,
+                KeyCode::SCROLL_LOCK => Modifiers::SCROLL_LOCK,
+                _ => Modifiers::empty(),
+            };
+            if !lock.is_empty() {
+                self.locks.toggle(lock);
+                self.update_leds();
+            }
+        }
+
+        let modifiers = self.modifiers();
+        KeyEvent {
+            key,
+            pressed,
+            modifiers,
+            ch: if pressed {
+                self.keymap.translate(key, modifiers)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn is_held(&self, key: KeyCode) -> bool {
+        self.held[key.0 as usize / 64] & (1 << (key.0 % 64)) != 0
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        let mut modifiers = self.locks;
+        for (key, modifier) in [
+            (KeyCode::LEFT_SHIFT, Modifiers::SHIFT),
+            (KeyCode::RIGHT_SHIFT, Modifiers::SHIFT),
+            (KeyCode::LEFT_CTRL, Modifiers::CTRL),
+            (KeyCode::RIGHT_CTRL, Modifiers::CTRL),
+            (KeyCode::LEFT_ALT, Modifiers::ALT),
+            (KeyCode::RIGHT_ALT, Modifiers::ALTGR),
+            (KeyCode::LEFT_META, Modifiers::META),
+            (KeyCode::RIGHT_META, Modifiers::META),
+        ] {
+            if self.is_held(key) {
+                modifiers |= modifier;
+            }
+        }
+        modifiers
+    }
+
+    // starts sending the LED command if the LEDs don't match the locks
+    // the rest happens as the ACKs come in, see `handle_reply`
+    fn update_leds(&mut self) {
+        let mut leds = 0;
+        for (lock, led) in [
+            (Modifiers::SCROLL_LOCK, SCROLL_LOCK_LED),
+            (Modifiers::NUM_LOCK, NUM_LOCK_LED),
+            (Modifiers::CAPS_LOCK, CAPS_LOCK_LED),
+        ] {
+            if self.locks.contains(lock) {
+                leds |= led;
+            }
+        }
+        if self.leds != LedState::Idle || leds == self.leds_sent {
+            return;
+        }
+        self.leds_sent = leds;
+        self.leds = LedState::WaitCommandAck(leds);
+        let _ = write_data(SET_LEDS);
+    }
+}
+
+// resets the keyboard and finds out which scancode set it sends
+// the interrupts of the port are still disabled, the replies are polled for
+pub(super) fn init() -> Ps2Result<()> {
+    send_to_device(Ps2Port::Keyboard, RESET)?;
+    match read_reply(Ps2Port::Keyboard)? {
+        SELF_TEST_PASSED => {}
+        reply => return Err(Ps2Error::Unexpected(reply)),
+    }
+
+    // set 2 is the default after a reset and the one every keyboard supports
+    // asked for anyway, then read back
+    send_to_device(Ps2Port::Keyboard, SCANCODE_SET)?;
+    send_to_device(Ps2Port::Keyboard, 2)?;
+    send_to_device(Ps2Port::Keyboard, SCANCODE_SET)?;
+    send_to_device(Ps2Port::Keyboard, 0)?;
+    let set = match read_reply(Ps2Port::Keyboard)? {
+        1 => ScancodeSet::Set1,
+        2 => ScancodeSet::Set2,
+        set => {
+            warn!(
+                "[keyboard] unsupported scancode set {}, assuming set 2",
+                set
+            );
+            ScancodeSet::Set2
+        }
+    };
+
+    send_to_device(Ps2Port::Keyboard, SET_LEDS)?;
+    send_to_device(Ps2Port::Keyboard, 0)?;
+    send_to_device(Ps2Port::Keyboard, ENABLE_SCANNING)?;
+
+    let name = crate::cmdline::params().keymap;
+    let keymap = keymap::find(name).unwrap_or_else(|| {
+        warn!("[keyboard] unknown keymap {:?}, using us", name);
+        &keymap::US
+    });
+
+    let mut keyboard = KEYBOARD.lock();
+    keyboard.set = set;
+    keyboard.keymap = keymap;
+    drop(keyboard);
+
+    if let Err(err) = fs::register_device("keyboard", Arc::new(KeyboardDevice)) {
+        warn!("[keyboard] failed to register /dev/keyboard: {:?}", err);
+    }
+    info!("[keyboard] {:?}, keymap {}", set, keymap.name);
+    Ok(())
+}
+
+pub(super) fn interrupt(byte: u8) {
+    let mut keyboard = KEYBOARD.lock();
+    let Some(event) = keyboard.receive(byte) else {
+        return;
+    };
+
+    // Shift+PgUp/PgDn scroll the console, they are not passed on
+    if event.pressed && event.modifiers.contains(Modifiers::SHIFT) {
+        match event.key {
+            KeyCode::PAGE_UP => return vga_buffer::scroll_back(),
+            KeyCode::PAGE_DOWN => return vga_buffer::scroll_forward(),
+            _ => {}
+        }
+    }
+
+    let queued = keyboard.events.push(event);
+    drop(keyboard);
+    // the wait queue needs the scheduler, which is there once someone's waiting
+    if queued && !EVENTS_READY.is_empty() {
+        EVENTS_READY.wake_all();
+    }
+
+    if event.pressed {
+        type_key(&event);
+    }
+}
+
+// passes what the key types on to the console terminal
+// the keys that don't type a character send the escape sequences of a VT100 (xterm)
+fn type_key(event: &KeyEvent) {
+    let sequence: &[u8] = match event.key {
+        KeyCode::UP => b"\x1b[A",
+        KeyCode::DOWN => b"\x1b[B",
+        KeyCode::RIGHT => b"\x1b[C",
+        KeyCode::LEFT => b"\x1b[D",
+        KeyCode::HOME => b"\x1b[H",
+        KeyCode::END => b"\x1b[F",
+        KeyCode::INSERT => b"\x1b[2~",
+        KeyCode::DELETE => b"\x1b[3~",
+        KeyCode::PAGE_UP => b"\x1b[5~",
+        KeyCode::PAGE_DOWN => b"\x1b[6~",
+        _ => {
+            let Some(c) = event.ch else {
+                return;
+            };
+            // Alt+key is ESC followed by the key
+            if event.modifiers.contains(Modifiers::ALT) {
+                tty::input(tty::CONSOLE, b"\x1b");
+            }
+            let mut buf = [0; 4];
+            tty::input(tty::CONSOLE, c.encode_utf8(&mut buf).as_bytes());
+            return;
+        }
+    };
+    tty::input(tty::CONSOLE, sequence);
+}
+
+// the oldest event, `None` if there's none
+pub(super) fn try_read_event() -> Option<KeyEvent> {
+    KEYBOARD.lock().events.pop()
+}
+
+// waits for the next event
+// gives up if the process is interrupted (^C) in the meantime
+pub(super) fn read_event() -> FsResult<KeyEvent> {
+    loop {
+        if let Some(event) = try_read_event() {
+            return Ok(event);
+        }
+        if process::interrupt_pending() {
+            return Err(FsError::Interrupted);
+        }
+        EVENTS_READY
+            .wait_while(|| KEYBOARD.lock().events.is_empty() && !process::interrupt_pending());
+    }
+}
+
+// `/dev/keyboard`, the events as `KeyEvent::to_bytes`
+struct KeyboardDevice;
+
+impl CharDevice for KeyboardDevice {
+    // waits for an event, then returns as many as fit
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize> {
+        if buf.len() < KeyEvent::SIZE {
+            return Err(FsError::InvalidArgument);
+        }
+        let mut read = 0;
+        for (i, chunk) in buf.chunks_exact_mut(KeyEvent::SIZE).enumerate() {
+            let event = if i == 0 {
+                read_event()?
+            } else {
+                match try_read_event() {
+                    Some(event) => event,
+                    None => break,
+                }
+            };
+            chunk.copy_from_slice(&event.to_bytes());
+            read += KeyEvent::SIZE;
+        }
+        Ok(read)
+    }
+}
+
+// set 2 scancodes to set 1, the table the controller translates with
+// the extended codes (after `EXTENDED`) go through it too
+#[rustfmt::skip]
+static SET2_TO_SET1: [u8; 0x85] = {
+    let pairs: &[(u8, u8)] = &[
+        (0x01, 0x43), (0x03, 0x3f), (0x04, 0x3d), (0x05, 0x3b), (0x06, 0x3c), (0x07, 0x58),
+        (0x09, 0x44), (0x0a, 0x42), (0x0b, 0x40), (0x0c, 0x3e), (0x0d, 0x0f), (0x0e, 0x29),
+        (0x11, 0x38), (0x12, 0x2a), (0x14, 0x1d), (0x15, 0x10), (0x16, 0x02), (0x1a, 0x2c),
+        (0x1b, 0x1f), (0x1c, 0x1e), (0x1d, 0x11), (0x1e, 0x03), (0x1f, 0x5b), (0x21, 0x2e),
+        (0x22, 0x2d), (0x23, 0x20), (0x24, 0x12), (0x25, 0x05), (0x26, 0x04), (0x27, 0x5c),
+        (0x29, 0x39), (0x2a, 0x2f), (0x2b, 0x21), (0x2c, 0x14), (0x2d, 0x13), (0x2e, 0x06),
+        (0x2f, 0x5d), (0x31, 0x31), (0x32, 0x30), (0x33, 0x23), (0x34, 0x22), (0x35, 0x15),
+        (0x36, 0x07), (0x3a, 0x32), (0x3b, 0x24), (0x3c, 0x16), (0x3d, 0x08), (0x3e, 0x09),
+        (0x41, 0x33), (0x42, 0x25), (0x43, 0x17), (0x44, 0x18), (0x45, 0x0b), (0x46, 0x0a),
+        (0x49, 0x34), (0x4a, 0x35), (0x4b, 0x26), (0x4c, 0x27), (0x4d, 0x19), (0x4e, 0x0c),
+        (0x52, 0x28), (0x54, 0x1a), (0x55, 0x0d), (0x58, 0x3a), (0x59, 0x36), (0x5a, 0x1c),
+        (0x5b, 0x1b), (0x5d, 0x2b), (0x61, 0x56), (0x66, 0x0e), (0x69, 0x4f), (0x6b, 0x4b),
+        (0x6c, 0x47), (0x70, 0x52), (0x71, 0x53), (0x72, 0x50), (0x73, 0x4c), (0x74, 0x4d),
+        (0x75, 0x48), (0x76, 0x01), (0x77, 0x45), (0x78, 0x57), (0x79, 0x4e), (0x7a, 0x51),
+        (0x7b, 0x4a), (0x7c, 0x37), (0x7d, 0x49), (0x7e, 0x46), (0x83, 0x41), (0x84, 0x54),
+    ];
+    let mut table = [0; 0x85];
+    let mut i = 0;
+    while i < pairs.len() {
+        table[pairs[i].0 as usize] = pairs[i].1;
+        i += 1;
+    }
+    table
+};
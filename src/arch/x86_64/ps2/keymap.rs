@@ -0,0 +1,81 @@
+use super::keyboard::{KeyCode, Modifiers};
+
+// turns the keys into the characters they type
+// the tables are indexed by `KeyCode` (up to the space bar), 0 for the keys that type nothing
+// the keypad is the same everywhere, see `keypad`
+// like on the Linux console, Backspace types DEL and Enter a carriage return
+pub(super) struct Keymap {
+    pub(super) name: &'static str,
+    normal: &'static [u8; KEYMAP_LEN],
+    shifted: &'static [u8; KEYMAP_LEN],
+}
+
+// escape up to the space bar
+const KEYMAP_LEN: usize = KeyCode::SPACE.0 as usize + 1;
+
+pub(super) const US: Keymap = Keymap {
+    name: "us",
+    normal: b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ",
+    shifted: b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ",
+};
+
+pub(super) static KEYMAPS: &[&Keymap] = &[&US];
+
+impl Keymap {
+    // `None` for the keys that don't type anything (e.g. modifiers, arrows)
+    pub(super) fn translate(&self, key: KeyCode, modifiers: Modifiers) -> Option<char> {
+        if let Some(c) = keypad(key, modifiers) {
+            return Some(c);
+        }
+
+        let normal = *self.normal.get(key.0 as usize)?;
+        let letter = normal.is_ascii_lowercase();
+        // caps lock only works on the letters
+        let shift = modifiers.contains(Modifiers::SHIFT)
+            ^ (letter && modifiers.contains(Modifiers::CAPS_LOCK));
+        let c = if shift {
+            self.shifted[key.0 as usize]
+        } else {
+            normal
+        };
+        if c == 0 {
+            return None;
+        }
+
+        // Ctrl+A is 0x01 ... Ctrl+_ is 0x1f
+        if modifiers.contains(Modifiers::CTRL) && matches!(c.to_ascii_uppercase(), b'@'..=b'_') {
+            return Some((c.to_ascii_uppercase() & 0x1f) as char);
+        }
+        Some(c as char)
+    }
+}
+
+fn keypad(key: KeyCode, modifiers: Modifiers) -> Option<char> {
+    // the digits and the dot with num lock, otherwise they are the arrows, home, end...
+    let digits = modifiers.contains(Modifiers::NUM_LOCK) && !modifiers.contains(Modifiers::SHIFT);
+    let c = match key {
+        KeyCode::KP_SLASH => '/',
+        KeyCode::KP_ASTERISK => '*',
+        KeyCode::KP_MINUS => '-',
+        KeyCode::KP_PLUS => '+',
+        KeyCode::KP_ENTER => '\r',
+        KeyCode::KP_7 if digits => '7',
+        KeyCode::KP_8 if digits => '8',
+        KeyCode::KP_9 if digits => '9',
+        KeyCode::KP_4 if digits => '4',
+        KeyCode::KP_5 if digits => '5',
+        KeyCode::KP_6 if digits => '6',
+        KeyCode::KP_1 if digits => '1',
+        KeyCode::KP_2 if digits => '2',
+        KeyCode::KP_3 if digits => '3',
+        KeyCode::KP_0 if digits => '0',
+        KeyCode::KP_DOT if digits => '.',
+        _ => return None,
+    };
+    Some(c)
+}
+
+// the keymap called `name`, if there's one
+pub(super) fn find(name: &str) -> Option<&'static Keymap> {
+    KEYMAPS.iter().copied().find(|keymap| keymap.name == name)
+}
@@ -0,0 +1,314 @@
+mod keyboard;
+mod keymap;
+mod mouse;
+mod queue;
+
+use super::{
+    apic,
+    interrupts::{free_irq, request_irq, IrqFlags, IrqHandler, IrqReturn},
+    port::Port,
+};
+use log::{info, warn};
+
+// the 8042 PS/2 controller
+// https://wiki.osdev.org/I8042_PS/2_Controller
+
+const DATA_PORT: u16 = 0x60;
+// status when read, command when written
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+// status register
+const OUTPUT_FULL: u8 = 1 << 0;
+const INPUT_FULL: u8 = 1 << 1;
+// the byte in the data port is from the second port
+const SECOND_PORT_OUTPUT: u8 = 1 << 5;
+
+// controller commands
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND_PORT: u8 = 0xa7;
+const ENABLE_SECOND_PORT: u8 = 0xa8;
+const TEST_SECOND_PORT: u8 = 0xa9;
+const SELF_TEST: u8 = 0xaa;
+const TEST_FIRST_PORT: u8 = 0xab;
+const DISABLE_FIRST_PORT: u8 = 0xad;
+const ENABLE_FIRST_PORT: u8 = 0xae;
+// the next byte written to the data port goes to the second port
+const WRITE_SECOND_PORT: u8 = 0xd4;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+// the ISA IRQs of the ports
+const KEYBOARD_IRQ: u8 = 1;
+const MOUSE_IRQ: u8 = 12;
+
+// configuration byte
+const FIRST_PORT_IRQ: u8 = 1 << 0;
+const SECOND_PORT_IRQ: u8 = 1 << 1;
+const SECOND_PORT_CLOCK_DISABLED: u8 = 1 << 5;
+// the controller translates scancode set 2 into set 1
+const TRANSLATION: u8 = 1 << 6;
+
+// replies of the devices to a command byte
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+
+// polls before giving up on the controller
+// there's no timer to go by this early in the boot
+const TIMEOUT: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Ps2Error {
+    Timeout,
+    // the device or the controller replied with something unexpected
+    Unexpected(u8),
+}
+
+type Ps2Result<T> = Result<T, Ps2Error>;
+
+// the devices, by the port they are expected on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ps2Port {
+    Keyboard,
+    // the auxiliary port
+    Mouse,
+}
+
+fn status() -> u8 {
+    // SAFETY: the status register of the 8042, reading it has no side effects
+    unsafe { Port::new(STATUS_PORT).read() }
+}
+
+// waits till the controller is ready to take a byte
+fn wait_input_empty() -> Ps2Result<()> {
+    for _ in 0..TIMEOUT {
+        if status() & INPUT_FULL == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn wait_output_full() -> Ps2Result<()> {
+    for _ in 0..TIMEOUT {
+        if status() & OUTPUT_FULL != 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn command(command: u8) -> Ps2Result<()> {
+    wait_input_empty()?;
+    // SAFETY: the command register of the 8042
+    unsafe { Port::new(COMMAND_PORT).write(command) };
+    Ok(())
+}
+
+// a command followed by a byte to the data port
+fn command_with_arg(cmd: u8, arg: u8) -> Ps2Result<()> {
+    command(cmd)?;
+    write_data(arg)
+}
+
+// a command the controller answers
+fn command_with_reply(cmd: u8) -> Ps2Result<u8> {
+    command(cmd)?;
+    read_data()
+}
+
+fn write_data(data: u8) -> Ps2Result<()> {
+    wait_input_empty()?;
+    // SAFETY: the data port of the 8042
+    unsafe { Port::new(DATA_PORT).write(data) };
+    Ok(())
+}
+
+// polls for the reply of the controller to a command
+fn read_data() -> Ps2Result<u8> {
+    wait_output_full()?;
+    // SAFETY: the data port of the 8042
+    Ok(unsafe { Port::new(DATA_PORT).read() })
+}
+
+// drops whatever the devices sent before they were set up
+fn flush() {
+    for _ in 0..TIMEOUT {
+        if status() & OUTPUT_FULL == 0 {
+            return;
+        }
+        // SAFETY: the data port of the 8042
+        unsafe { Port::new(DATA_PORT).read::<u8>() };
+    }
+}
+
+// the byte in the data port, without waiting for it
+fn read_data_unchecked() -> u8 {
+    // SAFETY: the data port of the 8042
+    unsafe { Port::new(DATA_PORT).read() }
+}
+
+// polls for a byte from the device on `port`, what the other one sends in the meantime is dropped
+// only while the interrupts of the ports are disabled
+fn read_reply(port: Ps2Port) -> Ps2Result<u8> {
+    for _ in 0..TIMEOUT {
+        let status = status();
+        if status & OUTPUT_FULL == 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+        let from = if status & SECOND_PORT_OUTPUT != 0 {
+            Ps2Port::Mouse
+        } else {
+            Ps2Port::Keyboard
+        };
+        let byte = read_data_unchecked();
+        if from == port {
+            return Ok(byte);
+        }
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn write_to_device(port: Ps2Port, byte: u8) -> Ps2Result<()> {
+    if port == Ps2Port::Mouse {
+        command(WRITE_SECOND_PORT)?;
+    }
+    write_data(byte)
+}
+
+// sends a byte to the device on `port` and waits for its ACK
+// resent a few times if the device asks for it
+fn send_to_device(port: Ps2Port, byte: u8) -> Ps2Result<()> {
+    for _ in 0..3 {
+        write_to_device(port, byte)?;
+        match read_reply(port)? {
+            ACK => return Ok(()),
+            RESEND => continue,
+            reply => return Err(Ps2Error::Unexpected(reply)),
+        }
+    }
+    Err(Ps2Error::Unexpected(RESEND))
+}
+
+fn read_config() -> Ps2Result<u8> {
+    command_with_reply(READ_CONFIG)
+}
+
+fn write_config(config: u8) -> Ps2Result<()> {
+    command_with_arg(WRITE_CONFIG, config)
+}
+
+// returns whether there's a second port
+fn init_controller() -> Ps2Result<bool> {
+    // nothing gets in the way while the controller is set up
+    command(DISABLE_FIRST_PORT)?;
+    command(DISABLE_SECOND_PORT)?;
+    flush();
+
+    // the interrupts stay off till the devices are set up, the replies are polled for
+    // no translation, the keyboard driver decodes the set it's in
+    let config = read_config()? & !(FIRST_PORT_IRQ | SECOND_PORT_IRQ | TRANSLATION);
+    write_config(config)?;
+
+    match command_with_reply(SELF_TEST)? {
+        SELF_TEST_PASSED => {}
+        reply => return Err(Ps2Error::Unexpected(reply)),
+    }
+    // the self test may reset the controller
+    write_config(config)?;
+
+    // enabling the second port clears the bit of its clock, if there's one
+    let mut dual_channel = false;
+    if config & SECOND_PORT_CLOCK_DISABLED != 0 {
+        command(ENABLE_SECOND_PORT)?;
+        dual_channel = read_config()? & SECOND_PORT_CLOCK_DISABLED == 0;
+        command(DISABLE_SECOND_PORT)?;
+    }
+
+    if command_with_reply(TEST_FIRST_PORT)? != PORT_TEST_PASSED {
+        warn!("[ps2] first port failed its test");
+    }
+    if dual_channel && command_with_reply(TEST_SECOND_PORT)? != PORT_TEST_PASSED {
+        warn!("[ps2] second port failed its test");
+        dual_channel = false;
+    }
+
+    Ok(dual_channel)
+}
+
+// sets up the controller, the keyboard on its first port and the mouse on the second one
+pub(super) fn init() {
+    let dual_channel = match init_controller() {
+        Ok(dual_channel) => dual_channel,
+        Err(err) => {
+            warn!("[ps2] no usable controller: {:?}", err);
+            return;
+        }
+    };
+    info!(
+        "[ps2] controller initialised, dual channel: {}",
+        dual_channel
+    );
+
+    // the devices that work get their interrupts enabled
+    let mut irqs = 0;
+    match command(ENABLE_FIRST_PORT).and_then(|_| keyboard::init()) {
+        Ok(()) if request(KEYBOARD_IRQ, keyboard_interrupt) => irqs |= FIRST_PORT_IRQ,
+        Ok(()) => {
+            let _ = command(DISABLE_FIRST_PORT);
+        }
+        Err(err) => {
+            warn!("[ps2] no keyboard: {:?}", err);
+            let _ = command(DISABLE_FIRST_PORT);
+        }
+    }
+    if dual_channel {
+        match command(ENABLE_SECOND_PORT).and_then(|_| mouse::init()) {
+            Ok(()) if request(MOUSE_IRQ, mouse_interrupt) => irqs |= SECOND_PORT_IRQ,
+            Ok(()) => {
+                let _ = command(DISABLE_SECOND_PORT);
+            }
+            Err(err) => {
+                warn!("[ps2] no mouse: {:?}", err);
+                let _ = command(DISABLE_SECOND_PORT);
+            }
+        }
+    }
+
+    // the clock of a port is enabled along with it, the config is read again to keep that
+    if let Err(err) = read_config().and_then(|config| write_config(config | irqs)) {
+        warn!("[ps2] failed to enable the interrupts: {:?}", err);
+        if irqs & FIRST_PORT_IRQ != 0 {
+            free_irq(apic::isa_gsi(KEYBOARD_IRQ), keyboard_interrupt, 0);
+        }
+        if irqs & SECOND_PORT_IRQ != 0 {
+            free_irq(apic::isa_gsi(MOUSE_IRQ), mouse_interrupt, 0);
+        }
+    }
+}
+
+// the ISA IRQs are edge triggered and not shared
+fn request(irq: u8, handler: IrqHandler) -> bool {
+    match request_irq(apic::isa_gsi(irq), handler, 0, IrqFlags::empty()) {
+        Ok(_) => true,
+        Err(err) => {
+            warn!("[ps2] failed to request IRQ {}: {:?}", irq, err);
+            false
+        }
+    }
+}
+
+fn keyboard_interrupt(_: usize) -> IrqReturn {
+    keyboard::interrupt(read_data_unchecked());
+    IrqReturn::Handled
+}
+
+fn mouse_interrupt(_: usize) -> IrqReturn {
+    mouse::interrupt(read_data_unchecked());
+    IrqReturn::Handled
+}
@@ -0,0 +1,266 @@
+use super::{queue::EventQueue, read_reply, send_to_device, Ps2Error, Ps2Port, Ps2Result};
+use crate::{
+    arch::x86_64::{process, WaitQueue},
+    fs::{self, CharDevice, FsError, FsResult},
+    locks::SpinLockIrq,
+};
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use log::{info, trace, warn};
+
+// mouse commands
+const GET_DEVICE_ID: u8 = 0xf2;
+const SET_SAMPLE_RATE: u8 = 0xf3;
+const ENABLE_REPORTING: u8 = 0xf4;
+const SET_DEFAULTS: u8 = 0xf6;
+const RESET: u8 = 0xff;
+
+const SELF_TEST_PASSED: u8 = 0xaa;
+
+// device ids
+const STANDARD_MOUSE: u8 = 0x00;
+// has a scroll wheel, sends 4 byte packets
+const INTELLIMOUSE: u8 = 0x03;
+
+// the first byte of a packet
+const LEFT_BUTTON: u8 = 1 << 0;
+const RIGHT_BUTTON: u8 = 1 << 1;
+const MIDDLE_BUTTON: u8 = 1 << 2;
+// always set, used to find the start of a packet
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+// samples per second, the default is 100
+const SAMPLE_RATE: u8 = 100;
+
+const QUEUE_LEN: usize = 256;
+
+bitflags! {
+    pub(super) struct MouseButtons: u8 {
+        const LEFT   = 1 << 0;
+        const RIGHT  = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+// the motion since the previous event, and the buttons held now
+// x grows to the right and y downwards, like on the screen
+// the wheel is positive when scrolled up (away from the user)
+#[derive(Debug, Clone, Copy)]
+pub(super) struct MouseEvent {
+    pub(super) dx: i16,
+    pub(super) dy: i16,
+    pub(super) wheel: i8,
+    pub(super) buttons: MouseButtons,
+}
+
+impl MouseEvent {
+    // how the events are read from `/dev/mouse`
+    // dx: i16, dy: i16, wheel: i8, buttons: u8, 2 bytes of padding, all little endian
+    const SIZE: usize = 8;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.dx.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.dy.to_le_bytes());
+        bytes[4] = self.wheel as u8;
+        bytes[5] = self.buttons.bits();
+        bytes
+    }
+}
+
+struct Mouse {
+    // 3, or 4 with a scroll wheel
+    packet_len: usize,
+    packet: [u8; 4],
+    // bytes of `packet` received so far
+    received: usize,
+    events: EventQueue<MouseEvent, QUEUE_LEN>,
+}
+
+static MOUSE: SpinLockIrq<Mouse> = SpinLockIrq::new(Mouse::new());
+// the tasks waiting for an event
+static EVENTS_READY: WaitQueue = WaitQueue::new();
+
+impl Mouse {
+    const fn new() -> Self {
+        Self {
+            packet_len: 3,
+            packet: [0; 4],
+            received: 0,
+            events: EventQueue::new(),
+        }
+    }
+
+    // a byte from the mouse, returns the event once a packet is complete
+    fn receive(&mut self, byte: u8) -> Option<MouseEvent> {
+        // out of sync, e.g. a byte got lost. Waits for something that can start a packet
+        if self.received == 0 && byte & ALWAYS_ONE == 0 {
+            trace!("[mouse] dropped {:#x}", byte);
+            return None;
+        }
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_len {
+            return None;
+        }
+        self.received = 0;
+        decode(&self.packet[..self.packet_len])
+    }
+}
+
+fn decode(packet: &[u8]) -> Option<MouseEvent> {
+    let flags = packet[0];
+    // the motion is not usable
+    if flags & (X_OVERFLOW | Y_OVERFLOW) != 0 {
+        return None;
+    }
+
+    // 9 bit two's complement, the sign is in the first byte
+    let motion = |value: u8, sign: u8| {
+        let value = value as i16;
+        if flags & sign != 0 {
+            value - 0x100
+        } else {
+            value
+        }
+    };
+    // the low 4 bits, two's complement, positive when scrolled down
+    let wheel = match packet.get(3) {
+        Some(&z) => -(((z << 4) as i8) >> 4),
+        None => 0,
+    };
+
+    let mut buttons = MouseButtons::empty();
+    for (bit, button) in [
+        (LEFT_BUTTON, MouseButtons::LEFT),
+        (RIGHT_BUTTON, MouseButtons::RIGHT),
+        (MIDDLE_BUTTON, MouseButtons::MIDDLE),
+    ] {
+        if flags & bit != 0 {
+            buttons |= button;
+        }
+    }
+
+    Some(MouseEvent {
+        dx: motion(packet[1], X_SIGN),
+        // the mouse counts upwards
+        dy: -motion(packet[2], Y_SIGN),
+        wheel,
+        buttons,
+    })
+}
+
+fn set_sample_rate(rate: u8) -> Ps2Result<()> {
+    send_to_device(Ps2Port::Mouse, SET_SAMPLE_RATE)?;
+    send_to_device(Ps2Port::Mouse, rate)
+}
+
+fn device_id() -> Ps2Result<u8> {
+    send_to_device(Ps2Port::Mouse, GET_DEVICE_ID)?;
+    read_reply(Ps2Port::Mouse)
+}
+
+// resets the mouse and turns on the scroll wheel if it has one
+// the interrupts of the port are still disabled, the replies are polled for
+pub(super) fn init() -> Ps2Result<()> {
+    send_to_device(Ps2Port::Mouse, RESET)?;
+    match read_reply(Ps2Port::Mouse)? {
+        SELF_TEST_PASSED => {}
+        reply => return Err(Ps2Error::Unexpected(reply)),
+    }
+    // followed by the device id
+    match read_reply(Ps2Port::Mouse)? {
+        STANDARD_MOUSE => {}
+        id => return Err(Ps2Error::Unexpected(id)),
+    }
+    send_to_device(Ps2Port::Mouse, SET_DEFAULTS)?;
+
+    // the magic sequence that turns a mouse into an IntelliMouse, if it is one
+    for rate in [200, 100, 80] {
+        set_sample_rate(rate)?;
+    }
+    let id = device_id()?;
+    let packet_len = match id {
+        INTELLIMOUSE => 4,
+        STANDARD_MOUSE => 3,
+        id => {
+            warn!(
+                "[mouse] unknown device id {:#x}, assuming a standard mouse",
+                id
+            );
+            3
+        }
+    };
+    set_sample_rate(SAMPLE_RATE)?;
+    send_to_device(Ps2Port::Mouse, ENABLE_REPORTING)?;
+
+    MOUSE.lock().packet_len = packet_len;
+
+    if let Err(err) = fs::register_device("mouse", Arc::new(MouseDevice)) {
+        warn!("[mouse] failed to register /dev/mouse: {:?}", err);
+    }
+    info!("[mouse] initialised, scroll wheel: {}", packet_len == 4);
+    Ok(())
+}
+
+pub(super) fn interrupt(byte: u8) {
+    let mut mouse = MOUSE.lock();
+    let Some(event) = mouse.receive(byte) else {
+        return;
+    };
+    let queued = mouse.events.push(event);
+    drop(mouse);
+    // the wait queue needs the scheduler, which is there once someone's waiting
+    if queued && !EVENTS_READY.is_empty() {
+        EVENTS_READY.wake_all();
+    }
+}
+
+// the oldest event, `None` if there's none
+pub(super) fn try_read_event() -> Option<MouseEvent> {
+    MOUSE.lock().events.pop()
+}
+
+// waits for the next event
+// gives up if the process is interrupted (^C) in the meantime
+pub(super) fn read_event() -> FsResult<MouseEvent> {
+    loop {
+        if let Some(event) = try_read_event() {
+            return Ok(event);
+        }
+        if process::interrupt_pending() {
+            return Err(FsError::Interrupted);
+        }
+        EVENTS_READY.wait_while(|| MOUSE.lock().events.is_empty() && !process::interrupt_pending());
+    }
+}
+
+// `/dev/mouse`, the events as `MouseEvent::to_bytes`
+struct MouseDevice;
+
+impl CharDevice for MouseDevice {
+    // waits for an event, then returns as many as fit
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize> {
+        if buf.len() < MouseEvent::SIZE {
+            return Err(FsError::InvalidArgument);
+        }
+        let mut read = 0;
+        for (i, chunk) in buf.chunks_exact_mut(MouseEvent::SIZE).enumerate() {
+            let event = if i == 0 {
+                read_event()?
+            } else {
+                match try_read_event() {
+                    Some(event) => event,
+                    None => break,
+                }
+            };
+            chunk.copy_from_slice(&event.to_bytes());
+            read += MouseEvent::SIZE;
+        }
+        Ok(read)
+    }
+}
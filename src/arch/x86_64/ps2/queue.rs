@@ -0,0 +1,43 @@
+// the input events kept till someone reads them
+// fixed size, filled from the interrupt handlers where there's no allocating
+// the new events are dropped once it's full
+pub(super) struct EventQueue<T, const N: usize> {
+    events: [Option<T>; N],
+    // where the oldest event is
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> EventQueue<T, N> {
+    pub(super) const fn new() -> Self {
+        Self {
+            events: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    // returns false if the event was dropped
+    pub(super) fn push(&mut self, event: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.events[(self.head + self.len) % N] = Some(event);
+        self.len += 1;
+        true
+    }
+
+    pub(super) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        event
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
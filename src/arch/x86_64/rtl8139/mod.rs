@@ -0,0 +1,225 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{info, warn};
+
+use super::{
+    apic,
+    interrupts::{free_irq, request_irq, IrqFlags, IrqHandler, IrqReturn},
+    pci::{
+        self, BaseAddrRegType, HeaderType, MsiError, MsiVectors, PciDevice, PciDriver, PciId,
+        ProbeError,
+    },
+    port::Port,
+};
+use crate::locks::SpinLock;
+
+// Realtek RTL8139 fast ethernet controller
+// https://wiki.osdev.org/RTL8139
+
+// the registers, offsets from the IO base
+// Receive (Rx) Buffer Start Address
+const RBSTART: u16 = 0x30;
+const COMMAND: u16 = 0x37;
+const IMR: u16 = 0x3c;
+const ISR: u16 = 0x3e;
+// Receive (Rx) Configuration Register
+const RCR: u16 = 0x44;
+const CONFIG1: u16 = 0x52;
+
+// command
+const RESET: u8 = 1 << 4;
+const RX_ENABLE: u8 = 1 << 3;
+const TX_ENABLE: u8 = 1 << 2;
+
+// interrupt mask and status
+const RX_OK: u16 = 1 << 0;
+const TX_OK: u16 = 1 << 2;
+
+// accept the physical match, multicast, broadcast and all the other packets
+const RX_ACCEPT_ALL: u32 = 0xf;
+
+// 8 KiB and the 16 bytes of the header of the last packet
+const RX_BUFFER_LEN: usize = 8192 + 16;
+
+// polls of the command register before the card is given up on, if it doesn't come out of reset
+const RESET_TIMEOUT: usize = 100_000;
+
+// PCI command, the card reads and writes the buffers itself
+const BUS_MASTER: u16 = 1 << 2;
+
+const IDS: [PciId; 1] = [PciId::new(0x10ec, 0x8139)];
+
+enum Interrupt {
+    Msi(MsiVectors),
+    // the GSI of the INTx line
+    Line(u32),
+}
+
+// a card that's been set up
+struct Nic {
+    // handed out by `probe`, so that `remove` tears down that very card
+    // (a function can be probed again while it's set up, see `pci::driver::bind`)
+    id: usize,
+    base: u16,
+    // where the card puts the received packets, it's the card's till it's stopped
+    _rx_buffer: Box<[u8; RX_BUFFER_LEN]>,
+    interrupt: Interrupt,
+}
+
+static NICS: SpinLock<Vec<Nic>> = SpinLock::new(Vec::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Rtl8139;
+
+static DRIVER: Rtl8139 = Rtl8139;
+
+impl PciDriver for Rtl8139 {
+    fn name(&self) -> &'static str {
+        "rtl8139"
+    }
+
+    fn ids(&self) -> &'static [PciId] {
+        &IDS
+    }
+
+    fn probe(&self, device: &mut PciDevice) -> Result<usize, ProbeError> {
+        let HeaderType::Type0(mut header) = device.header_type else {
+            return Err(ProbeError::MissingBar);
+        };
+        let (bus, dev, function) = (device.bus, device.device, device.function);
+        let base = header
+            .bars(bus, dev, function)
+            .into_iter()
+            .find_map(|mut bar| match bar.read() {
+                BaseAddrRegType::Io(port) => Some(port as u16),
+                BaseAddrRegType::Mem { .. } => None,
+            })
+            .ok_or(ProbeError::MissingBar)?;
+
+        let cur = device.command();
+        device.write_command(cur | BUS_MASTER);
+
+        let reset = unsafe {
+            // power on
+            Port::new(base + CONFIG1).write::<u8>(0x00);
+
+            let mut command = Port::new(base + COMMAND);
+            command.write::<u8>(RESET);
+            (0..RESET_TIMEOUT).any(|_| command.read::<u8>() & RESET == 0)
+        };
+        if !reset {
+            device.write_command(cur);
+            return Err(ProbeError::Device);
+        }
+
+        // MSI if it can, INTx otherwise
+        // before RX is enabled, so that the card stays stopped if there's no interrupt for it
+        let handlers = [(rtl8139_interrupt as IrqHandler, base as usize)];
+        let interrupt = match device.request_msi(&handlers) {
+            // the card doesn't interrupt till IMR is set, the vector can be unmasked right away
+            Ok(mut vectors) => match vectors.unmask(0) {
+                Ok(()) => Ok(Interrupt::Msi(vectors)),
+                Err(err) => {
+                    vectors.free();
+                    Err(ProbeError::Msi(err))
+                }
+            },
+            Err(MsiError::NotCapable) => {
+                // level triggered, active low and maybe shared
+                let gsi = apic::isa_gsi(header.interrupt_line(bus, dev, function));
+                request_irq(
+                    gsi,
+                    rtl8139_interrupt,
+                    base as usize,
+                    IrqFlags::SHARED | IrqFlags::LEVEL | IrqFlags::ACTIVE_LOW,
+                )
+                .map(|_| Interrupt::Line(gsi))
+                .map_err(ProbeError::Irq)
+            }
+            Err(err) => Err(ProbeError::Msi(err)),
+        };
+        let interrupt = match interrupt {
+            Ok(interrupt) => interrupt,
+            Err(err) => {
+                device.write_command(cur);
+                return Err(err);
+            }
+        };
+
+        let rx_buffer = Box::new([0u8; RX_BUFFER_LEN]);
+        unsafe {
+            Port::new(base + COMMAND).write::<u8>(RX_ENABLE | TX_ENABLE);
+            Port::new(base + RBSTART).write(rx_buffer.as_ptr() as u32);
+            Port::new(base + IMR).write(RX_OK | TX_OK);
+            Port::new(base + RCR).write::<u32>(RX_ACCEPT_ALL);
+        }
+
+        info!(
+            "[rtl8139] {:02x}:{:02x}.{} at port {:#x}, {}",
+            bus,
+            dev,
+            function,
+            base,
+            match interrupt {
+                Interrupt::Msi(_) => "MSI",
+                Interrupt::Line(_) => "INTx",
+            }
+        );
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        NICS.lock().push(Nic {
+            id,
+            base,
+            _rx_buffer: rx_buffer,
+            interrupt,
+        });
+        Ok(id)
+    }
+
+    fn remove(&self, device: &mut PciDevice, id: usize) {
+        let mut nic = {
+            let mut nics = NICS.lock();
+            let Some(index) = nics.iter().position(|nic| nic.id == id) else {
+                warn!("[rtl8139] removing a card that's not been set up");
+                return;
+            };
+            nics.remove(index)
+        };
+
+        // no messages while the card is being stopped
+        if let Interrupt::Msi(vectors) = &mut nic.interrupt {
+            let _ = vectors.mask(0);
+        }
+        unsafe {
+            Port::new(nic.base + IMR).write(0u16);
+            // the card stops writing into the buffer, it can be freed
+            Port::new(nic.base + COMMAND).write::<u8>(0);
+        }
+        match nic.interrupt {
+            Interrupt::Msi(vectors) => vectors.free(),
+            Interrupt::Line(gsi) => free_irq(gsi, rtl8139_interrupt, nic.base as usize),
+        }
+
+        let cur = device.command();
+        device.write_command(cur & !BUS_MASTER);
+    }
+}
+
+pub(super) fn init() {
+    pci::register_driver(&DRIVER);
+}
+
+// `base` is the IO base of the RTL8139
+fn rtl8139_interrupt(base: usize) -> IrqReturn {
+    let base = base as u16;
+    unsafe {
+        let mut isr = Port::new(base + ISR);
+        let status = isr.read::<u16>();
+        if status == 0 {
+            // someone else on the line
+            return IrqReturn::None;
+        }
+        // the bits are cleared by writing them back, the line stays asserted otherwise
+        isr.write(status);
+    }
+    IrqReturn::Handled
+}
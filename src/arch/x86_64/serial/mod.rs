@@ -0,0 +1,278 @@
+use super::{
+    apic,
+    interrupts::{request_irq, IrqFlags, IrqReturn},
+    port::Port,
+    tty,
+};
+use crate::locks::SpinLockIrq;
+use core::fmt;
+use log::{info, warn};
+
+// 16550 UART
+// https://wiki.osdev.org/Serial_Ports
+
+// the registers, offsets from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+// with DLAB set
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+// read only
+const INTERRUPT_ID: u16 = 2;
+// write only
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+// interrupt enable
+const RX_AVAILABLE: u8 = 1 << 0;
+
+// FIFO control
+const FIFO_ENABLE: u8 = 1 << 0;
+const FIFO_CLEAR_RX: u8 = 1 << 1;
+const FIFO_CLEAR_TX: u8 = 1 << 2;
+// interrupt once there are 14 bytes in the receive FIFO (or after a timeout)
+const FIFO_TRIGGER_14: u8 = 0b11 << 6;
+
+// interrupt identification
+// both bits are set if the FIFOs are there and working (16550A and later)
+const FIFOS_ENABLED: u8 = 0b11 << 6;
+
+// line control
+const DATA_BITS_8: u8 = 0b11;
+// the divisor registers replace the data and interrupt enable ones
+const DLAB: u8 = 1 << 7;
+
+// modem control
+const DTR: u8 = 1 << 0;
+const RTS: u8 = 1 << 1;
+// gates the interrupt line of the UART on PCs
+const OUT2: u8 = 1 << 3;
+const LOOPBACK: u8 = 1 << 4;
+
+// line status
+const DATA_READY: u8 = 1 << 0;
+const TX_HOLDING_EMPTY: u8 = 1 << 5;
+
+// the UART clock divided by 16
+const MAX_BAUD: u32 = 115_200;
+const BAUD: u32 = 115_200;
+
+// polls of the line status before the rest of a write is dropped, e.g. when nothing is connected
+const TX_TIMEOUT: usize = 100_000;
+
+// bytes taken from the receive FIFO, and put in the transmit one, at a time
+const FIFO_LEN: usize = 16;
+
+// base port and IRQ of COM1-COM4
+const COM_PORTS: [(u16, u8); 4] = [(0x3f8, 4), (0x2f8, 3), (0x3e8, 4), (0x2e8, 3)];
+
+// what `print!` writes to, before and after the port is set up
+const CONSOLE_PORT: usize = 0;
+
+struct Uart {
+    base: u16,
+    initialised: bool,
+    // the transmitter takes `FIFO_LEN` bytes at a time, instead of 1
+    fifo: bool,
+}
+
+static PORTS: [SpinLockIrq<Uart>; 4] = [
+    SpinLockIrq::new(Uart::new(COM_PORTS[0].0)),
+    SpinLockIrq::new(Uart::new(COM_PORTS[1].0)),
+    SpinLockIrq::new(Uart::new(COM_PORTS[2].0)),
+    SpinLockIrq::new(Uart::new(COM_PORTS[3].0)),
+];
+
+impl Uart {
+    const fn new(base: u16) -> Self {
+        Self {
+            base,
+            initialised: false,
+            fifo: false,
+        }
+    }
+
+    fn read_reg(&self, reg: u16) -> u8 {
+        // SAFETY: the registers of the UART
+        unsafe { Port::new(self.base + reg).read() }
+    }
+
+    fn write_reg(&self, reg: u16, value: u8) {
+        // SAFETY: the registers of the UART
+        unsafe { Port::new(self.base + reg).write(value) }
+    }
+
+    // returns false if there's no working UART at the port
+    fn init(&mut self, baud: u32) -> bool {
+        // nothing answers on the port
+        self.write_reg(SCRATCH, 0x5a);
+        if self.read_reg(SCRATCH) != 0x5a {
+            return false;
+        }
+
+        self.write_reg(INTERRUPT_ENABLE, 0);
+        self.configure(baud);
+        self.write_reg(
+            FIFO_CONTROL,
+            FIFO_ENABLE | FIFO_CLEAR_RX | FIFO_CLEAR_TX | FIFO_TRIGGER_14,
+        );
+        self.fifo = self.read_reg(INTERRUPT_ID) & FIFOS_ENABLED == FIFOS_ENABLED;
+
+        // what's sent in loopback mode has to come back
+        self.write_reg(MODEM_CONTROL, RTS | OUT2 | LOOPBACK);
+        self.write_reg(DATA, 0xae);
+        if self.read_reg(DATA) != 0xae {
+            return false;
+        }
+
+        self.write_reg(MODEM_CONTROL, DTR | RTS | OUT2);
+        // whatever was received before
+        while self.read_reg(LINE_STATUS) & DATA_READY != 0 {
+            self.read_reg(DATA);
+        }
+        self.write_reg(INTERRUPT_ENABLE, RX_AVAILABLE);
+        self.initialised = true;
+        true
+    }
+
+    // 8 data bits, no parity, 1 stop bit
+    fn configure(&self, baud: u32) {
+        let divisor = (MAX_BAUD / baud.clamp(1, MAX_BAUD)) as u16;
+        self.write_reg(LINE_CONTROL, DLAB);
+        self.write_reg(DIVISOR_LOW, divisor as u8);
+        self.write_reg(DIVISOR_HIGH, (divisor >> 8) as u8);
+        self.write_reg(LINE_CONTROL, DATA_BITS_8);
+    }
+
+    // fills the transmitter if it's empty, returns the number of bytes taken (0 if it's still busy)
+    fn send(&self, buf: &[u8]) -> usize {
+        if self.read_reg(LINE_STATUS) & TX_HOLDING_EMPTY == 0 {
+            return 0;
+        }
+        let len = buf.len().min(if self.fifo { FIFO_LEN } else { 1 });
+        for &byte in &buf[..len] {
+            self.write_reg(DATA, byte);
+        }
+        len
+    }
+
+    // takes what's in the receive FIFO, as much as fits in `buf`
+    fn receive(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while len < buf.len() && self.read_reg(LINE_STATUS) & DATA_READY != 0 {
+            buf[len] = self.read_reg(DATA);
+            len += 1;
+        }
+        len
+    }
+}
+
+// what `print!` writes through
+struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            // the terminals on the other end expect CRLF
+            if i != 0 {
+                write(CONSOLE_PORT, b"\r\n");
+            }
+            write(CONSOLE_PORT, line.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+// sets up the UARTs that are there, the terminals on them are `/dev/ttyS0`..`/dev/ttyS3`
+pub(super) fn init() {
+    for (i, (port, &(base, irq))) in PORTS.iter().zip(COM_PORTS.iter()).enumerate() {
+        if port.lock().init(BAUD) {
+            info!(
+                "[serial] COM{} at {:#x}, IRQ {}, {} baud",
+                i + 1,
+                base,
+                irq,
+                BAUD
+            );
+        }
+    }
+
+    // the ports on the same IRQ share a handler
+    for irq in [3, 4] {
+        let used = COM_PORTS
+            .iter()
+            .enumerate()
+            .any(|(i, &(_, port_irq))| port_irq == irq && present(i));
+        if !used {
+            continue;
+        }
+        if let Err(err) = request_irq(
+            apic::isa_gsi(irq),
+            interrupt,
+            irq as usize,
+            IrqFlags::empty(),
+        ) {
+            warn!("[serial] failed to request IRQ {}: {:?}", irq, err);
+        }
+    }
+}
+
+// whether there's a working UART for COM`port + 1`
+pub(super) fn present(port: usize) -> bool {
+    PORTS[port].lock().initialised
+}
+
+// IRQ3 and IRQ4, each shared by two ports
+// what's received is passed on to the terminal of the port
+fn interrupt(irq: usize) -> IrqReturn {
+    let mut handled = IrqReturn::None;
+    for (i, &(_, port_irq)) in COM_PORTS.iter().enumerate() {
+        if port_irq as usize != irq {
+            continue;
+        }
+        loop {
+            let mut buf = [0; FIFO_LEN];
+            let port = PORTS[i].lock();
+            if !port.initialised {
+                break;
+            }
+            let len = port.receive(&mut buf);
+            drop(port);
+            if len == 0 {
+                break;
+            }
+            handled = IrqReturn::Handled;
+            tty::input(tty::serial(i), &buf[..len]);
+        }
+    }
+    handled
+}
+
+// the port is locked (and the interrupts are off) only while the transmitter is filled
+// waiting for it to drain is done with the lock released
+pub(super) fn write(port: usize, mut buf: &[u8]) {
+    while !buf.is_empty() {
+        let mut sent = 0;
+        for _ in 0..TX_TIMEOUT {
+            sent = PORTS[port].lock().send(buf);
+            if sent != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        if sent == 0 {
+            return;
+        }
+        buf = &buf[sent..];
+    }
+}
+
+// used by `print!`, works before `init` too
+// the UART is usually set up by the firmware (or emulated), good enough for the early messages
+pub(super) fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    Console.write_fmt(args).unwrap();
+}
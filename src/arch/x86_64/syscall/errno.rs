@@ -0,0 +1,52 @@
+use crate::fs::FsError;
+
+// error numbers returned (negated) to the userspace in `rax`
+// same values as Linux, so that the userspace libraries don't need to translate them
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub(super) enum Errno {
+    EPERM = 1,
+    ENOENT = 2,
+    EINTR = 4,
+    EBADF = 9,
+    EAGAIN = 11,
+    EFAULT = 14,
+    EBUSY = 16,
+    EEXIST = 17,
+    ENOTDIR = 20,
+    EISDIR = 21,
+    EINVAL = 22,
+    EMFILE = 24,
+    ENOTTY = 25,
+    ENOSPC = 28,
+    ESPIPE = 29,
+    ENAMETOOLONG = 36,
+    ENOSYS = 38,
+    ENOTEMPTY = 39,
+    ETIMEDOUT = 110,
+}
+
+pub(super) type SyscallResult = Result<u64, Errno>;
+
+impl From<FsError> for Errno {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::NotFound => Errno::ENOENT,
+            FsError::NotADirectory => Errno::ENOTDIR,
+            FsError::IsADirectory => Errno::EISDIR,
+            FsError::AlreadyExists => Errno::EEXIST,
+            FsError::NotEmpty => Errno::ENOTEMPTY,
+            FsError::InvalidArgument => Errno::EINVAL,
+            FsError::NameTooLong => Errno::ENAMETOOLONG,
+            FsError::NoSpace => Errno::ENOSPC,
+            FsError::NotSeekable => Errno::ESPIPE,
+            FsError::BadDescriptor => Errno::EBADF,
+            FsError::TooManyOpenFiles => Errno::EMFILE,
+            FsError::Busy => Errno::EBUSY,
+            FsError::InappropriateIoctl => Errno::ENOTTY,
+            FsError::BadAddress => Errno::EFAULT,
+            FsError::Interrupted => Errno::EINTR,
+        }
+    }
+}
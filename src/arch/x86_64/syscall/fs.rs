@@ -0,0 +1,235 @@
+use super::{
+    errno::{Errno, SyscallResult},
+    user,
+};
+use crate::{
+    arch::x86_64::process,
+    fs::{self, File, FileType, Metadata, OpenFlags, SeekFrom, PATH_MAX},
+};
+use alloc::sync::Arc;
+use log::trace;
+
+// open flags (same as Linux)
+const O_ACCMODE: u64 = 0o3;
+const O_RDONLY: u64 = 0o0;
+const O_WRONLY: u64 = 0o1;
+const O_RDWR: u64 = 0o2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+const O_DIRECTORY: u64 = 0o200000;
+
+// lseek whence
+const SEEK_SET: u64 = 0;
+const SEEK_CUR: u64 = 1;
+const SEEK_END: u64 = 2;
+
+// file type bits of `st_mode`
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+// `d_type` of the directory entries
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+// the `struct stat` of Linux x86_64
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+struct Stat {
+    st_dev: u64,
+    st_ino: u64,
+    st_nlink: u64,
+    st_mode: u32,
+    st_uid: u32,
+    st_gid: u32,
+    __pad0: u32,
+    st_rdev: u64,
+    st_size: i64,
+    st_blksize: i64,
+    st_blocks: i64,
+    st_atime: i64,
+    st_atime_nsec: i64,
+    st_mtime: i64,
+    st_mtime_nsec: i64,
+    st_ctime: i64,
+    st_ctime_nsec: i64,
+    __unused: [i64; 3],
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        let kind = match metadata.kind {
+            FileType::Regular => S_IFREG,
+            FileType::Directory => S_IFDIR,
+            FileType::CharDevice => S_IFCHR,
+        };
+        Self {
+            st_dev: metadata.dev,
+            st_ino: metadata.ino,
+            st_nlink: metadata.nlink as u64,
+            st_mode: kind | metadata.mode as u32,
+            st_size: metadata.size as i64,
+            st_blksize: 4096,
+            st_blocks: metadata.size.div_ceil(512) as i64,
+            ..Default::default()
+        }
+    }
+}
+
+// the fixed part of `struct linux_dirent64`, followed by the null terminated name
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Dirent64Header {
+    d_ino: u64,
+    d_off: i64,
+    d_reclen: u16,
+    d_type: u8,
+}
+
+fn get_file(fd: u64) -> Result<Arc<dyn File>, Errno> {
+    // the lock is not held while the file is used
+    Ok(process::current_files().lock().get(fd as usize)?)
+}
+
+pub(super) fn read(fd: u64, buf: u64, count: u64) -> SyscallResult {
+    let file = get_file(fd)?;
+    let buf = user::slice_mut(buf, count as usize)?;
+    Ok(file.read(buf)? as u64)
+}
+
+pub(super) fn write(fd: u64, buf: u64, count: u64) -> SyscallResult {
+    let file = get_file(fd)?;
+    let buf = user::slice(buf, count as usize)?;
+    Ok(file.write(buf)? as u64)
+}
+
+pub(super) fn open(path: u64, flags: u64, mode: u64) -> SyscallResult {
+    let path = user::read_str(path, PATH_MAX)?;
+
+    let mut open_flags = match flags & O_ACCMODE {
+        O_RDONLY => OpenFlags::READ,
+        O_WRONLY => OpenFlags::WRITE,
+        O_RDWR => OpenFlags::READ | OpenFlags::WRITE,
+        _ => return Err(Errno::EINVAL),
+    };
+    for (flag, open_flag) in [
+        (O_CREAT, OpenFlags::CREATE),
+        (O_EXCL, OpenFlags::EXCLUSIVE),
+        (O_TRUNC, OpenFlags::TRUNCATE),
+        (O_APPEND, OpenFlags::APPEND),
+        (O_DIRECTORY, OpenFlags::DIRECTORY),
+    ] {
+        if flags & flag != 0 {
+            open_flags |= open_flag;
+        }
+    }
+    trace!("[open] {} ({:?})", path, open_flags);
+
+    let file = fs::open(&path, open_flags, (mode & 0o7777) as u16)?;
+    let fd = process::current_files().lock().insert(file)?;
+    Ok(fd as u64)
+}
+
+pub(super) fn close(fd: u64) -> SyscallResult {
+    let file = process::current_files().lock().remove(fd as usize)?;
+    // closed here (if this was the last reference), with the table unlocked
+    drop(file);
+    Ok(0)
+}
+
+pub(super) fn stat(path: u64, statbuf: u64) -> SyscallResult {
+    let path = user::read_str(path, PATH_MAX)?;
+    let metadata = fs::stat(&path)?;
+    user::write(statbuf, Stat::from(metadata))?;
+    Ok(0)
+}
+
+pub(super) fn fstat(fd: u64, statbuf: u64) -> SyscallResult {
+    let metadata = get_file(fd)?.stat()?;
+    user::write(statbuf, Stat::from(metadata))?;
+    Ok(0)
+}
+
+pub(super) fn lseek(fd: u64, offset: u64, whence: u64) -> SyscallResult {
+    let file = get_file(fd)?;
+    let pos = match whence {
+        SEEK_SET => SeekFrom::Start(offset),
+        SEEK_CUR => SeekFrom::Current(offset as i64),
+        SEEK_END => SeekFrom::End(offset as i64),
+        _ => return Err(Errno::EINVAL),
+    };
+    Ok(file.seek(pos)?)
+}
+
+pub(super) fn ioctl(fd: u64, cmd: u64, arg: u64) -> SyscallResult {
+    Ok(get_file(fd)?.ioctl(cmd as u32, arg)?)
+}
+
+pub(super) fn getdents64(fd: u64, dirp: u64, count: u64) -> SyscallResult {
+    let file = get_file(fd)?;
+    let buf = user::slice_mut(dirp, count as usize)?;
+    // `d_off` is the position of the next entry
+    let mut pos = file.seek(SeekFrom::Current(0)).unwrap_or(0);
+
+    let header_len = core::mem::size_of::<Dirent64Header>();
+    let mut written = 0;
+    let mut too_small = false;
+    file.readdir(&mut |entry| {
+        // the records are 8 byte aligned
+        let reclen = (header_len + entry.name.len() + 1).next_multiple_of(8);
+        if written + reclen > buf.len() {
+            too_small = true;
+            return false;
+        }
+        pos += 1;
+
+        let record = &mut buf[written..written + reclen];
+        let header = Dirent64Header {
+            d_ino: entry.ino,
+            d_off: pos as i64,
+            d_reclen: reclen as u16,
+            d_type: match entry.kind {
+                FileType::Regular => DT_REG,
+                FileType::Directory => DT_DIR,
+                FileType::CharDevice => DT_CHR,
+            },
+        };
+        // SAFETY: the record is big enough for the header, which has no alignment requirements
+        unsafe { core::ptr::write_unaligned(record.as_mut_ptr() as *mut Dirent64Header, header) };
+        let name = &mut record[header_len..];
+        name[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+        // null terminated, padded with zeroes
+        name[entry.name.len()..].fill(0);
+
+        written += reclen;
+        too_small = false;
+        true
+    })?;
+
+    // not even one entry fits in the buffer
+    if written == 0 && too_small {
+        return Err(Errno::EINVAL);
+    }
+    Ok(written as u64)
+}
+
+pub(super) fn mkdir(path: u64, mode: u64) -> SyscallResult {
+    let path = user::read_str(path, PATH_MAX)?;
+    fs::mkdir(&path, (mode & 0o7777) as u16)?;
+    Ok(0)
+}
+
+pub(super) fn rmdir(path: u64) -> SyscallResult {
+    let path = user::read_str(path, PATH_MAX)?;
+    fs::rmdir(&path)?;
+    Ok(0)
+}
+
+pub(super) fn unlink(path: u64) -> SyscallResult {
+    let path = user::read_str(path, PATH_MAX)?;
+    fs::unlink(&path)?;
+    Ok(0)
+}
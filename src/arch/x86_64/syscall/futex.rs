@@ -0,0 +1,156 @@
+use super::{
+    errno::{Errno, SyscallResult},
+    user, Timespec,
+};
+use crate::{
+    arch::WaitQueue,
+    locks::SpinLockIrq,
+    mem::{PhysicalAddress, VirtualAddress},
+};
+use alloc::{collections::BTreeMap, sync::Arc};
+use log::trace;
+
+const FUTEX_WAIT: u64 = 0;
+const FUTEX_WAKE: u64 = 1;
+// all the futexes are keyed by the physical address, so the private flag makes no difference
+const FUTEX_PRIVATE_FLAG: u64 = 128;
+
+// futexes are keyed on the physical address backing the user virtual address
+// so that the tasks sharing memory using different virtual addresses still see the same futex
+// wait queues are created lazily and removed when no one uses them anymore
+// the waits with a timeout are ended from the timer interrupt, hence the irq safe lock
+static FUTEXES: SpinLockIrq<BTreeMap<PhysicalAddress, Arc<WaitQueue>>> =
+    SpinLockIrq::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FutexOp {
+    Wait,
+    Wake,
+}
+
+impl FutexOp {
+    fn decode(op: u64) -> Result<Self, Errno> {
+        match op & !FUTEX_PRIVATE_FLAG {
+            FUTEX_WAIT => Ok(Self::Wait),
+            FUTEX_WAKE => Ok(Self::Wake),
+            _ => Err(Errno::ENOSYS),
+        }
+    }
+}
+
+pub(super) fn futex(uaddr: u64, op: u64, val: u64, timeout: u64) -> SyscallResult {
+    // must be aligned to 4 bytes
+    if uaddr % 4 != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let op = FutexOp::decode(op)?;
+    let (key, _) = user::translate(VirtualAddress::new(uaddr))?;
+    trace!("[futex] addr: {:#x}, key: {:#x?}, op: {:?}", uaddr, key, op);
+
+    match op {
+        FutexOp::Wait => {
+            let timeout = if timeout == 0 {
+                None
+            } else {
+                Some(
+                    user::read::<Timespec>(timeout)?
+                        .to_ns()
+                        .ok_or(Errno::EINVAL)?,
+                )
+            };
+            futex_wait(uaddr, key, val as u32, timeout)
+        }
+        FutexOp::Wake => futex_wake(key, val as usize),
+    }
+}
+
+fn futex_wait(uaddr: u64, key: PhysicalAddress, val: u32, timeout: Option<u64>) -> SyscallResult {
+    let queue = FUTEXES
+        .lock()
+        .entry(key)
+        .or_insert_with(|| Arc::new(WaitQueue::new()))
+        .clone();
+
+    // the value is checked with the scheduler locked
+    // so that a wake up following a change of the value can't get lost
+    let mut value_matched = false;
+    let condition = || {
+        // SAFETY: the address was translated successfully using the current page table
+        let cur = unsafe { core::ptr::read_volatile(uaddr as *const u32) };
+        value_matched = cur == val;
+        value_matched
+    };
+    let woken_up = match timeout {
+        Some(timeout) => queue.wait_timeout_while(timeout, condition),
+        None => {
+            queue.wait_while(condition);
+            true
+        }
+    };
+
+    release_queue(key, queue);
+
+    if !value_matched {
+        Err(Errno::EAGAIN)
+    } else if !woken_up {
+        Err(Errno::ETIMEDOUT)
+    } else {
+        Ok(0)
+    }
+}
+
+// returns the number of tasks woken up
+pub(super) fn futex_wake(key: PhysicalAddress, count: usize) -> SyscallResult {
+    let Some(queue) = FUTEXES.lock().get(&key).cloned() else {
+        return Ok(0);
+    };
+
+    let mut woken_up = 0;
+    while woken_up < count && queue.wake_one() {
+        woken_up += 1;
+    }
+
+    release_queue(key, queue);
+
+    Ok(woken_up as u64)
+}
+
+// drop the wait queue from the map if no one is waiting on it or about to
+fn release_queue(key: PhysicalAddress, queue: Arc<WaitQueue>) {
+    let mut futexes = FUTEXES.lock();
+    drop(queue);
+    if let Some(queue) = futexes.get(&key) {
+        if Arc::strong_count(queue) == 1 && queue.is_empty() {
+            futexes.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_flag_is_ignored() {
+        assert_eq!(FutexOp::decode(FUTEX_WAIT), Ok(FutexOp::Wait));
+        assert_eq!(
+            FutexOp::decode(FUTEX_WAIT | FUTEX_PRIVATE_FLAG),
+            Ok(FutexOp::Wait)
+        );
+        assert_eq!(FutexOp::decode(FUTEX_WAKE), Ok(FutexOp::Wake));
+        assert_eq!(
+            FutexOp::decode(FUTEX_WAKE | FUTEX_PRIVATE_FLAG),
+            Ok(FutexOp::Wake)
+        );
+    }
+
+    #[test]
+    fn unknown_ops_are_not_supported() {
+        // FUTEX_FD, FUTEX_REQUEUE
+        assert_eq!(FutexOp::decode(2), Err(Errno::ENOSYS));
+        assert_eq!(FutexOp::decode(3), Err(Errno::ENOSYS));
+        assert_eq!(FutexOp::decode(3 | FUTEX_PRIVATE_FLAG), Err(Errno::ENOSYS));
+        // FUTEX_CLOCK_REALTIME is not the private flag
+        assert_eq!(FutexOp::decode(FUTEX_WAIT | 256), Err(Errno::ENOSYS));
+    }
+}
@@ -1,6 +1,200 @@
-use log::info;
+mod errno;
+mod fs;
+mod futex;
+mod thread;
+mod time;
+mod user;
 
-use crate::arch::x86_64::{rdmsr, wrmsr};
+use errno::{Errno, SyscallResult};
+use log::{info, trace};
+
+use crate::{
+    arch::x86_64::{process, rdmsr, wrmsr},
+    fs::{FsError, FsResult},
+};
+
+// syscall numbers
+// same as Linux x86_64, arguments are passed in rdi, rsi, rdx, r10, r8, r9
+// the syscall number is passed in rax and the return value is stored there too
+// errors are returned as negated `Errno` values
+const SYS_READ: u64 = 0;
+const SYS_WRITE: u64 = 1;
+const SYS_OPEN: u64 = 2;
+const SYS_CLOSE: u64 = 3;
+const SYS_STAT: u64 = 4;
+const SYS_FSTAT: u64 = 5;
+const SYS_LSEEK: u64 = 8;
+const SYS_IOCTL: u64 = 16;
+const SYS_NANOSLEEP: u64 = 35;
+const SYS_GETPID: u64 = 39;
+const SYS_CLONE: u64 = 56;
+const SYS_EXIT: u64 = 60;
+const SYS_MKDIR: u64 = 83;
+const SYS_RMDIR: u64 = 84;
+const SYS_UNLINK: u64 = 87;
+const SYS_ARCH_PRCTL: u64 = 158;
+const SYS_GETTID: u64 = 186;
+const SYS_FUTEX: u64 = 202;
+const SYS_GETDENTS64: u64 = 217;
+const SYS_SET_TID_ADDRESS: u64 = 218;
+const SYS_CLOCK_GETTIME: u64 = 228;
+
+// registers of the task that invoked the syscall
+// saved on the kernel stack by `syscall_int_handler`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(super) struct SyscallFrame {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rbp: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    rbx: u64,
+    rax: u64,
+    // pushed by the CPU on interrupt
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+impl Timespec {
+    fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / 1_000_000_000) as i64,
+            tv_nsec: (ns % 1_000_000_000) as i64,
+        }
+    }
+
+    fn to_ns(self) -> Option<u64> {
+        if self.tv_sec < 0 || !(0..1_000_000_000).contains(&self.tv_nsec) {
+            return None;
+        }
+        (self.tv_sec as u64)
+            .checked_mul(1_000_000_000)?
+            .checked_add(self.tv_nsec as u64)
+    }
+}
+
+// entry point for `int 0x2e`
+// saves all the general purpose registers, so that the syscall arguments (and the return value) can be accessed as `SyscallFrame`
+#[naked]
+pub(super) extern "C" fn syscall_int_handler() -> ! {
+    unsafe {
+        core::arch::asm!(
+            // from the userspace, switch to the kernel GS (per CPU data)
+            "test byte ptr [rsp + 8], 3",
+            "jz 2f",
+            "swapgs",
+            "2:",
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+
+            "mov rdi, rsp",
+            "call {}",
+
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rbp",
+            "pop rdi",
+            "pop rsi",
+            "pop rdx",
+            "pop rcx",
+            "pop rbx",
+            "pop rax",
+
+            // back to the userspace, switch back to its GS
+            "test byte ptr [rsp + 8], 3",
+            "jz 3f",
+            "swapgs",
+            "3:",
+            "iretq",
+            sym syscall_dispatch,
+            options(noreturn),
+        );
+    }
+}
+
+extern "C" fn syscall_dispatch(frame: &mut SyscallFrame) {
+    let ret: SyscallResult = match frame.rax {
+        SYS_READ => fs::read(frame.rdi, frame.rsi, frame.rdx),
+        SYS_WRITE => fs::write(frame.rdi, frame.rsi, frame.rdx),
+        SYS_OPEN => fs::open(frame.rdi, frame.rsi, frame.rdx),
+        SYS_CLOSE => fs::close(frame.rdi),
+        SYS_STAT => fs::stat(frame.rdi, frame.rsi),
+        SYS_FSTAT => fs::fstat(frame.rdi, frame.rsi),
+        SYS_LSEEK => fs::lseek(frame.rdi, frame.rsi, frame.rdx),
+        SYS_IOCTL => fs::ioctl(frame.rdi, frame.rsi, frame.rdx),
+        SYS_NANOSLEEP => time::nanosleep(frame.rdi, frame.rsi),
+        SYS_GETPID => thread::getpid(),
+        SYS_CLONE => thread::clone(frame),
+        SYS_EXIT => thread::exit(frame.rdi),
+        SYS_MKDIR => fs::mkdir(frame.rdi, frame.rsi),
+        SYS_RMDIR => fs::rmdir(frame.rdi),
+        SYS_UNLINK => fs::unlink(frame.rdi),
+        SYS_ARCH_PRCTL => thread::arch_prctl(frame.rdi, frame.rsi),
+        SYS_GETTID => thread::gettid(),
+        SYS_FUTEX => futex::futex(frame.rdi, frame.rsi, frame.rdx, frame.r10),
+        SYS_GETDENTS64 => fs::getdents64(frame.rdi, frame.rsi, frame.rdx),
+        SYS_SET_TID_ADDRESS => thread::set_tid_address(frame.rdi),
+        SYS_CLOCK_GETTIME => time::clock_gettime(frame.rdi, frame.rsi),
+        n => {
+            trace!("unsupported syscall: {:#x} (r11: {:#x})", n, frame.r11);
+            Err(Errno::ENOSYS)
+        }
+    };
+
+    frame.rax = match ret {
+        Ok(val) => val,
+        Err(errno) => (-(errno as i64)) as u64,
+    };
+
+    process::exit_if_interrupted();
+}
+
+// for the drivers handed pointers by the userspace (ioctl)
+pub(super) fn copy_from_user<T: Copy>(addr: u64) -> FsResult<T> {
+    user::read(addr).map_err(|_| FsError::BadAddress)
+}
+
+pub(super) fn copy_to_user<T: Copy>(addr: u64, value: T) -> FsResult<()> {
+    user::write(addr, value).map_err(|_| FsError::BadAddress)
+}
 
 #[naked]
 unsafe extern "C" fn syscall_handler() {
@@ -65,3 +259,30 @@ pub(super) fn init() {
 
     info!("Syscall mechanism initialised");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timespec_to_ns() {
+        let ts = |tv_sec, tv_nsec| Timespec { tv_sec, tv_nsec };
+        assert_eq!(ts(0, 0).to_ns(), Some(0));
+        assert_eq!(ts(2, 500).to_ns(), Some(2_000_000_500));
+        assert_eq!(ts(0, 999_999_999).to_ns(), Some(999_999_999));
+        assert_eq!(
+            Timespec::from_ns(2_000_000_500).to_ns(),
+            Some(2_000_000_500)
+        );
+    }
+
+    #[test]
+    fn invalid_timespecs_are_rejected() {
+        let ts = |tv_sec, tv_nsec| Timespec { tv_sec, tv_nsec };
+        assert_eq!(ts(-1, 0).to_ns(), None);
+        assert_eq!(ts(0, -1).to_ns(), None);
+        assert_eq!(ts(0, 1_000_000_000).to_ns(), None);
+        // doesn't fit in 64 bits of nanoseconds
+        assert_eq!(ts(i64::MAX, 0).to_ns(), None);
+    }
+}
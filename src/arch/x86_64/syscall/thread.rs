@@ -0,0 +1,187 @@
+use super::{
+    errno::{Errno, SyscallResult},
+    futex,
+    user::{self, Access},
+    SyscallFrame,
+};
+use crate::{arch::x86_64::process, mem::VirtualAddress};
+use log::trace;
+
+// clone flags (same as Linux)
+const CLONE_VM: u64 = 0x0000_0100;
+const CLONE_FS: u64 = 0x0000_0200;
+const CLONE_FILES: u64 = 0x0000_0400;
+const CLONE_SIGHAND: u64 = 0x0000_0800;
+const CLONE_THREAD: u64 = 0x0001_0000;
+const CLONE_SYSVSEM: u64 = 0x0004_0000;
+const CLONE_SETTLS: u64 = 0x0008_0000;
+const CLONE_PARENT_SETTID: u64 = 0x0010_0000;
+const CLONE_CHILD_CLEARTID: u64 = 0x0020_0000;
+const CLONE_CHILD_SETTID: u64 = 0x0100_0000;
+// the lower byte holds the signal sent to the parent on exit
+const CSIGNAL: u64 = 0xff;
+
+// the flags that make no difference for us as there is no other choice yet
+const CLONE_IGNORED: u64 = CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_SYSVSEM | CSIGNAL;
+
+// arch_prctl codes
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+
+// first thing run by the new thread, see `create_user_thread`
+// the stack holds the copy of the parent's `SyscallFrame`
+// so the child returns to the userspace like the parent does, just with a different rax and rsp
+#[naked]
+extern "C" fn return_from_clone() -> ! {
+    unsafe {
+        core::arch::asm!(
+            // SAFETY: the only way to get here is when `task_switch` transfers control to this new thread
+            // `SCHEDULER_LOCK` is locked before `task_switch` is run and is not unlocked before next statement
+            "call scheduler_unlock",
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rbp",
+            "pop rdi",
+            "pop rsi",
+            "pop rdx",
+            "pop rcx",
+            "pop rbx",
+            "pop rax",
+            // the userspace GS, with the interrupts off till `iretq` (they'd see the kernel running with it)
+            "cli",
+            "swapgs",
+            "iretq",
+            options(noreturn),
+        );
+    }
+}
+
+// only threads (tasks sharing the address space) are supported for now
+// clone(flags, stack, parent_tid, child_tid, tls)
+pub(super) fn clone(frame: &SyscallFrame) -> SyscallResult {
+    let flags = frame.rdi;
+    let stack = frame.rsi;
+    let parent_tid = frame.rdx;
+    let child_tid = frame.r10;
+    let tls = frame.r8;
+    trace!("[clone] flags: {:#x}, stack: {:#x}", flags, stack);
+
+    if flags & CLONE_THREAD != 0 && flags & CLONE_VM == 0 {
+        return Err(Errno::EINVAL);
+    }
+    // no fork (yet)
+    if flags & (CLONE_VM | CLONE_THREAD) != CLONE_VM | CLONE_THREAD {
+        return Err(Errno::ENOSYS);
+    }
+    let supported = CLONE_VM
+        | CLONE_THREAD
+        | CLONE_SETTLS
+        | CLONE_PARENT_SETTID
+        | CLONE_CHILD_SETTID
+        | CLONE_CHILD_CLEARTID
+        | CLONE_IGNORED;
+    if flags & !supported != 0 {
+        return Err(Errno::EINVAL);
+    }
+    // sharing the stack with the parent never ends well
+    if stack == 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    // validate the user pointers before the thread is created
+    // so that a failure doesn't leave a half initialised thread behind
+    if flags & CLONE_PARENT_SETTID != 0 {
+        user::check_range(parent_tid, core::mem::size_of::<u32>(), Access::Write)?;
+    }
+    if flags & (CLONE_CHILD_SETTID | CLONE_CHILD_CLEARTID) != 0 {
+        user::check_range(child_tid, core::mem::size_of::<u32>(), Access::Write)?;
+    }
+
+    let mut child_frame = *frame;
+    // child sees 0 as the return value of clone
+    child_frame.rax = 0;
+    child_frame.rsp = stack;
+
+    let fs_base = (flags & CLONE_SETTLS != 0).then_some(tls);
+    let clear_child_tid =
+        (flags & CLONE_CHILD_CLEARTID != 0).then(|| VirtualAddress::new(child_tid));
+
+    let tid = process::spawn_user_thread(
+        return_from_clone,
+        child_frame,
+        VirtualAddress::new(stack),
+        fs_base,
+        clear_child_tid,
+    );
+
+    // the threads share the address space, so the child's tid can be written from here
+    if flags & CLONE_CHILD_SETTID != 0 {
+        user::write(child_tid, tid)?;
+    }
+    if flags & CLONE_PARENT_SETTID != 0 {
+        user::write(parent_tid, tid)?;
+    }
+
+    Ok(tid as u64)
+}
+
+// terminates the calling thread
+// if asked for (CLONE_CHILD_CLEARTID / set_tid_address), the tid is cleared and a waiter is woken up
+// which is how the pthread_join like functions learn about the exit
+pub(super) fn exit(code: u64) -> ! {
+    trace!(
+        "[exit] thread {} exiting with code {}",
+        process::current_tid(),
+        code
+    );
+
+    if let Some(addr) = process::take_clear_child_tid() {
+        let addr = addr.to_inner();
+        // nothing to do if the userspace gave us garbage, the thread is going away anyway
+        if user::write(addr, 0u32).is_ok() {
+            if let Ok((key, _)) = user::translate(VirtualAddress::new(addr)) {
+                let _ = futex::futex_wake(key, 1);
+            }
+        }
+    }
+
+    process::exit_thread()
+}
+
+pub(super) fn set_tid_address(addr: u64) -> SyscallResult {
+    let addr = (addr != 0).then(|| VirtualAddress::new(addr));
+    process::set_clear_child_tid(addr);
+    Ok(process::current_tid() as u64)
+}
+
+pub(super) fn arch_prctl(code: u64, addr: u64) -> SyscallResult {
+    match code {
+        ARCH_SET_FS => {
+            // the base has to be a canonical userspace address
+            if addr >= user::USER_SPACE_END {
+                return Err(Errno::EPERM);
+            }
+            process::set_fs_base(addr);
+            Ok(0)
+        }
+        ARCH_GET_FS => {
+            user::write(addr, process::fs_base())?;
+            Ok(0)
+        }
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+pub(super) fn gettid() -> SyscallResult {
+    Ok(process::current_tid() as u64)
+}
+
+pub(super) fn getpid() -> SyscallResult {
+    Ok(process::current_pid() as u64)
+}
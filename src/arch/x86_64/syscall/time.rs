@@ -0,0 +1,34 @@
+use super::{
+    errno::{Errno, SyscallResult},
+    user, Timespec,
+};
+use crate::arch::x86_64::{process, timers};
+use log::trace;
+
+// clock ids (same as Linux)
+const CLOCK_REALTIME: u64 = 0;
+const CLOCK_MONOTONIC: u64 = 1;
+
+pub(super) fn clock_gettime(clock_id: u64, tp: u64) -> SyscallResult {
+    let now = match clock_id {
+        CLOCK_REALTIME => timers::realtime_ns(),
+        CLOCK_MONOTONIC => timers::monotonic_ns(),
+        _ => return Err(Errno::EINVAL),
+    };
+    user::write(tp, Timespec::from_ns(now))?;
+    Ok(0)
+}
+
+// there are no signals yet, so the sleep is never interrupted
+// and the remaining time (`rem`) is never written
+pub(super) fn nanosleep(req: u64, _rem: u64) -> SyscallResult {
+    let duration = user::read::<Timespec>(req)?.to_ns().ok_or(Errno::EINVAL)?;
+    trace!(
+        "[nanosleep] thread {} for {} ns",
+        process::current_tid(),
+        duration
+    );
+
+    process::delay(duration);
+    Ok(0)
+}
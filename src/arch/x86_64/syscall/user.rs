@@ -0,0 +1,120 @@
+use super::errno::Errno;
+use crate::{
+    arch::{get_cur_page_table_start, EntryFlags, P4Table},
+    mem::{PhysicalAddress, VirtualAddress, PAGE_SIZE},
+};
+use alloc::string::String;
+
+// lower half of the address space belongs to the userspace
+pub(super) const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+// what a syscall does with a userspace buffer, the pages have to allow it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    fn allowed(self, flags: EntryFlags) -> bool {
+        match self {
+            Self::Read => true,
+            Self::Write => flags.contains(EntryFlags::WRITABLE),
+        }
+    }
+}
+
+// translates a userspace address using the page table of the current task,
+// along with the flags of the page it's in
+// syscalls run with the page table of the task that invoked them
+// the kernel isn't kept from writing to the read only pages (CR0.WP is clear), nor from
+// the pages only it can access, so a page the userspace can't access is an error
+pub(super) fn translate(addr: VirtualAddress) -> Result<(PhysicalAddress, EntryFlags), Errno> {
+    if addr.to_inner() >= USER_SPACE_END {
+        return Err(Errno::EFAULT);
+    }
+
+    // SAFETY: cr3 always points to a valid page table
+    // `forget` ensures that the page table of the task is not freed when we are done
+    let mut table = unsafe { P4Table::from_addr(get_cur_page_table_start()) };
+    let translated = table.translate_with_flags(addr);
+    table.forget();
+
+    translated
+        .filter(|(_, flags)| flags.contains(EntryFlags::USER_ACCESSIBLE))
+        .ok_or(Errno::EFAULT)
+}
+
+// checks that every page in [addr, addr + len) is mapped in the current task's page table
+// and that the userspace can access it the way it's going to be
+pub(super) fn check_range(addr: u64, len: usize, access: Access) -> Result<(), Errno> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = addr.checked_add(len as u64 - 1).ok_or(Errno::EFAULT)?;
+    if end >= USER_SPACE_END {
+        return Err(Errno::EFAULT);
+    }
+
+    let mut page = addr & !(PAGE_SIZE - 1);
+    while page <= end {
+        let (_, flags) = translate(VirtualAddress::new(page))?;
+        if !access.allowed(flags) {
+            return Err(Errno::EFAULT);
+        }
+        page += PAGE_SIZE;
+    }
+    Ok(())
+}
+
+pub(super) fn read<T: Copy>(addr: u64) -> Result<T, Errno> {
+    check_range(addr, core::mem::size_of::<T>(), Access::Read)?;
+    // SAFETY: the range is mapped in the current page table
+    Ok(unsafe { core::ptr::read_unaligned(addr as *const T) })
+}
+
+pub(super) fn write<T: Copy>(addr: u64, value: T) -> Result<(), Errno> {
+    check_range(addr, core::mem::size_of::<T>(), Access::Write)?;
+    // SAFETY: the range is mapped in the current page table
+    unsafe { core::ptr::write_unaligned(addr as *mut T, value) };
+    Ok(())
+}
+
+// the buffer is only valid while the current page table is in use (till the syscall returns)
+pub(super) fn slice<'a>(addr: u64, len: usize) -> Result<&'a [u8], Errno> {
+    check_range(addr, len, Access::Read)?;
+    if len == 0 {
+        return Ok(&[]);
+    }
+    // SAFETY: the range is mapped in the current page table
+    Ok(unsafe { core::slice::from_raw_parts(addr as *const u8, len) })
+}
+
+pub(super) fn slice_mut<'a>(addr: u64, len: usize) -> Result<&'a mut [u8], Errno> {
+    check_range(addr, len, Access::Write)?;
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    // SAFETY: the range is mapped in the current page table
+    Ok(unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) })
+}
+
+// copies a null terminated UTF-8 string of at most `max_len` bytes (terminator included)
+pub(super) fn read_str(addr: u64, max_len: usize) -> Result<String, Errno> {
+    let mut bytes = alloc::vec::Vec::new();
+    // the pages are checked one at a time, as the end of the string is not known in advance
+    let mut checked_till = addr;
+    for cur in addr..addr.saturating_add(max_len as u64) {
+        if cur >= checked_till {
+            translate(VirtualAddress::new(cur))?;
+            checked_till = (cur & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+        }
+        // SAFETY: the page is mapped in the current page table
+        let byte = unsafe { core::ptr::read(cur as *const u8) };
+        if byte == 0 {
+            return String::from_utf8(bytes).map_err(|_| Errno::EINVAL);
+        }
+        bytes.push(byte);
+    }
+    Err(Errno::ENAMETOOLONG)
+}
@@ -0,0 +1,154 @@
+use super::pit;
+use crate::{
+    arch::{disable_interrupts, enable_interrupts, is_int_enabled},
+    locks::SpinLock,
+};
+use alloc::vec::Vec;
+use log::info;
+use spin::RwLock;
+
+// a counter that can tell the time since boot
+pub(in super::super) trait ClockSource: Sync {
+    fn name(&self) -> &'static str;
+
+    // how good the clock is (resolution, cost of reading, stability)
+    // the registered clock with the highest rating is used
+    fn rating(&self) -> u32;
+
+    // ns since an arbitrary point in the past, never goes backwards
+    fn read_ns(&self) -> u64;
+
+    // the clock only moves forward with its periodic interrupt
+    // so it can't be used to measure time while the interrupts are disabled
+    fn needs_tick(&self) -> bool {
+        false
+    }
+
+    // called when the clock is selected / replaced by a better one
+    fn enable(&self) {}
+    fn disable(&self) {}
+}
+
+struct Selected {
+    clock: &'static dyn ClockSource,
+    // added to the readings of the clock, so that the time doesn't jump when switching clocks
+    offset: i64,
+}
+
+static CLOCKSOURCES: SpinLock<Vec<&'static dyn ClockSource>> = SpinLock::new(Vec::new());
+static SELECTED: RwLock<Option<Selected>> = RwLock::new(None);
+
+pub(super) fn register(clock: &'static dyn ClockSource) {
+    info!(
+        "registering clocksource {} (rating {})",
+        clock.name(),
+        clock.rating()
+    );
+    CLOCKSOURCES.lock().push(clock);
+    select_best();
+}
+
+// switches to the best clock registered so far
+fn select_best() {
+    let Some(best) = CLOCKSOURCES
+        .lock()
+        .iter()
+        .copied()
+        .max_by_key(|clock| clock.rating())
+    else {
+        return;
+    };
+
+    // the clock is read from the interrupt handlers, don't let them spin on the lock
+    let int_enabled = is_int_enabled();
+    disable_interrupts();
+
+    let mut selected = SELECTED.write();
+    let switch = !selected
+        .as_ref()
+        .is_some_and(|cur| core::ptr::addr_eq(cur.clock, best));
+    if switch {
+        // the first clock starts wherever it is, the later ones carry on from the previous one
+        let now = selected
+            .as_ref()
+            .map(|cur| cur.clock.read_ns() as i64 + cur.offset);
+        if let Some(cur) = selected.as_ref() {
+            cur.clock.disable();
+        }
+        best.enable();
+        *selected = Some(Selected {
+            clock: best,
+            offset: now.map_or(0, |now| now - best.read_ns() as i64),
+        });
+    }
+
+    drop(selected);
+    if int_enabled {
+        enable_interrupts();
+    }
+
+    // not under the lock, logging reads the clock
+    if switch {
+        info!("using clocksource {}", best.name());
+    }
+}
+
+// ns since boot (roughly), 0 till a clock is registered
+pub(super) fn read_ns() -> u64 {
+    SELECTED.read().as_ref().map_or(0, |cur| {
+        (cur.clock.read_ns() as i64 + cur.offset).max(0) as u64
+    })
+}
+
+// readings taken at the start and the end of a calibration interval
+pub(in super::super) struct Calibration<T> {
+    pub(in super::super) start: T,
+    pub(in super::super) end: T,
+    // time since boot at the start of the interval
+    pub(in super::super) start_ns: u64,
+    // how long the interval actually was
+    pub(in super::super) elapsed_ns: u64,
+}
+
+// waits for (at least) `duration_ns` with the interrupts disabled
+// `read` samples whatever is being calibrated at both ends of the interval
+pub(in super::super) fn calibrate<T>(
+    duration_ns: u64,
+    mut read: impl FnMut() -> T,
+) -> Calibration<T> {
+    let clock = SELECTED
+        .read()
+        .as_ref()
+        .filter(|cur| !cur.clock.needs_tick())
+        .map(|cur| (cur.clock, cur.offset));
+
+    match clock {
+        Some((clock, offset)) => {
+            let start_ns = clock.read_ns();
+            let start = read();
+            while clock.read_ns() - start_ns < duration_ns {
+                core::hint::spin_loop();
+            }
+            let end = read();
+            Calibration {
+                start,
+                end,
+                start_ns: (start_ns as i64 + offset).max(0) as u64,
+                elapsed_ns: clock.read_ns() - start_ns,
+            }
+        }
+        // no free running clock, count down using the PIT instead
+        None => {
+            let start_ns = read_ns();
+            let start = read();
+            pit::busy_wait(duration_ns);
+            let end = read();
+            Calibration {
+                start,
+                end,
+                start_ns,
+                elapsed_ns: duration_ns,
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use alloc::vec::Vec;
 use log::{info, trace};
 
+use super::clocksource::ClockSource;
 use crate::{
     arch::x86_64::{acpi::HpetEntry, paging::mmio},
     mem::{PhysicalAddress, VirtualAddress},
@@ -93,7 +94,7 @@ impl Hpet {
     }
 
     #[inline]
-    fn counter_to_ns(&self, counter: u64) -> u64 {
+    pub(super) fn counter_to_ns(&self, counter: u64) -> u64 {
         (counter * 10u64.pow(9)) / self.frequency()
     }
 
@@ -181,3 +182,17 @@ impl Hpet {
         self.counter_to_ns(counter)
     }
 }
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn rating(&self) -> u32 {
+        250
+    }
+
+    fn read_ns(&self) -> u64 {
+        self.time_since_boot_in_ns()
+    }
+}
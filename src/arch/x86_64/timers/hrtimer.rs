@@ -0,0 +1,43 @@
+use super::timer::TimerEntry;
+use alloc::{collections::BTreeMap, vec::Vec};
+
+// high resolution timers, ordered by their expiry (in ns)
+// the id breaks the ties between the timers expiring at the same time
+#[derive(Debug)]
+pub(super) struct HrTimerQueue {
+    timers: BTreeMap<(u64, u64), TimerEntry>,
+}
+
+impl HrTimerQueue {
+    pub(super) const fn new() -> Self {
+        Self {
+            timers: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn add(&mut self, entry: TimerEntry) {
+        self.timers.insert((entry.expires, entry.id), entry);
+    }
+
+    pub(super) fn remove(&mut self, expires: u64, id: u64) -> Option<TimerEntry> {
+        self.timers.remove(&(expires, id))
+    }
+
+    pub(super) fn next_event(&self) -> Option<u64> {
+        self.timers
+            .first_key_value()
+            .map(|((expires, _), _)| *expires)
+    }
+
+    // removes and returns the timers expiring at or before `now` (in ns)
+    pub(super) fn expire(&mut self, now: u64) -> Vec<TimerEntry> {
+        let mut expired = Vec::new();
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.get().expires > now {
+                break;
+            }
+            expired.push(entry.remove());
+        }
+        expired
+    }
+}
@@ -1,13 +1,48 @@
-use log::info;
+use log::{info, warn};
+use spin::Once;
 
 use crate::arch::x86_64::smp::is_bsp;
 
-use super::acpi;
+use super::{
+    acpi, apic,
+    interrupts::{request_irq, IrqFlags, IrqReturn},
+};
 
+pub(super) mod clocksource;
 pub(crate) mod hpet;
+mod hrtimer;
+mod pit;
+mod rtc;
+mod timer;
+pub(super) mod tsc;
+mod wheel;
 
-pub(super) fn init(rsdt: &acpi::RsdtEntries) -> hpet::Hpet {
-    let hpet = rsdt.find_hpet().unwrap();
+pub(crate) use timer::monotonic_ns;
+pub(super) use timer::{
+    add_hrtimer, add_timer, enable_events, program_next_event, realtime_ns, run_expired,
+    TimerHandle,
+};
+
+static HPET: Once<hpet::Hpet> = Once::new();
+
+pub(super) fn init(rsdt: &acpi::RsdtEntries) {
+    match rsdt.find_hpet() {
+        Some(hpet) => init_hpet(hpet),
+        // the PIT is silenced by the HPET legacy replacement mode, so it's only used without one
+        None => {
+            info!("no HPET found, falling back to the PIT");
+            clocksource::register(&pit::PIT_CLOCK);
+        }
+    }
+
+    if tsc::calibrate() {
+        clocksource::register(&tsc::Tsc);
+    }
+
+    init_realtime(rsdt);
+}
+
+fn init_hpet(hpet: acpi::AcpiSdt) {
     let acpi::AcpiSdtType::Hpet(hpet) = hpet.fields else {
         unreachable!()
     };
@@ -24,5 +59,33 @@ pub(super) fn init(rsdt: &acpi::RsdtEntries) -> hpet::Hpet {
         }
     }
 
-    hpet
+    clocksource::register(HPET.call_once(|| hpet));
+}
+
+// routes IRQ0, once the IOAPICs are set up
+pub(super) fn init_irq() {
+    if let Err(err) = request_irq(
+        apic::isa_gsi(0),
+        legacy_timer_interrupt,
+        0,
+        IrqFlags::empty(),
+    ) {
+        warn!("failed to request the legacy timer IRQ: {:?}", err);
+    }
+}
+
+// IRQ0, raised by the PIT (or the HPET in the legacy replacement mode)
+fn legacy_timer_interrupt(_: usize) -> IrqReturn {
+    pit::PIT_CLOCK.tick();
+    IrqReturn::Handled
+}
+
+// the RTC only counts whole seconds, so the wall clock is read once at boot
+// and kept up to date using the monotonic clock afterwards
+fn init_realtime(rsdt: &acpi::RsdtEntries) {
+    let century_reg = rsdt.find_fadt().map_or(0, |fadt| fadt.century);
+    let rtc = rtc::Rtc::new(century_reg);
+    let now = rtc.read();
+    info!("RTC time: {} UTC", now);
+    timer::set_realtime(now.to_unix_timestamp() * 10u64.pow(9));
 }
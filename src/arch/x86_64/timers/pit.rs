@@ -0,0 +1,197 @@
+use super::clocksource::ClockSource;
+use crate::{arch::x86_64::port::Port, locks::SpinLockIrq};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use lazy_static::lazy_static;
+
+// 8254 Programmable Interval Timer
+// https://wiki.osdev.org/Programmable_Interval_Timer
+
+// the input clock of all the channels
+const PIT_FREQ: u64 = 1_193_182;
+
+const CHANNEL0_PORT: u16 = 0x40;
+const CHANNEL2_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+// keyboard controller port B: gate of channel 2, PC speaker and the output of channel 2
+const PORT_B: u16 = 0x61;
+
+// command register bits
+const SELECT_CHANNEL0: u8 = 0b00 << 6;
+const SELECT_CHANNEL2: u8 = 0b10 << 6;
+const LATCH_COUNT: u8 = 0b00 << 4;
+const ACCESS_LOHI: u8 = 0b11 << 4;
+// interrupt on terminal count
+const MODE_ONESHOT: u8 = 0b000 << 1;
+// rate generator
+const MODE_PERIODIC: u8 = 0b010 << 1;
+
+// port B bits
+const CHANNEL2_GATE: u8 = 1 << 0;
+const SPEAKER_ENABLE: u8 = 1 << 1;
+const CHANNEL2_OUTPUT: u8 = 1 << 5;
+
+// the largest count that can be loaded (0 stands for 65536)
+const MAX_COUNT: u64 = 1 << 16;
+
+// frequency of the periodic tick while the PIT is used as the clocksource
+const TICK_HZ: u64 = 100;
+const TICK_COUNT: u64 = PIT_FREQ / TICK_HZ;
+
+struct Pit {
+    channel0: Port,
+    channel2: Port,
+    command: Port,
+    port_b: Port,
+}
+
+impl Pit {
+    // SAFETY: the count has to fit in 16 bits (0 stands for 65536)
+    unsafe fn load_count(port: &mut Port, count: u64) {
+        port.write(count as u8);
+        port.write((count >> 8) as u8);
+    }
+
+    // counts down from `count` on channel 2 and waits till it reaches 0
+    // channel 2 doesn't raise interrupts, its output is polled instead
+    fn channel2_countdown(&mut self, count: u64) {
+        // SAFETY: the PIT is present on all PC compatible machines
+        unsafe {
+            // gate high (enables counting), speaker off
+            let val = self.port_b.read::<u8>();
+            self.port_b.write((val & !SPEAKER_ENABLE) | CHANNEL2_GATE);
+
+            self.command
+                .write(SELECT_CHANNEL2 | ACCESS_LOHI | MODE_ONESHOT);
+            // counting starts once the count is loaded
+            Self::load_count(&mut self.channel2, count);
+
+            // output goes high when the count reaches 0
+            while self.port_b.read::<u8>() & CHANNEL2_OUTPUT == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    // the current count of channel 0
+    fn read_channel0(&mut self) -> u64 {
+        // SAFETY: the PIT is present on all PC compatible machines
+        unsafe {
+            self.command.write(SELECT_CHANNEL0 | LATCH_COUNT);
+            let low = self.channel0.read::<u8>() as u64;
+            let high = self.channel0.read::<u8>() as u64;
+            (high << 8) | low
+        }
+    }
+
+    fn start_tick(&mut self) {
+        // SAFETY: the PIT is present on all PC compatible machines
+        unsafe {
+            self.command
+                .write(SELECT_CHANNEL0 | ACCESS_LOHI | MODE_PERIODIC);
+            Self::load_count(&mut self.channel0, TICK_COUNT);
+        }
+    }
+
+    // the one-shot mode fires (at most) once and then stays quiet
+    fn stop_tick(&mut self) {
+        // SAFETY: the PIT is present on all PC compatible machines
+        unsafe {
+            self.command
+                .write(SELECT_CHANNEL0 | ACCESS_LOHI | MODE_ONESHOT);
+            Self::load_count(&mut self.channel0, 0);
+        }
+    }
+}
+
+lazy_static! {
+    static ref PIT: SpinLockIrq<Pit> = SpinLockIrq::new(Pit {
+        channel0: Port::new(CHANNEL0_PORT),
+        channel2: Port::new(CHANNEL2_PORT),
+        command: Port::new(COMMAND_PORT),
+        port_b: Port::new(PORT_B),
+    });
+}
+
+fn count_to_ns(count: u64) -> u64 {
+    (count as u128 * 10u128.pow(9) / PIT_FREQ as u128) as u64
+}
+
+fn ns_to_count(ns: u64) -> u64 {
+    (ns as u128 * PIT_FREQ as u128 / 10u128.pow(9)) as u64
+}
+
+// spins for `ns` without relying on the interrupts
+// used to calibrate the other timers when there is no HPET
+pub(super) fn busy_wait(ns: u64) {
+    let mut count = ns_to_count(ns);
+    let mut pit = PIT.lock();
+    while count > 0 {
+        let chunk = count.min(MAX_COUNT);
+        pit.channel2_countdown(chunk % MAX_COUNT);
+        count -= chunk;
+    }
+}
+
+// channel 0 in periodic mode, the time is the number of ticks so far
+// plus how far the counter has got into the current one
+pub(super) struct PitClock {
+    ticks: AtomicU64,
+    running: AtomicBool,
+    // the last value returned, the time must not go backwards
+    last_ns: AtomicU64,
+}
+
+pub(super) static PIT_CLOCK: PitClock = PitClock {
+    ticks: AtomicU64::new(0),
+    running: AtomicBool::new(false),
+    last_ns: AtomicU64::new(0),
+};
+
+impl PitClock {
+    // to be called from the IRQ0 handler
+    pub(super) fn tick(&self) {
+        if self.running.load(Ordering::Relaxed) {
+            self.ticks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ClockSource for PitClock {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn rating(&self) -> u32 {
+        110
+    }
+
+    fn read_ns(&self) -> u64 {
+        // the lock disables the interrupts, so the tick count can't change while the counter is read
+        let (ticks, count) = {
+            let mut pit = PIT.lock();
+            (self.ticks.load(Ordering::Relaxed), pit.read_channel0())
+        };
+        let elapsed = TICK_COUNT.saturating_sub(count);
+        let now = count_to_ns(ticks * TICK_COUNT + elapsed);
+
+        // the counter may have wrapped with the tick interrupt still pending
+        // in which case `now` is behind by a tick, stick to the last value instead
+        let last = self.last_ns.fetch_max(now, Ordering::Relaxed);
+        now.max(last)
+    }
+
+    fn needs_tick(&self) -> bool {
+        true
+    }
+
+    // no logging in here, the clocksource lock is held and logging reads the clock
+    fn enable(&self) {
+        self.running.store(true, Ordering::Relaxed);
+        PIT.lock().start_tick();
+    }
+
+    fn disable(&self) {
+        PIT.lock().stop_tick();
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
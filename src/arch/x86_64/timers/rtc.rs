@@ -0,0 +1,183 @@
+use crate::arch::x86_64::port::Port;
+use log::trace;
+
+// the register index is written to the address port and the value is then read from the data port
+const CMOS_ADDRESS_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+// bit 7 of the address port masks the NMIs
+// keep them masked while the RTC is being accessed
+const NMI_DISABLE: u8 = 1 << 7;
+
+const SECONDS_REG: u8 = 0x00;
+const MINUTES_REG: u8 = 0x02;
+const HOURS_REG: u8 = 0x04;
+const DAY_REG: u8 = 0x07;
+const MONTH_REG: u8 = 0x08;
+const YEAR_REG: u8 = 0x09;
+const STATUS_A_REG: u8 = 0x0a;
+const STATUS_B_REG: u8 = 0x0b;
+
+// status A: the RTC is updating its registers, the values read now may be inconsistent
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+// status B: 24 hour format (12 hour format otherwise)
+const HOUR_FORMAT_24: u8 = 1 << 1;
+// status B: the values are binary (BCD otherwise)
+const BINARY_MODE: u8 = 1 << 2;
+// hours register in the 12 hour format
+const HOUR_PM: u8 = 1 << 7;
+
+// a date and time as kept by the RTC (UTC is assumed)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct DateTime {
+    pub(super) year: u32,
+    pub(super) month: u8,
+    pub(super) day: u8,
+    pub(super) hour: u8,
+    pub(super) minute: u8,
+    pub(super) second: u8,
+}
+
+impl DateTime {
+    // seconds since 1970-01-01 00:00:00 UTC
+    pub(super) fn to_unix_timestamp(self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let secs =
+            days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        secs.max(0) as u64
+    }
+}
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// number of days between 1970-01-01 and the given date of the proleptic Gregorian calendar
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // years start in March, so that the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+#[derive(Debug)]
+pub(super) struct Rtc {
+    // index of the century register as given by the FADT, 0 if there is none
+    century_reg: u8,
+}
+
+impl Rtc {
+    pub(super) fn new(century_reg: u8) -> Self {
+        Self { century_reg }
+    }
+
+    fn read_reg(&self, reg: u8) -> u8 {
+        let mut address = Port::new(CMOS_ADDRESS_PORT);
+        let mut data = Port::new(CMOS_DATA_PORT);
+        // SAFETY: the CMOS ports are always present on PC compatible machines
+        unsafe {
+            address.write(NMI_DISABLE | reg);
+            data.read()
+        }
+    }
+
+    fn update_in_progress(&self) -> bool {
+        self.read_reg(STATUS_A_REG) & UPDATE_IN_PROGRESS != 0
+    }
+
+    fn read_raw(&self) -> RawTime {
+        while self.update_in_progress() {
+            core::hint::spin_loop();
+        }
+        RawTime {
+            second: self.read_reg(SECONDS_REG),
+            minute: self.read_reg(MINUTES_REG),
+            hour: self.read_reg(HOURS_REG),
+            day: self.read_reg(DAY_REG),
+            month: self.read_reg(MONTH_REG),
+            year: self.read_reg(YEAR_REG),
+            century: if self.century_reg != 0 {
+                self.read_reg(self.century_reg)
+            } else {
+                0
+            },
+        }
+    }
+
+    // the update can still start while the registers are being read
+    // so read them till two reads in a row agree
+    pub(super) fn read(&self) -> DateTime {
+        let mut last = self.read_raw();
+        loop {
+            let cur = self.read_raw();
+            if cur == last {
+                break;
+            }
+            last = cur;
+        }
+        trace!("[rtc] raw time: {:?}", last);
+
+        let status_b = self.read_reg(STATUS_B_REG);
+        let decode = |val: u8| {
+            if status_b & BINARY_MODE != 0 {
+                val
+            } else {
+                bcd_to_binary(val)
+            }
+        };
+
+        // the PM flag is not part of the BCD value
+        let mut hour = decode(last.hour & !HOUR_PM);
+        if status_b & HOUR_FORMAT_24 == 0 {
+            // 12 AM is midnight and 12 PM is noon
+            hour %= 12;
+            if last.hour & HOUR_PM != 0 {
+                hour += 12;
+            }
+        }
+
+        let year = decode(last.year) as u32;
+        let year = if self.century_reg != 0 {
+            decode(last.century) as u32 * 100 + year
+        } else if year < 70 {
+            // no century register, assume that the date is not before the UNIX epoch
+            2000 + year
+        } else {
+            1900 + year
+        };
+
+        DateTime {
+            year,
+            month: decode(last.month),
+            day: decode(last.day),
+            hour,
+            minute: decode(last.minute),
+            second: decode(last.second),
+        }
+    }
+}
+
+fn bcd_to_binary(val: u8) -> u8 {
+    (val >> 4) * 10 + (val & 0x0f)
+}
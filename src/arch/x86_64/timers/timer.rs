@@ -0,0 +1,329 @@
+use super::{clocksource, hrtimer::HrTimerQueue, wheel::TimerWheel};
+use crate::{arch::x86_64::apic::lapic::get_lapic, locks::SpinLockIrq};
+use alloc::{boxed::Box, sync::Arc};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use lazy_static::lazy_static;
+use log::trace;
+
+// resolution of the timer wheel (1 ms)
+const TICK_NS: u64 = 10u64.pow(6);
+
+// too short and the interrupt fires before `task_switch` completes
+const MIN_EVENT_DELTA_NS: u64 = 1000;
+
+// states of a timer
+const PENDING: u8 = 0;
+const FIRED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+// run in the interrupt context (interrupts disabled), so it must not sleep
+pub(in super::super) type TimerCallback = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimerKind {
+    // timer wheel, expiry rounded up to `TICK_NS`
+    Coarse,
+    // hrtimer tree, ns resolution
+    HighRes,
+}
+
+pub(super) struct TimerEntry {
+    pub(super) id: u64,
+    // in ticks for the timer wheel, in ns for the hrtimers
+    pub(super) expires: u64,
+    state: Arc<AtomicU8>,
+    callback: TimerCallback,
+}
+
+impl core::fmt::Debug for TimerEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TimerEntry")
+            .field("id", &self.id)
+            .field("expires", &self.expires)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl TimerEntry {
+    fn new(id: u64, expires: u64, state: Arc<AtomicU8>, callback: TimerCallback) -> Self {
+        Self {
+            id,
+            expires,
+            state,
+            callback,
+        }
+    }
+
+    // runs the callback, unless the timer was cancelled
+    fn fire(self) {
+        if self
+            .state
+            .compare_exchange(PENDING, FIRED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            trace!("[timers] running timer {}", self.id);
+            (self.callback)();
+        }
+    }
+}
+
+// used to cancel a pending timer
+// dropping the handle doesn't cancel the timer
+#[derive(Debug)]
+pub(in super::super) struct TimerHandle {
+    id: u64,
+    expires: u64,
+    kind: TimerKind,
+    state: Arc<AtomicU8>,
+}
+
+impl TimerHandle {
+    // returns false if the timer has already fired (or was cancelled before)
+    // once this returns true, the callback is guaranteed not to run
+    pub(in super::super) fn cancel(&self) -> bool {
+        let cancelled = self
+            .state
+            .compare_exchange(PENDING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        // the cancelled timers on the wheel are dropped when their slot comes up
+        if cancelled && self.kind == TimerKind::HighRes {
+            TIMERS.lock().hrtimers.remove(self.expires, self.id);
+        }
+        cancelled
+    }
+
+    pub(in super::super) fn is_pending(&self) -> bool {
+        self.state.load(Ordering::Acquire) == PENDING
+    }
+}
+
+#[derive(Debug)]
+struct Timers {
+    wheel: TimerWheel,
+    hrtimers: HrTimerQueue,
+    next_id: u64,
+    // the time (ns since boot) the LAPIC timer is armed for, u64::MAX if it's not armed
+    armed: u64,
+    // the LAPIC can't be programmed before it's initialised
+    events_enabled: bool,
+}
+
+impl Timers {
+    fn next_event_ns(&self) -> Option<u64> {
+        let wheel = self.wheel.next_event().map(|tick| tick * TICK_NS);
+        match (wheel, self.hrtimers.next_event()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    // tickless: the LAPIC timer is armed only if there is something to do
+    fn program(&mut self, now: u64) {
+        if !self.events_enabled {
+            return;
+        }
+
+        // SAFETY: lapic initialised once the events are enabled
+        let lapic = unsafe { get_lapic() };
+        match self.next_event_ns() {
+            Some(deadline) => {
+                let delta = deadline.saturating_sub(now).max(MIN_EVENT_DELTA_NS);
+                trace!("[timers] next event in {} ns", delta);
+                self.armed = now + delta;
+                lapic.arm_timer(Some(delta));
+            }
+            None => {
+                trace!("[timers] no pending events");
+                self.armed = u64::MAX;
+                lapic.arm_timer(None);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: SpinLockIrq<Timers> = SpinLockIrq::new(Timers {
+        wheel: TimerWheel::new(),
+        hrtimers: HrTimerQueue::new(),
+        next_id: 0,
+        armed: u64::MAX,
+        events_enabled: false,
+    });
+}
+
+// UNIX time (in ns) at the moment `monotonic_ns` was 0
+static BOOT_TIME_NS: AtomicU64 = AtomicU64::new(0);
+
+// ns since boot, as told by the best clocksource available
+pub(crate) fn monotonic_ns() -> u64 {
+    clocksource::read_ns()
+}
+
+// `unix_time_ns` is the wall clock time right now
+pub(super) fn set_realtime(unix_time_ns: u64) {
+    BOOT_TIME_NS.store(
+        unix_time_ns.saturating_sub(monotonic_ns()),
+        Ordering::Relaxed,
+    );
+}
+
+// ns since the UNIX epoch
+pub(in super::super) fn realtime_ns() -> u64 {
+    BOOT_TIME_NS.load(Ordering::Relaxed) + monotonic_ns()
+}
+
+// from now on, the LAPIC timer is used to deliver the timer events
+// SAFETY: lapic has to be initialised
+pub(in super::super) unsafe fn enable_events() {
+    let mut timers = TIMERS.lock();
+    timers.events_enabled = true;
+    timers.program(monotonic_ns());
+}
+
+// a timer for the timeouts that don't need to be precise
+// the expiry is rounded up to the next tick of the timer wheel
+pub(in super::super) fn add_timer(
+    delay_ns: u64,
+    callback: impl FnOnce() + Send + 'static,
+) -> TimerHandle {
+    let expires = (monotonic_ns() + delay_ns).div_ceil(TICK_NS);
+    add(
+        TimerKind::Coarse,
+        expires,
+        expires * TICK_NS,
+        Box::new(callback),
+    )
+}
+
+// a timer that expires as close to `delay_ns` from now as possible
+pub(in super::super) fn add_hrtimer(
+    delay_ns: u64,
+    callback: impl FnOnce() + Send + 'static,
+) -> TimerHandle {
+    let expires = monotonic_ns() + delay_ns;
+    add(TimerKind::HighRes, expires, expires, Box::new(callback))
+}
+
+fn add(kind: TimerKind, expires: u64, deadline_ns: u64, callback: TimerCallback) -> TimerHandle {
+    let state = Arc::new(AtomicU8::new(PENDING));
+
+    let mut timers = TIMERS.lock();
+    let id = timers.next_id;
+    timers.next_id += 1;
+
+    let entry = TimerEntry::new(id, expires, state.clone(), callback);
+    trace!("[timers] adding {:?} timer {:?}", kind, entry);
+    match kind {
+        TimerKind::Coarse => timers.wheel.add(entry),
+        TimerKind::HighRes => timers.hrtimers.add(entry),
+    }
+
+    // the LAPIC is armed for something later than the new timer
+    if deadline_ns < timers.armed {
+        timers.program(monotonic_ns());
+    }
+
+    TimerHandle {
+        id,
+        expires,
+        kind,
+        state,
+    }
+}
+
+// runs the callbacks of the timers that expired
+// to be called from the timer interrupt handler
+pub(in super::super) fn run_expired() {
+    let now = monotonic_ns();
+    let expired = {
+        let mut timers = TIMERS.lock();
+        let mut expired = timers.hrtimers.expire(now);
+        expired.extend(timers.wheel.advance(now / TICK_NS));
+        expired
+    };
+
+    // the lock is not held, so that the callbacks can add new timers
+    for entry in expired {
+        entry.fire();
+    }
+}
+
+// arms the LAPIC timer for the next pending timer (if any)
+pub(in super::super) fn program_next_event() {
+    TIMERS.lock().program(monotonic_ns());
+}
+
+#[cfg(test)]
+impl TimerEntry {
+    // a timer that does nothing when it fires
+    pub(super) fn noop(id: u64, expires: u64) -> Self {
+        Self::new(
+            id,
+            expires,
+            Arc::new(AtomicU8::new(PENDING)),
+            Box::new(|| {}),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::sync::atomic::AtomicUsize;
+
+    // a timer on `wheel` that counts its runs in `runs`
+    fn add_counting(
+        wheel: &mut TimerWheel,
+        id: u64,
+        expires: u64,
+        runs: &Arc<AtomicUsize>,
+    ) -> TimerHandle {
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let runs = runs.clone();
+        let callback = Box::new(move || {
+            runs.fetch_add(1, Ordering::Relaxed);
+        });
+        wheel.add(TimerEntry::new(id, expires, state.clone(), callback));
+        TimerHandle {
+            id,
+            expires,
+            kind: TimerKind::Coarse,
+            state,
+        }
+    }
+
+    #[test]
+    fn cancelled_timers_dont_run() {
+        let mut wheel = TimerWheel::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let kept = add_counting(&mut wheel, 0, 10, &runs);
+        let cancelled = add_counting(&mut wheel, 1, 10, &runs);
+        // on another level
+        let cancelled_far = add_counting(&mut wheel, 2, 5000, &runs);
+
+        assert!(cancelled.cancel());
+        assert!(!cancelled.cancel());
+        assert!(cancelled_far.cancel());
+        assert!(kept.is_pending());
+
+        // the cancelled ones still come out of the wheel, they are just not run
+        let expired = wheel.advance(10);
+        assert_eq!(expired.len(), 2);
+        expired.into_iter().for_each(TimerEntry::fire);
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        assert!(!kept.is_pending());
+        // too late, it has fired already
+        assert!(!kept.cancel());
+
+        let expired = wheel.advance(5000);
+        assert_eq!(
+            expired.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            [2]
+        );
+        expired.into_iter().for_each(TimerEntry::fire);
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        assert!(wheel.is_empty());
+    }
+}
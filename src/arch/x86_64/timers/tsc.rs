@@ -0,0 +1,111 @@
+use super::clocksource::{self, ClockSource};
+use core::{
+    arch::x86_64::{__cpuid, _rdtsc, CpuidResult},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use log::info;
+
+// how long to count the TSC ticks against the HPET
+const CALIBRATION_NS: u64 = 50 * 10u64.pow(6);
+
+// conversions between TSC ticks and ns are done as `(value * MULT) >> 32`
+// cheaper than dividing by the frequency every time
+const SHIFT: u32 = 32;
+
+// 0 if the TSC can't be used as a clock (not invariant or not calibrated yet)
+static TSC_FREQ_HZ: AtomicU64 = AtomicU64::new(0);
+static TICKS_TO_NS_MULT: AtomicU64 = AtomicU64::new(0);
+static NS_TO_TICKS_MULT: AtomicU64 = AtomicU64::new(0);
+// TSC value and the time since boot at the moment of calibration
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+static BASE_NS: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+pub(in super::super) fn rdtsc() -> u64 {
+    // SAFETY: TSC is present on all x86_64 processors
+    unsafe { _rdtsc() }
+}
+
+// invariant TSC runs at a constant rate in all ACPI P-, C- and T-states
+// so it can be used as a wall clock
+fn has_invariant_tsc() -> bool {
+    let CpuidResult { eax: max_leaf, .. } = unsafe { __cpuid(0x8000_0000) };
+    if max_leaf < 0x8000_0007 {
+        return false;
+    }
+    let CpuidResult { edx, .. } = unsafe { __cpuid(0x8000_0007) };
+    edx & (1 << 8) != 0
+}
+
+// LAPIC timer can fire when the TSC reaches the value in IA32_TSC_DEADLINE
+pub(in super::super) fn supports_tsc_deadline() -> bool {
+    let CpuidResult { ecx, .. } = unsafe { __cpuid(1) };
+    ecx & (1 << 24) != 0
+}
+
+pub(in super::super) fn is_usable() -> bool {
+    TSC_FREQ_HZ.load(Ordering::Relaxed) != 0
+}
+
+// measures the TSC frequency against the clocksource in use (or the PIT)
+// the TSC is not used as a clock unless it's invariant
+// returns true if the TSC can be used as a clock
+pub(super) fn calibrate() -> bool {
+    if !has_invariant_tsc() {
+        info!("TSC is not invariant, not using it as a clock");
+        return false;
+    }
+
+    let clocksource::Calibration {
+        start: start_ticks,
+        end: end_ticks,
+        start_ns,
+        elapsed_ns,
+    } = clocksource::calibrate(CALIBRATION_NS, rdtsc);
+    let ticks = end_ticks - start_ticks;
+
+    let freq = (ticks as u128 * 10u128.pow(9) / elapsed_ns as u128) as u64;
+    info!("TSC frequency: {} Hz", freq);
+
+    TICKS_TO_NS_MULT.store(
+        ((10u128.pow(9) << SHIFT) / freq as u128) as u64,
+        Ordering::Relaxed,
+    );
+    NS_TO_TICKS_MULT.store(
+        (((freq as u128) << SHIFT) / 10u128.pow(9)) as u64,
+        Ordering::Relaxed,
+    );
+    BASE_TICKS.store(start_ticks, Ordering::Relaxed);
+    BASE_NS.store(start_ns, Ordering::Relaxed);
+    TSC_FREQ_HZ.store(freq, Ordering::Release);
+    true
+}
+
+pub(in super::super) fn ticks_to_ns(ticks: u64) -> u64 {
+    ((ticks as u128 * TICKS_TO_NS_MULT.load(Ordering::Relaxed) as u128) >> SHIFT) as u64
+}
+
+pub(in super::super) fn ns_to_ticks(ns: u64) -> u64 {
+    ((ns as u128 * NS_TO_TICKS_MULT.load(Ordering::Relaxed) as u128) >> SHIFT) as u64
+}
+
+// reading the TSC is a lot cheaper than going to the HPET through MMIO
+// only registered once calibrated
+pub(super) struct Tsc;
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn rating(&self) -> u32 {
+        300
+    }
+
+    // ns since boot
+    #[inline]
+    fn read_ns(&self) -> u64 {
+        let ticks = rdtsc().saturating_sub(BASE_TICKS.load(Ordering::Relaxed));
+        BASE_NS.load(Ordering::Relaxed) + ticks_to_ns(ticks)
+    }
+}
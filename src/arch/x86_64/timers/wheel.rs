@@ -0,0 +1,268 @@
+use super::timer::TimerEntry;
+use alloc::vec::Vec;
+
+// hierarchical timing wheel for the coarse timeouts
+// level `l` has SLOTS slots, each covering SLOTS^l ticks
+// a timer is kept at the lowest level that can tell its expiry apart from the current tick,
+// and moves down a level (cascades) when the wheel reaches its slot.
+// so adding a timer is O(1) no matter how far in the future it expires
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 4;
+
+// timers further in the future than what the top level can hold
+// are parked in the furthest slot and re-inserted each time it cascades
+const MAX_TICKS_AHEAD: u64 = (SLOTS as u64 - 1) << (LEVEL_BITS * (LEVELS as u32 - 1));
+
+struct Level {
+    slots: [Vec<TimerEntry>; SLOTS],
+    // bit n set if the slot n is not empty
+    occupied: u64,
+}
+
+impl Level {
+    const EMPTY_SLOT: Vec<TimerEntry> = Vec::new();
+
+    fn new() -> Self {
+        Self {
+            slots: [Self::EMPTY_SLOT; SLOTS],
+            occupied: 0,
+        }
+    }
+}
+
+pub(super) struct TimerWheel {
+    levels: [Level; LEVELS],
+    // all the ticks up to this one (inclusive) have been processed
+    current: u64,
+    len: usize,
+}
+
+impl core::fmt::Debug for TimerWheel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TimerWheel")
+            .field("current", &self.current)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl TimerWheel {
+    pub(super) fn new() -> Self {
+        Self {
+            levels: core::array::from_fn(|_| Level::new()),
+            current: 0,
+            len: 0,
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // `entry.expires` is in ticks
+    pub(super) fn add(&mut self, entry: TimerEntry) {
+        // can't go back in time, expire with the next tick processed
+        let expires = entry
+            .expires
+            .clamp(self.current + 1, self.current + MAX_TICKS_AHEAD);
+
+        // the lowest level at which the expiry and the current tick fall within one rotation
+        let level = (0..LEVELS)
+            .find(|&l| {
+                let shift = LEVEL_BITS * l as u32;
+                (expires >> shift) - (self.current >> shift) < SLOTS as u64
+            })
+            .unwrap();
+        let slot = ((expires >> (LEVEL_BITS * level as u32)) & SLOT_MASK) as usize;
+
+        let level = &mut self.levels[level];
+        level.slots[slot].push(entry);
+        level.occupied |= 1 << slot;
+        self.len += 1;
+    }
+
+    // the next tick at which something happens (a timer expires or a slot cascades)
+    pub(super) fn next_event(&self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+
+        (0..LEVELS)
+            .filter_map(|l| {
+                let level = &self.levels[l];
+                if level.occupied == 0 {
+                    return None;
+                }
+                let shift = LEVEL_BITS * l as u32;
+                let pos = (self.current >> shift) & SLOT_MASK;
+                // distance (in slots of this level) to the nearest occupied slot after the current one
+                let rotated = level.occupied.rotate_right(pos as u32 + 1);
+                let distance = rotated.trailing_zeros() as u64 + 1;
+                Some(((self.current >> shift) + distance) << shift)
+            })
+            .min()
+    }
+
+    // moves the wheel forward to `now` (in ticks)
+    // returns the timers that expired on the way
+    pub(super) fn advance(&mut self, now: u64) -> Vec<TimerEntry> {
+        let mut expired = Vec::new();
+
+        // jump straight to the ticks that have something to do, instead of going through every one of them
+        while let Some(next) = self.next_event()
+            && next <= now
+        {
+            self.current = next;
+            self.process_tick(&mut expired);
+        }
+        self.current = self.current.max(now);
+
+        expired
+    }
+
+    fn process_tick(&mut self, expired: &mut Vec<TimerEntry>) {
+        // cascade the slots of the higher levels that the wheel just reached
+        for l in (1..LEVELS).rev() {
+            let shift = LEVEL_BITS * l as u32;
+            if self.current & ((1 << shift) - 1) != 0 {
+                continue;
+            }
+            let slot = ((self.current >> shift) & SLOT_MASK) as usize;
+            let entries = self.take_slot(l, slot);
+            for entry in entries {
+                if entry.expires <= self.current {
+                    expired.push(entry);
+                } else {
+                    self.add(entry);
+                }
+            }
+        }
+
+        let slot = (self.current & SLOT_MASK) as usize;
+        expired.extend(self.take_slot(0, slot));
+    }
+
+    fn take_slot(&mut self, level: usize, slot: usize) -> Vec<TimerEntry> {
+        let level = &mut self.levels[level];
+        level.occupied &= !(1 << slot);
+        let entries = core::mem::take(&mut level.slots[slot]);
+        self.len -= entries.len();
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wheel_with(expiries: &[u64]) -> TimerWheel {
+        let mut wheel = TimerWheel::new();
+        for (id, &expires) in expiries.iter().enumerate() {
+            wheel.add(TimerEntry::noop(id as u64, expires));
+        }
+        wheel
+    }
+
+    // goes from one event to the next till the wheel is empty
+    // returns the timers as (id, the tick they expired at), in the order they expired in
+    fn run(wheel: &mut TimerWheel) -> Vec<(u64, u64)> {
+        let mut fired = Vec::new();
+        while let Some(next) = wheel.next_event() {
+            assert!(next > wheel.current);
+            fired.extend(wheel.advance(next).iter().map(|entry| (entry.id, next)));
+        }
+        assert!(wheel.is_empty());
+        fired
+    }
+
+    #[test]
+    fn expire_on_time_across_levels() {
+        let level_1 = SLOTS as u64;
+        let level_2 = level_1 * SLOTS as u64;
+        let level_3 = level_2 * SLOTS as u64;
+        let expiries = [
+            level_3 + 5,
+            1,
+            level_1 - 1,
+            level_1,
+            level_1 + 1,
+            level_2 - 1,
+            level_2,
+            level_2 + 1,
+            level_3,
+            3 * level_1 + 7,
+        ];
+        let mut wheel = wheel_with(&expiries);
+
+        let fired = run(&mut wheel);
+        assert_eq!(fired.len(), expiries.len());
+        for &(id, tick) in fired.iter() {
+            assert_eq!(tick, expiries[id as usize], "timer {} expired late", id);
+        }
+        assert!(fired.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn expire_from_a_later_tick() {
+        let mut wheel = wheel_with(&[]);
+        wheel.advance(1000);
+        let expiries = [1000 + 70, 1000 + 5000, 1000 + 64];
+        for (id, &expires) in expiries.iter().enumerate() {
+            wheel.add(TimerEntry::noop(id as u64, expires));
+        }
+        for (id, tick) in run(&mut wheel) {
+            assert_eq!(tick, expiries[id as usize]);
+        }
+    }
+
+    #[test]
+    fn next_event() {
+        let mut wheel = wheel_with(&[]);
+        assert_eq!(wheel.next_event(), None);
+
+        wheel.add(TimerEntry::noop(0, 10));
+        wheel.add(TimerEntry::noop(1, 100));
+        assert_eq!(wheel.next_event(), Some(10));
+        assert_eq!(wheel.advance(10).len(), 1);
+        // the second one is on level 1, its slot cascades first
+        assert_eq!(wheel.next_event(), Some(64));
+        assert!(wheel.advance(64).is_empty());
+        assert_eq!(wheel.next_event(), Some(100));
+        assert_eq!(wheel.advance(100).len(), 1);
+        assert_eq!(wheel.next_event(), None);
+    }
+
+    #[test]
+    fn advance_past_several() {
+        let mut wheel = wheel_with(&[3, 200, 5000, 300]);
+        let mut ids = wheel
+            .advance(300)
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, [0, 1, 3]);
+        assert!(wheel.advance(4999).is_empty());
+        assert_eq!(wheel.advance(5000).len(), 1);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn expired_already() {
+        let mut wheel = wheel_with(&[]);
+        wheel.advance(50);
+        // expires with the next tick processed
+        wheel.add(TimerEntry::noop(0, 20));
+        assert_eq!(wheel.next_event(), Some(51));
+        assert_eq!(wheel.advance(51).len(), 1);
+    }
+
+    #[test]
+    fn beyond_the_top_level() {
+        let expires = MAX_TICKS_AHEAD * 2 + 3;
+        let mut wheel = wheel_with(&[expires]);
+        assert_eq!(run(&mut wheel), [(0, expires)]);
+    }
+}
@@ -0,0 +1,424 @@
+use super::termios::{
+    Termios, ECHO, ECHOCTL, ECHOE, ECHOK, ECHOKE, ECHONL, ICANON, ICRNL, IEXTEN, IGNCR, INLCR,
+    ISIG, NOFLSH, VEOF, VEOL, VERASE, VINTR, VKILL, VMIN, VQUIT, VWERASE,
+};
+use alloc::vec::Vec;
+
+// the longest line in canonical mode, the rest is dropped till the line ends
+const LINE_LEN: usize = 1024;
+const READY_LEN: usize = 4096;
+const ECHO_LEN: usize = 1024;
+
+// the line discipline of a terminal: turns what's typed into what the readers get
+// runs in the interrupt handlers of the drivers, so it doesn't allocate
+// and can't write the echo to the screen (the consoles are not interrupt safe)
+// the echo is kept till a reader or a writer comes along, see `take_echo`
+pub(super) struct LineDiscipline {
+    pub(super) termios: Termios,
+    // the line being edited in canonical mode
+    line: [u8; LINE_LEN],
+    line_len: usize,
+    // what the readers get: the complete lines in canonical mode, every byte otherwise
+    ready: [u8; READY_LEN],
+    ready_head: usize,
+    ready_len: usize,
+    // EOF on an empty line, the next read returns 0
+    eof: bool,
+    echo: [u8; ECHO_LEN],
+    echo_len: usize,
+}
+
+// what the driver has to do after passing a byte on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Received {
+    Nothing,
+    // there's something to read or to echo, the readers have to be woken up
+    Wake,
+    // INTR or QUIT, the foreground process has to be interrupted
+    Interrupt,
+}
+
+impl LineDiscipline {
+    pub(super) const fn new() -> Self {
+        Self {
+            termios: Termios::new(),
+            line: [0; LINE_LEN],
+            line_len: 0,
+            ready: [0; READY_LEN],
+            ready_head: 0,
+            ready_len: 0,
+            eof: false,
+            echo: [0; ECHO_LEN],
+            echo_len: 0,
+        }
+    }
+
+    pub(super) fn receive(&mut self, byte: u8) -> Received {
+        let termios = self.termios;
+        let byte = match byte {
+            b'\r' if termios.input(IGNCR) => return Received::Nothing,
+            b'\r' if termios.input(ICRNL) => b'\n',
+            b'\n' if termios.input(INLCR) => b'\r',
+            byte => byte,
+        };
+
+        if termios.local(ISIG) && (termios.is(VINTR, byte) || termios.is(VQUIT, byte)) {
+            if !termios.local(NOFLSH) {
+                self.flush_input();
+            }
+            self.echo_char(byte);
+            return Received::Interrupt;
+        }
+
+        if !termios.local(ICANON) {
+            self.push_ready(byte);
+            self.echo_char(byte);
+            return Received::Wake;
+        }
+
+        if termios.is(VERASE, byte) {
+            if self.line_len > 0 {
+                self.erase_last();
+                self.echo_erase(byte);
+            }
+        } else if termios.is(VWERASE, byte) && termios.local(IEXTEN) {
+            if self.line_len > 0 {
+                // the blanks before the word, then the word
+                while self.last_char().is_some_and(is_blank) {
+                    self.erase_last();
+                }
+                while self.last_char().is_some_and(|c| !is_blank(c)) {
+                    self.erase_last();
+                }
+                self.echo_erase(byte);
+            }
+        } else if termios.is(VKILL, byte) {
+            self.kill(byte);
+        } else if termios.is(VEOF, byte) {
+            // the line so far is passed on without a newline, or it's the end of the input
+            if self.line_len == 0 {
+                self.eof = true;
+            }
+            self.end_line();
+        } else if byte == b'\n' || termios.is(VEOL, byte) {
+            self.push_line(byte);
+            if termios.local(ECHO) || (byte == b'\n' && termios.local(ECHONL)) {
+                self.push_echo(&[byte]);
+            }
+            self.end_line();
+        } else {
+            // one byte is kept for the newline
+            if self.line_len < LINE_LEN - 1 {
+                self.push_line(byte);
+                self.echo_char(byte);
+            }
+        }
+        Received::Wake
+    }
+
+    // the first byte of the last character of the line
+    fn last_char(&self) -> Option<u8> {
+        self.last_char_start().map(|start| self.line[start])
+    }
+
+    fn last_char_start(&self) -> Option<usize> {
+        // the continuation bytes are 0b10xxxxxx
+        self.line[..self.line_len]
+            .iter()
+            .rposition(|&b| b & 0xc0 != 0x80)
+    }
+
+    // removes the last character of the line, from the screen too with ECHOE
+    fn erase_last(&mut self) {
+        let Some(start) = self.last_char_start() else {
+            self.line_len = 0;
+            return;
+        };
+        let first = self.line[start];
+        self.line_len = start;
+        if self.termios.local(ECHO) && self.termios.local(ECHOE) {
+            // the tabs are taken to be a column wide, there's no telling where they ended
+            for _ in 0..self.echo_width(first) {
+                self.push_echo(b"\x08 \x08");
+            }
+        }
+    }
+
+    // without ECHOE, the erase characters are echoed instead
+    fn echo_erase(&mut self, erase_char: u8) {
+        if !self.termios.local(ECHOE) {
+            self.echo_char(erase_char);
+        }
+    }
+
+    fn kill(&mut self, kill_char: u8) {
+        let termios = self.termios;
+        if termios.local(ECHOKE) && termios.local(ECHOE) {
+            while self.line_len > 0 {
+                self.erase_last();
+            }
+            return;
+        }
+        self.line_len = 0;
+        self.echo_char(kill_char);
+        if termios.local(ECHO) && termios.local(ECHOK) {
+            self.push_echo(b"\n");
+        }
+    }
+
+    // the columns `byte` took up when it was echoed
+    fn echo_width(&self, byte: u8) -> usize {
+        if is_control(byte) && self.termios.local(ECHOCTL) {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn echo_char(&mut self, byte: u8) {
+        if !self.termios.local(ECHO) {
+            return;
+        }
+        if is_control(byte) && self.termios.local(ECHOCTL) {
+            self.push_echo(&[b'^', byte ^ 0x40]);
+        } else {
+            self.push_echo(&[byte]);
+        }
+    }
+
+    fn push_echo(&mut self, bytes: &[u8]) {
+        // dropped if no one took the echo in a while
+        if self.echo_len + bytes.len() <= ECHO_LEN {
+            self.echo[self.echo_len..self.echo_len + bytes.len()].copy_from_slice(bytes);
+            self.echo_len += bytes.len();
+        }
+    }
+
+    fn push_line(&mut self, byte: u8) {
+        self.line[self.line_len] = byte;
+        self.line_len += 1;
+    }
+
+    // the line is ready to be read
+    fn end_line(&mut self) {
+        for i in 0..self.line_len {
+            self.push_ready(self.line[i]);
+        }
+        self.line_len = 0;
+    }
+
+    // dropped if the readers don't keep up
+    fn push_ready(&mut self, byte: u8) {
+        if self.ready_len < READY_LEN {
+            self.ready[(self.ready_head + self.ready_len) % READY_LEN] = byte;
+            self.ready_len += 1;
+        }
+    }
+
+    // drops what's been typed and not read yet
+    pub(super) fn flush_input(&mut self) {
+        self.line_len = 0;
+        self.ready_len = 0;
+        self.eof = false;
+    }
+
+    pub(super) fn take_echo(&mut self) -> Vec<u8> {
+        let echo = self.echo[..self.echo_len].to_vec();
+        self.echo_len = 0;
+        echo
+    }
+
+    pub(super) fn has_echo(&self) -> bool {
+        self.echo_len > 0
+    }
+
+    // whether `read` would return something
+    pub(super) fn readable(&self) -> bool {
+        self.ready_len > 0
+            || self.eof
+            || (!self.termios.local(ICANON) && self.termios.cc[VMIN] == 0)
+    }
+
+    // `None` if there's nothing to read yet
+    // in canonical mode, at most a line is returned
+    pub(super) fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.ready_len == 0 {
+            if !self.readable() {
+                return None;
+            }
+            self.eof = false;
+            return Some(0);
+        }
+
+        let canonical = self.termios.local(ICANON);
+        let mut read = 0;
+        while read < buf.len() && self.ready_len > 0 {
+            let byte = self.ready[self.ready_head];
+            self.ready_head = (self.ready_head + 1) % READY_LEN;
+            self.ready_len -= 1;
+            buf[read] = byte;
+            read += 1;
+            if canonical && byte == b'\n' {
+                break;
+            }
+        }
+        Some(read)
+    }
+
+    pub(super) fn set_termios(&mut self, termios: Termios) {
+        // the line being edited is passed on as it is when leaving canonical mode
+        if self.termios.local(ICANON) && termios.lflag & ICANON == 0 {
+            self.end_line();
+        }
+        self.termios = termios;
+    }
+}
+
+fn is_blank(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+// the characters echoed as ^X, except for the ones that move the cursor
+fn is_control(byte: u8) -> bool {
+    (byte < 0x20 && byte != b'\n' && byte != b'\t') || byte == 0x7f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn type_in(line: &mut LineDiscipline, bytes: &[u8]) -> Vec<Received> {
+        bytes.iter().map(|&byte| line.receive(byte)).collect()
+    }
+
+    fn read_all(line: &mut LineDiscipline) -> Option<Vec<u8>> {
+        let mut buf = [0; 64];
+        line.read(&mut buf).map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn canonical_lines_are_read_once_complete() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"ls");
+        assert!(!line.readable());
+        assert_eq!(read_all(&mut line), None);
+
+        // ICRNL, Enter sends a carriage return
+        type_in(&mut line, b"\r");
+        assert_eq!(read_all(&mut line), Some(b"ls\n".to_vec()));
+        assert_eq!(line.take_echo(), b"ls\n");
+    }
+
+    #[test]
+    fn canonical_reads_stop_at_the_end_of_a_line() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"one\ntwo\n");
+        assert_eq!(read_all(&mut line), Some(b"one\n".to_vec()));
+        assert_eq!(read_all(&mut line), Some(b"two\n".to_vec()));
+        assert_eq!(read_all(&mut line), None);
+    }
+
+    #[test]
+    fn erase() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"cat\x7f\x7fp\n");
+        assert_eq!(read_all(&mut line), Some(b"cp\n".to_vec()));
+        assert_eq!(line.take_echo(), b"cat\x08 \x08\x08 \x08p\n");
+    }
+
+    #[test]
+    fn erase_takes_the_whole_utf8_character() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, "né".as_bytes());
+        type_in(&mut line, b"\x7f\n");
+        assert_eq!(read_all(&mut line), Some(b"n\n".to_vec()));
+    }
+
+    #[test]
+    fn erase_on_an_empty_line_does_nothing() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"\x7f\x17\n");
+        assert_eq!(read_all(&mut line), Some(b"\n".to_vec()));
+        assert_eq!(line.take_echo(), b"\n");
+    }
+
+    #[test]
+    fn word_erase() {
+        let mut line = LineDiscipline::new();
+        // ^W takes the blanks, then the word before them
+        type_in(&mut line, b"echo foo  \x17bar\n");
+        assert_eq!(read_all(&mut line), Some(b"echo bar\n".to_vec()));
+    }
+
+    #[test]
+    fn kill() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"rm -rf\x15ls\n");
+        assert_eq!(read_all(&mut line), Some(b"ls\n".to_vec()));
+    }
+
+    #[test]
+    fn eof_on_an_empty_line_is_the_end_of_the_input() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"\x04");
+        assert!(line.readable());
+        assert_eq!(read_all(&mut line), Some(vec![]));
+        // just the once
+        assert_eq!(read_all(&mut line), None);
+    }
+
+    #[test]
+    fn eof_passes_the_line_on_without_a_newline() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"abc\x04");
+        assert_eq!(read_all(&mut line), Some(b"abc".to_vec()));
+        assert_eq!(read_all(&mut line), None);
+    }
+
+    #[test]
+    fn intr_flushes_the_input_and_interrupts() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"done\nhalf");
+        assert_eq!(line.receive(0x03), Received::Interrupt);
+        assert_eq!(read_all(&mut line), None);
+        assert_eq!(line.take_echo(), b"done\nhalf^C");
+    }
+
+    #[test]
+    fn intr_is_ordinary_input_without_isig() {
+        let mut line = LineDiscipline::new();
+        let mut termios = Termios::new();
+        termios.lflag &= !ISIG;
+        line.set_termios(termios);
+        assert_eq!(line.receive(0x03), Received::Wake);
+        type_in(&mut line, b"\n");
+        assert_eq!(read_all(&mut line), Some(b"\x03\n".to_vec()));
+    }
+
+    #[test]
+    fn raw_mode_passes_every_byte_on() {
+        let mut line = LineDiscipline::new();
+        let mut termios = Termios::new();
+        termios.lflag &= !(ICANON | ECHO);
+        line.set_termios(termios);
+        assert_eq!(type_in(&mut line, b"a\x7f"), [Received::Wake; 2]);
+        assert_eq!(read_all(&mut line), Some(b"a\x7f".to_vec()));
+        assert!(!line.has_echo());
+
+        // VMIN 0, the reads don't wait
+        termios.cc[VMIN] = 0;
+        line.set_termios(termios);
+        assert_eq!(read_all(&mut line), Some(vec![]));
+    }
+
+    #[test]
+    fn leaving_canonical_mode_passes_the_line_on() {
+        let mut line = LineDiscipline::new();
+        type_in(&mut line, b"par");
+        let mut termios = Termios::new();
+        termios.lflag &= !ICANON;
+        line.set_termios(termios);
+        assert_eq!(read_all(&mut line), Some(b"par".to_vec()));
+    }
+}
@@ -0,0 +1,272 @@
+mod line;
+mod termios;
+
+use super::{framebuffer, process, serial, syscall, vga_buffer, WaitQueue};
+use crate::{
+    fs::{self, CharDevice, FsError, FsResult},
+    locks::SpinLockIrq,
+};
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use line::{LineDiscipline, Received};
+use log::warn;
+use termios::{Termios, ONLCR};
+
+// the terminals: the console (screen and keyboard) and the serial ports
+// what's typed goes through the line discipline, see `LineDiscipline`
+// there are no sessions or controlling terminals, the first process to use a terminal
+// becomes its foreground process. Every process is a process group of its own
+
+// ioctl requests, same as Linux
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+// wait for the output to drain first, it's written out right away here
+const TCSETSW: u32 = 0x5403;
+// flush the input first
+const TCSETSF: u32 = 0x5404;
+const TIOCGPGRP: u32 = 0x540f;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCGWINSZ: u32 = 0x5413;
+
+// the size of the serial terminals, there's no asking the other end
+const SERIAL_SIZE: (usize, usize) = (80, 24);
+
+pub(super) const CONSOLE: usize = 0;
+const TTY_COUNT: usize = 5;
+
+// where the output goes
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Console,
+    Serial(usize),
+}
+
+struct Tty {
+    output: Output,
+    line: LineDiscipline,
+    // the process interrupted by INTR and QUIT
+    foreground: Option<u32>,
+}
+
+impl Tty {
+    const fn new(output: Output) -> Self {
+        Self {
+            output,
+            line: LineDiscipline::new(),
+            foreground: None,
+        }
+    }
+
+    // the current process becomes the foreground one if there's none
+    fn claim(&mut self) {
+        if self.foreground.is_none() {
+            self.foreground = Some(process::current_pid());
+        }
+    }
+}
+
+// `/dev/tty0`, then `/dev/ttyS0`..`/dev/ttyS3`
+static TTYS: [SpinLockIrq<Tty>; TTY_COUNT] = [
+    SpinLockIrq::new(Tty::new(Output::Console)),
+    SpinLockIrq::new(Tty::new(Output::Serial(0))),
+    SpinLockIrq::new(Tty::new(Output::Serial(1))),
+    SpinLockIrq::new(Tty::new(Output::Serial(2))),
+    SpinLockIrq::new(Tty::new(Output::Serial(3))),
+];
+// the tasks waiting for input (or for an interrupt), per terminal
+static READERS: [WaitQueue; TTY_COUNT] = [
+    WaitQueue::new(),
+    WaitQueue::new(),
+    WaitQueue::new(),
+    WaitQueue::new(),
+    WaitQueue::new(),
+];
+
+// the terminal of COM`port + 1`
+pub(super) const fn serial(port: usize) -> usize {
+    port + 1
+}
+
+// registers the console and the serial ports that are there
+pub(super) fn init() {
+    register("tty0", CONSOLE);
+    for port in 0..TTY_COUNT - 1 {
+        if serial::present(port) {
+            register(&format!("ttyS{}", port), serial(port));
+        }
+    }
+}
+
+fn register(name: &str, index: usize) {
+    if let Err(err) = fs::register_device(name, Arc::new(TtyDevice(index))) {
+        warn!("[tty] failed to register /dev/{}: {:?}", name, err);
+    }
+}
+
+// what the driver received, called from its interrupt handler
+pub(super) fn input(index: usize, bytes: &[u8]) {
+    let mut wake = false;
+    for &byte in bytes {
+        let mut tty = TTYS[index].lock();
+        match tty.line.receive(byte) {
+            Received::Nothing => {}
+            Received::Wake => wake = true,
+            Received::Interrupt => {
+                wake = true;
+                let foreground = tty.foreground;
+                // the scheduler is not locked with a terminal locked
+                drop(tty);
+                if let Some(pid) = foreground {
+                    if !process::interrupt_process(pid) {
+                        // gone, the next one to use the terminal takes over
+                        let mut tty = TTYS[index].lock();
+                        if tty.foreground == Some(pid) {
+                            tty.foreground = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // the wait queue needs the scheduler, which is there once someone's waiting
+    if wake && !READERS[index].is_empty() {
+        READERS[index].wake_all();
+    }
+}
+
+// writes `bytes` the way the terminal is set up to
+fn write_output(output: Output, termios: &Termios, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    let converted;
+    let bytes = if termios.output(ONLCR) && bytes.contains(&b'\n') {
+        converted = bytes.iter().fold(Vec::new(), |mut out, &byte| {
+            if byte == b'\n' {
+                out.push(b'\r');
+            }
+            out.push(byte);
+            out
+        });
+        &converted[..]
+    } else {
+        bytes
+    };
+
+    match output {
+        Output::Console => super::_print(format_args!("{}", String::from_utf8_lossy(bytes))),
+        Output::Serial(port) => serial::write(port, bytes),
+    }
+}
+
+// writes out the echo of what was typed since the last time
+// returns the settings it was written with
+fn flush_echo(index: usize) -> (Output, Termios) {
+    let mut tty = TTYS[index].lock();
+    tty.claim();
+    let echo = tty.line.take_echo();
+    let (output, termios) = (tty.output, tty.line.termios);
+    drop(tty);
+    write_output(output, &termios, &echo);
+    (output, termios)
+}
+
+// waits for a line in canonical mode, for `VMIN` bytes (0 or 1) otherwise
+fn read(index: usize, buf: &mut [u8]) -> FsResult<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    loop {
+        flush_echo(index);
+        if let Some(read) = TTYS[index].lock().line.read(buf) {
+            return Ok(read);
+        }
+        if process::interrupt_pending() {
+            return Err(FsError::Interrupted);
+        }
+        READERS[index].wait_while(|| {
+            let tty = TTYS[index].lock();
+            !tty.line.readable() && !tty.line.has_echo() && !process::interrupt_pending()
+        });
+    }
+}
+
+fn write(index: usize, buf: &[u8]) -> FsResult<usize> {
+    let (output, termios) = flush_echo(index);
+    write_output(output, &termios, buf);
+    Ok(buf.len())
+}
+
+// `struct winsize` of Linux
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct WindowSize {
+    rows: u16,
+    cols: u16,
+    xpixel: u16,
+    ypixel: u16,
+}
+
+// `arg` points to the userspace
+fn ioctl(index: usize, cmd: u32, arg: u64) -> FsResult<u64> {
+    TTYS[index].lock().claim();
+    match cmd {
+        TCGETS => {
+            let termios = TTYS[index].lock().line.termios;
+            syscall::copy_to_user(arg, termios)?;
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let termios = syscall::copy_from_user::<Termios>(arg)?;
+            let mut tty = TTYS[index].lock();
+            if cmd == TCSETSF {
+                tty.line.flush_input();
+            }
+            tty.line.set_termios(termios);
+            drop(tty);
+            // what's there may be enough for a read now
+            READERS[index].wake_all();
+        }
+        TIOCGPGRP => {
+            let foreground = TTYS[index].lock().foreground.unwrap_or(0);
+            syscall::copy_to_user(arg, foreground as i32)?;
+        }
+        TIOCSPGRP => {
+            let pgid = syscall::copy_from_user::<i32>(arg)?;
+            if pgid < 0 || !process::process_exists(pgid as u32) {
+                return Err(FsError::InvalidArgument);
+            }
+            TTYS[index].lock().foreground = Some(pgid as u32);
+        }
+        TIOCGWINSZ => {
+            let (cols, rows) = match TTYS[index].lock().output {
+                Output::Console => framebuffer::console_size().unwrap_or(vga_buffer::SIZE),
+                Output::Serial(_) => SERIAL_SIZE,
+            };
+            let size = WindowSize {
+                rows: rows as u16,
+                cols: cols as u16,
+                xpixel: 0,
+                ypixel: 0,
+            };
+            syscall::copy_to_user(arg, size)?;
+        }
+        _ => return Err(FsError::InappropriateIoctl),
+    }
+    Ok(0)
+}
+
+// `/dev/tty0`, `/dev/ttySn`
+struct TtyDevice(usize);
+
+impl CharDevice for TtyDevice {
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize> {
+        read(self.0, buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> FsResult<usize> {
+        write(self.0, buf)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: u64) -> FsResult<u64> {
+        ioctl(self.0, cmd, arg)
+    }
+}
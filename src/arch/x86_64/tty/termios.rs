@@ -0,0 +1,115 @@
+// the settings of a terminal, laid out like `struct termios` of Linux
+// so that the userspace libraries can pass theirs to TCGETS/TCSETS as is
+// https://man7.org/linux/man-pages/man3/termios.3.html
+
+// input flags
+// ignore carriage return
+pub(super) const IGNCR: u32 = 0o200;
+// carriage return to newline, what Enter sends
+pub(super) const ICRNL: u32 = 0o400;
+// newline to carriage return
+pub(super) const INLCR: u32 = 0o100;
+
+// output flags
+// process the output, the other output flags do nothing without it
+pub(super) const OPOST: u32 = 0o1;
+// newline to carriage return + newline
+pub(super) const ONLCR: u32 = 0o4;
+
+// control flags, only reported, there's no baud rate or parity to set on the consoles
+const B38400: u32 = 0o17;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+
+// local flags
+// INTR and QUIT interrupt the foreground process
+pub(super) const ISIG: u32 = 0o1;
+// canonical mode, the input is edited a line at a time
+pub(super) const ICANON: u32 = 0o2;
+pub(super) const ECHO: u32 = 0o10;
+// ERASE and WERASE erase the character on the screen too
+pub(super) const ECHOE: u32 = 0o20;
+// KILL is followed by a newline
+pub(super) const ECHOK: u32 = 0o40;
+// echo the newline even without ECHO
+pub(super) const ECHONL: u32 = 0o100;
+// don't flush the input on INTR and QUIT
+pub(super) const NOFLSH: u32 = 0o200;
+// the control characters are echoed as ^X
+pub(super) const ECHOCTL: u32 = 0o1000;
+// KILL erases the line on the screen
+pub(super) const ECHOKE: u32 = 0o4000;
+// WERASE
+pub(super) const IEXTEN: u32 = 0o100000;
+
+// the special characters, indices into `cc`
+pub(super) const VINTR: usize = 0;
+pub(super) const VQUIT: usize = 1;
+pub(super) const VERASE: usize = 2;
+pub(super) const VKILL: usize = 3;
+pub(super) const VEOF: usize = 4;
+// the minimum number of bytes a read returns in the non-canonical mode
+pub(super) const VMIN: usize = 6;
+pub(super) const VEOL: usize = 11;
+pub(super) const VWERASE: usize = 14;
+
+const NCCS: usize = 19;
+
+// a special character set to this is disabled
+pub(super) const DISABLED: u8 = 0;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(super) struct Termios {
+    pub(super) iflag: u32,
+    pub(super) oflag: u32,
+    pub(super) cflag: u32,
+    pub(super) lflag: u32,
+    // the line discipline, there's only the one
+    pub(super) line: u8,
+    pub(super) cc: [u8; NCCS],
+}
+
+impl Termios {
+    // what Linux starts its terminals with: a line at a time, echoed
+    pub(super) const fn new() -> Self {
+        let mut cc = [DISABLED; NCCS];
+        cc[VINTR] = ctrl(b'C');
+        cc[VQUIT] = ctrl(b'\\');
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = ctrl(b'U');
+        cc[VEOF] = ctrl(b'D');
+        cc[VMIN] = 1;
+        cc[VWERASE] = ctrl(b'W');
+        Self {
+            iflag: ICRNL,
+            oflag: OPOST | ONLCR,
+            cflag: B38400 | CS8 | CREAD,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            cc,
+        }
+    }
+
+    pub(super) fn local(&self, flag: u32) -> bool {
+        self.lflag & flag != 0
+    }
+
+    pub(super) fn input(&self, flag: u32) -> bool {
+        self.iflag & flag != 0
+    }
+
+    pub(super) fn output(&self, flag: u32) -> bool {
+        self.oflag & OPOST != 0 && self.oflag & flag != 0
+    }
+
+    // whether `byte` is the special character `index`
+    pub(super) fn is(&self, index: usize, byte: u8) -> bool {
+        self.cc[index] != DISABLED && self.cc[index] == byte
+    }
+}
+
+// Ctrl+`c`
+const fn ctrl(c: u8) -> u8 {
+    c & 0x1f
+}
@@ -39,6 +39,8 @@ unsafe fn jump_to_userspace(code: VirtualAddress, stack_top: VirtualAddress) {
         "push 0x200", // rflags (only interrupt bit set)
         "push rdx",   // code segment
         "push rdi",   // ret to virtual addr
+        "cli",
+        "swapgs", // the userspace GS
         "iretq",
         in("rdi") code.to_inner(),
         in("rsi") stack_top.to_inner(),
@@ -1,7 +1,7 @@
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-pub(super) enum Colour {
+pub(in super::super) enum Colour {
     Black = 0,
     Blue = 1,
     Green = 2,
@@ -20,12 +20,36 @@ pub(super) enum Colour {
     White = 15,
 }
 
+impl Colour {
+    // the low 4 bits are used
+    pub(in super::super) fn from_index(index: u8) -> Self {
+        match index & 0xf {
+            0 => Self::Black,
+            1 => Self::Blue,
+            2 => Self::Green,
+            3 => Self::Cyan,
+            4 => Self::Red,
+            5 => Self::Magenta,
+            6 => Self::Brown,
+            7 => Self::LightGray,
+            8 => Self::DarkGray,
+            9 => Self::LightBlue,
+            10 => Self::LightGreen,
+            11 => Self::LightCyan,
+            12 => Self::LightRed,
+            13 => Self::Pink,
+            14 => Self::Yellow,
+            _ => Self::White,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub(super) struct ColourCode(u8);
 
 impl ColourCode {
-    pub(super) fn new(foreground: Colour, background: Colour) -> ColourCode {
+    pub(super) const fn new(foreground: Colour, background: Colour) -> ColourCode {
         ColourCode((background as u8) << 4 | (foreground as u8))
     }
 }
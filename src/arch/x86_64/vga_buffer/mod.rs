@@ -1,17 +1,40 @@
 mod character;
 mod writer;
 
-use character::{Colour, ColourCode};
-use lazy_static::lazy_static;
 use spin::Mutex;
 use writer::Writer;
 
-lazy_static! {
-    static ref WRITER: Mutex<Writer> =
-        Mutex::new(Writer::new(ColourCode::new(Colour::Yellow, Colour::Black)));
-}
+use crate::arch::PreemptGuard;
+
+pub(super) use character::Colour;
+
+// lines moved by a Shift+PgUp/PgDn
+const SCROLL_STEP: isize = 12;
+
+// columns and rows
+pub(super) const SIZE: (usize, usize) = (writer::BUFFER_WIDTH, writer::BUFFER_HEIGHT);
+
+static WRITER: Mutex<Writer> = Mutex::new(Writer::new(Colour::Yellow, Colour::Black));
 
 pub fn _print(args: core::fmt::Arguments) {
     use core::fmt::Write;
+    // `WRITER` is not one of our spinlocks, so preemption has to be disabled by hand
+    // otherwise, everyone else printing spins till the holder is scheduled again
+    let _preempt = PreemptGuard::new();
     WRITER.lock().write_fmt(args).unwrap();
 }
+
+// Shift+PgUp, shows older lines
+// called from the keyboard interrupt, gives up if someone is printing
+pub(super) fn scroll_back() {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.scroll_view(SCROLL_STEP);
+    }
+}
+
+// Shift+PgDn, back towards the live screen
+pub(super) fn scroll_forward() {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.scroll_view(-SCROLL_STEP);
+    }
+}
@@ -1,11 +1,38 @@
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+pub(super) const BUFFER_HEIGHT: usize = 25;
+pub(super) const BUFFER_WIDTH: usize = 80;
+// lines kept after they scroll off the top of the screen
+const SCROLLBACK_LINES: usize = 200;
+const TAB_WIDTH: usize = 8;
 
-use crate::mem::PhysicalAddress;
+// CRT controller, its cursor registers
+const CRTC_INDEX_PORT: u16 = 0x3d4;
+const CRTC_DATA_PORT: u16 = 0x3d5;
+const CURSOR_START: u8 = 0x0a;
+const CURSOR_LOCATION_HIGH: u8 = 0x0e;
+const CURSOR_LOCATION_LOW: u8 = 0x0f;
+// in the cursor start register
+const CURSOR_DISABLE: u8 = 1 << 5;
 
-use super::character::{ColourCode, ScreenChar};
+use crate::{
+    arch::x86_64::{
+        ansi::{Action, Attributes, Erase, Parser},
+        port::Port,
+    },
+    mem::PhysicalAddress,
+};
+
+use super::character::{Colour, ColourCode, ScreenChar};
 use core::fmt;
 
+type Line = [ScreenChar; BUFFER_WIDTH];
+
+const fn blank(attributes: Attributes) -> ScreenChar {
+    ScreenChar {
+        ascii_character: b' ',
+        color_code: ColourCode::new(attributes.fg, attributes.bg),
+    }
+}
+
 struct Buffer;
 
 impl Buffer {
@@ -20,91 +47,258 @@ impl Buffer {
         unsafe { Self::VGA_BUFFER.add(row * BUFFER_WIDTH + col) }
     }
 
-    fn read(&self, row: usize, col: usize) -> ScreenChar {
+    fn write(&self, row: usize, col: usize, val: ScreenChar) {
         unsafe {
             let addr = Self::get_addr(row, col);
-            core::ptr::read_volatile(addr)
+            core::ptr::write_volatile(addr, val);
         }
     }
 
-    fn write(&self, row: usize, col: usize, val: ScreenChar) {
+    fn write_line(&self, row: usize, line: &Line) {
+        for (col, &sc) in line.iter().enumerate() {
+            self.write(row, col, sc);
+        }
+    }
+
+    fn crtc_write(&self, index: u8, value: u8) {
+        // SAFETY: the CRT controller ports of the VGA
         unsafe {
-            let addr = Self::get_addr(row, col);
-            core::ptr::write_volatile(addr, val);
+            Port::new(CRTC_INDEX_PORT).write(index);
+            Port::new(CRTC_DATA_PORT).write(value);
         }
     }
+
+    fn crtc_read(&self, index: u8) -> u8 {
+        // SAFETY: the CRT controller ports of the VGA
+        unsafe {
+            Port::new(CRTC_INDEX_PORT).write(index);
+            Port::new(CRTC_DATA_PORT).read()
+        }
+    }
+
+    fn move_cursor(&self, row: usize, col: usize) {
+        let pos = (row * BUFFER_WIDTH + col) as u16;
+        self.crtc_write(CURSOR_LOCATION_LOW, pos as u8);
+        self.crtc_write(CURSOR_LOCATION_HIGH, (pos >> 8) as u8);
+    }
+
+    fn show_cursor(&self, show: bool) {
+        let start = self.crtc_read(CURSOR_START);
+        let start = if show {
+            start & !CURSOR_DISABLE
+        } else {
+            start | CURSOR_DISABLE
+        };
+        self.crtc_write(CURSOR_START, start);
+    }
+}
+
+// ring of the lines that scrolled off the screen
+struct Scrollback {
+    lines: [Line; SCROLLBACK_LINES],
+    // where the next line goes
+    head: usize,
+    len: usize,
+}
+
+impl Scrollback {
+    fn push(&mut self, line: Line) {
+        self.lines[self.head] = line;
+        self.head = (self.head + 1) % SCROLLBACK_LINES;
+        self.len = (self.len + 1).min(SCROLLBACK_LINES);
+    }
+
+    // 0 is the most recent one
+    fn get(&self, i: usize) -> &Line {
+        assert!(i < self.len);
+        &self.lines[(self.head + SCROLLBACK_LINES - 1 - i) % SCROLLBACK_LINES]
+    }
 }
 
 pub(super) struct Writer {
-    column_position: usize,
-    colour_code: ColourCode,
+    row: usize,
+    col: usize,
+    attributes: Attributes,
+    default_attributes: Attributes,
+    // the live contents of the screen, what's in the VGA buffer unless scrolled back
+    screen: [Line; BUFFER_HEIGHT],
+    scrollback: Scrollback,
+    // how many lines the view is scrolled back by, 0 shows the live screen
+    view_offset: usize,
+    parser: Parser,
+    saved_cursor: (usize, usize),
+    cursor_visible: bool,
+    // the VGA buffer still has what the bootloader left on it
+    initialised: bool,
     buffer: Buffer,
 }
 
 impl Writer {
-    pub(super) fn new(colour_code: ColourCode) -> Self {
+    // const, so that the ~30 KiB of the writer don't go through the stack
+    pub(super) const fn new(foreground: Colour, background: Colour) -> Self {
+        let attributes = Attributes::new(foreground, background);
         Self {
-            column_position: 0,
-            colour_code,
+            row: 0,
+            col: 0,
+            attributes,
+            default_attributes: attributes,
+            screen: [[blank(attributes); BUFFER_WIDTH]; BUFFER_HEIGHT],
+            scrollback: Scrollback {
+                lines: [[blank(attributes); BUFFER_WIDTH]; SCROLLBACK_LINES],
+                head: 0,
+                len: 0,
+            },
+            view_offset: 0,
+            parser: Parser::new(),
+            saved_cursor: (0, 0),
+            cursor_visible: true,
+            initialised: false,
             buffer: Buffer,
         }
     }
 
-    fn write_byte(&mut self, byte: u8) {
-        match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
+    fn handle(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => {
+                let byte = match c {
+                    // printable ASCII
+                    ' '..='~' => c as u8,
+                    // not part of printable ASCII range
+                    _ => 0xfe,
+                };
+                self.put(byte);
+            }
+            Action::Control('\n') => self.new_line(),
+            Action::Control('\r') => self.col = 0,
+            Action::Control('\t') => {
+                let next = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(BUFFER_WIDTH);
+                while self.col < next {
+                    self.put(b' ');
+                }
+            }
+            // backspace, only moves the cursor
+            Action::Control('\x08') => self.col = self.col.saturating_sub(1),
+            Action::Control(_) => {}
+            Action::SetGraphics(params) => {
+                self.attributes.apply_sgr(&params, self.default_attributes)
+            }
+            Action::CursorUp(n) => self.row = self.row.saturating_sub(n),
+            Action::CursorDown(n) => self.row = (self.row + n).min(BUFFER_HEIGHT - 1),
+            Action::CursorForward(n) => self.col = (self.col + n).min(BUFFER_WIDTH - 1),
+            Action::CursorBack(n) => self.col = self.col.saturating_sub(n),
+            Action::CursorPosition { row, col } => {
+                self.row = row.min(BUFFER_HEIGHT - 1);
+                self.col = col.min(BUFFER_WIDTH - 1);
+            }
+            Action::EraseDisplay(erase) => {
+                let cursor = self.row * BUFFER_WIDTH + self.col;
+                let range = match erase {
+                    Erase::ToEnd => cursor..BUFFER_HEIGHT * BUFFER_WIDTH,
+                    Erase::ToStart => 0..(cursor + 1).min(BUFFER_HEIGHT * BUFFER_WIDTH),
+                    Erase::All => 0..BUFFER_HEIGHT * BUFFER_WIDTH,
+                };
+                for i in range {
+                    self.set(i / BUFFER_WIDTH, i % BUFFER_WIDTH, b' ');
+                }
+            }
+            Action::EraseLine(erase) => {
+                let range = match erase {
+                    Erase::ToEnd => self.col..BUFFER_WIDTH,
+                    Erase::ToStart => 0..(self.col + 1).min(BUFFER_WIDTH),
+                    Erase::All => 0..BUFFER_WIDTH,
+                };
+                for col in range {
+                    self.set(self.row, col, b' ');
                 }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                let color_code = self.colour_code;
-                self.buffer.write(
-                    row,
-                    col,
-                    ScreenChar {
-                        ascii_character: byte,
-                        color_code,
-                    },
-                );
-                self.column_position += 1;
+            }
+            Action::SaveCursor => self.saved_cursor = (self.row, self.col),
+            Action::RestoreCursor => (self.row, self.col) = self.saved_cursor,
+            Action::ShowCursor(show) => {
+                self.cursor_visible = show;
+                self.buffer.show_cursor(show);
             }
         }
     }
 
-    fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
-            }
+    // writes at the cursor and moves it
+    fn put(&mut self, byte: u8) {
+        if self.col >= BUFFER_WIDTH {
+            self.new_line();
         }
+        self.set(self.row, self.col, byte);
+        self.col += 1;
+    }
+
+    fn set(&mut self, row: usize, col: usize, byte: u8) {
+        let sc = ScreenChar {
+            ascii_character: byte,
+            color_code: ColourCode::new(self.attributes.fg, self.attributes.bg),
+        };
+        self.screen[row][col] = sc;
+        self.buffer.write(row, col, sc);
     }
 
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let sc = self.buffer.read(row, col);
-                self.buffer.write(row - 1, col, sc);
-            }
+        self.col = 0;
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+            return;
+        }
+
+        self.scrollback.push(self.screen[0]);
+        self.screen.copy_within(1.., 0);
+        self.screen[BUFFER_HEIGHT - 1] = [blank(self.attributes); BUFFER_WIDTH];
+        for (row, line) in self.screen.iter().enumerate() {
+            self.buffer.write_line(row, line);
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
-        self.column_position = 0;
     }
 
-    fn clear_row(&mut self, row: usize) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.colour_code,
-        };
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.write(row, col, blank);
+    // shows the view `view_offset` lines back
+    fn redraw(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            // the lines from the scrollback come first, then the live screen
+            let line = if row < self.view_offset {
+                *self.scrollback.get(self.view_offset - 1 - row)
+            } else {
+                self.screen[row - self.view_offset]
+            };
+            self.buffer.write_line(row, &line);
+        }
+        // the cursor is on the live screen
+        self.buffer
+            .show_cursor(self.cursor_visible && self.view_offset == 0);
+    }
+
+    // scrolls the view back into the history (negative `lines` go forward)
+    pub(super) fn scroll_view(&mut self, lines: isize) {
+        let offset = self
+            .view_offset
+            .saturating_add_signed(lines)
+            .min(self.scrollback.len);
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.redraw();
+        }
+    }
+
+    fn write_string(&mut self, s: &str) {
+        if !self.initialised {
+            self.initialised = true;
+            self.redraw();
+        }
+        // new output jumps back to the live screen
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.redraw();
+        }
+
+        for c in s.chars() {
+            if let Some(action) = self.parser.advance(c) {
+                self.handle(action);
+            }
         }
+        self.buffer
+            .move_cursor(self.row, self.col.min(BUFFER_WIDTH - 1));
     }
 }
 
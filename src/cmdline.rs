@@ -0,0 +1,161 @@
+use alloc::string::String;
+use bitflags::bitflags;
+use log::{info, warn, LevelFilter};
+use spin::Once;
+
+bitflags! {
+    // where `print!` writes to
+    pub(crate) struct Console: u8 {
+        const VGA    = 1 << 0;
+        const SERIAL = 1 << 1;
+    }
+}
+
+// the kernel parameters, set from the command line in the `multiboot2` line of `grub.cfg`
+// e.g. `multiboot2 /boot/kernel loglevel=trace console=serial sched.quantum_ns=50000000`
+#[derive(Debug)]
+pub(crate) struct Params {
+    pub(crate) loglevel: LevelFilter,
+    pub(crate) console: Console,
+    // how long a thread runs before it's preempted (if there's anything else to run)
+    pub(crate) sched_quantum_ns: u64,
+    // the first userspace program, started from the initrd
+    pub(crate) init: &'static str,
+    // start the application processors
+    pub(crate) smp: bool,
+    // width, height and bits per pixel to switch the display to once its driver is up
+    pub(crate) video: Option<(usize, usize, u8)>,
+    // layout of the keyboard
+    pub(crate) keymap: &'static str,
+}
+
+// used till the command line is parsed
+const DEFAULT: Params = Params {
+    loglevel: LevelFilter::Info,
+    console: Console::all(),
+    // 100 ms
+    sched_quantum_ns: 10u64.pow(8),
+    init: "/init",
+    smp: true,
+    video: None,
+    keymap: "us",
+};
+
+struct Param {
+    name: &'static str,
+    // parses the value and stores it, fails if the value is not valid
+    set: fn(&mut Params, &str) -> Option<()>,
+}
+
+static PARAMS_TABLE: &[Param] = &[
+    Param {
+        name: "loglevel",
+        // off, error, warn, info, debug or trace
+        set: |params, value| {
+            params.loglevel = value.parse().ok()?;
+            Some(())
+        },
+    },
+    Param {
+        name: "console",
+        // comma separated list, e.g. `console=vga,serial`
+        set: |params, value| {
+            let mut console = Console::empty();
+            for name in value.split(',') {
+                console |= match name {
+                    "vga" => Console::VGA,
+                    "serial" => Console::SERIAL,
+                    _ => return None,
+                };
+            }
+            params.console = console;
+            Some(())
+        },
+    },
+    Param {
+        name: "sched.quantum_ns",
+        set: |params, value| {
+            params.sched_quantum_ns = value.parse().ok().filter(|&ns| ns > 0)?;
+            Some(())
+        },
+    },
+    Param {
+        name: "init",
+        set: |params, value| {
+            if !value.starts_with('/') {
+                return None;
+            }
+            // the command line is in memory that's not reserved
+            params.init = String::from(value).leak();
+            Some(())
+        },
+    },
+    Param {
+        name: "smp",
+        set: |params, value| {
+            params.smp = match value {
+                "on" => true,
+                "off" => false,
+                _ => return None,
+            };
+            Some(())
+        },
+    },
+    Param {
+        name: "video",
+        // `1280x1024` or `1280x1024x16`, 32 bits per pixel if not given
+        set: |params, value| {
+            let mut parts = value.split('x');
+            let width = parts.next()?.parse().ok()?;
+            let height = parts.next()?.parse().ok()?;
+            let bpp = match parts.next() {
+                Some(bpp) => bpp.parse().ok()?,
+                None => 32,
+            };
+            if parts.next().is_some() {
+                return None;
+            }
+            params.video = Some((width, height, bpp));
+            Some(())
+        },
+    },
+    Param {
+        name: "keymap",
+        // checked by the keyboard driver, it knows the layouts
+        set: |params, value| {
+            if value.is_empty() {
+                return None;
+            }
+            params.keymap = String::from(value).leak();
+            Some(())
+        },
+    },
+];
+
+static PARAMS: Once<Params> = Once::new();
+
+// the parameters from the command line, or the defaults if it's not been parsed yet
+pub(crate) fn params() -> &'static Params {
+    PARAMS.r#try().unwrap_or(&DEFAULT)
+}
+
+// space separated `name=value` pairs, the unknown and invalid ones are ignored
+// needs the heap
+pub(crate) fn init(cmdline: &str) {
+    let mut params = DEFAULT;
+    for arg in cmdline.split_whitespace() {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        match PARAMS_TABLE.iter().find(|param| param.name == name) {
+            Some(param) => {
+                if (param.set)(&mut params, value).is_none() {
+                    warn!("[cmdline] invalid value for {}: {:?}", name, value);
+                }
+            }
+            None => warn!("[cmdline] unknown parameter {:?}", arg),
+        }
+    }
+
+    log::set_max_level(params.loglevel);
+    info!("[cmdline] {:?}", params);
+    PARAMS.call_once(|| params);
+}
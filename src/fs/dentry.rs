@@ -0,0 +1,69 @@
+use super::{error::FsResult, inode::Inode, mount};
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+// a resolved path component
+// keeps a link to its parent, so that `..` goes back the way the path came
+// (which is what makes `..` work across the mount points)
+pub(crate) struct Dentry {
+    pub(crate) name: String,
+    // the root of the mounted filesystem, if something is mounted here
+    pub(crate) inode: Arc<dyn Inode>,
+    pub(crate) parent: Option<Arc<Dentry>>,
+}
+
+impl Dentry {
+    pub(super) fn root() -> FsResult<Arc<Self>> {
+        Ok(Arc::new(Self {
+            name: String::from("/"),
+            inode: mount::root()?,
+            parent: None,
+        }))
+    }
+
+    pub(super) fn lookup(self: &Arc<Self>, name: &str) -> FsResult<Arc<Self>> {
+        match name {
+            "." => Ok(self.clone()),
+            // `..` of the root is the root itself
+            ".." => Ok(self.parent.clone().unwrap_or_else(|| self.clone())),
+            _ => {
+                let inode = self.inode.lookup(name)?;
+                Ok(Arc::new(Self {
+                    name: String::from(name),
+                    inode: mount::covering(inode),
+                    parent: Some(self.clone()),
+                }))
+            }
+        }
+    }
+
+    // absolute path of the dentry
+    pub(crate) fn path(&self) -> String {
+        let mut names = Vec::new();
+        let mut cur = Some(self);
+        while let Some(dentry) = cur {
+            if dentry.parent.is_some() {
+                names.push(dentry.name.as_str());
+            }
+            cur = dentry.parent.as_deref();
+        }
+
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+}
+
+impl core::fmt::Debug for Dentry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Dentry")
+            .field("path", &self.path())
+            .field("inode", &self.inode.metadata())
+            .finish()
+    }
+}
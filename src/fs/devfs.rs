@@ -0,0 +1,147 @@
+use super::{
+    error::{FsError, FsResult},
+    inode::{new_dev_id, DirEntry, FileSystem, FileType, Inode, Metadata},
+};
+use crate::locks::SpinLock;
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use log::info;
+use spin::Once;
+
+// a character device, what the drivers register to be visible under `/dev`
+// there's no position, reads and writes go straight to the driver
+// they may block till there's something to read
+pub(crate) trait CharDevice: Send + Sync {
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize>;
+
+    fn write(&self, _buf: &[u8]) -> FsResult<usize> {
+        Err(FsError::InvalidArgument)
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: u64) -> FsResult<u64> {
+        Err(FsError::InappropriateIoctl)
+    }
+}
+
+// the registered devices, flat: there are no subdirectories
+// sorted by name, so that `readdir` is stable
+static DEVICES: SpinLock<BTreeMap<String, Arc<DevInode>>> = SpinLock::new(BTreeMap::new());
+
+// the dev id of the filesystem, shared by all the instances
+static DEV: Once<u64> = Once::new();
+
+fn dev() -> u64 {
+    *DEV.call_once(new_dev_id)
+}
+
+// the devices the drivers registered
+// reflects the registrations made at any point, before or after mounting
+pub(crate) struct DevFs {
+    root: Arc<DevRoot>,
+}
+
+struct DevRoot;
+
+struct DevInode {
+    ino: u64,
+    device: Arc<dyn CharDevice>,
+}
+
+impl DevFs {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            root: Arc::new(DevRoot),
+        })
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+// makes `device` visible as `/dev/<name>`
+pub(crate) fn register_device(name: &str, device: Arc<dyn CharDevice>) -> FsResult<()> {
+    let mut devices = DEVICES.lock();
+    if devices.contains_key(name) {
+        return Err(FsError::AlreadyExists);
+    }
+    // the root is 1
+    let ino = devices.len() as u64 + 2;
+    devices.insert(String::from(name), Arc::new(DevInode { ino, device }));
+    drop(devices);
+
+    info!("[devfs] registered /dev/{}", name);
+    Ok(())
+}
+
+impl Inode for DevRoot {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: dev(),
+            ino: 1,
+            kind: FileType::Directory,
+            mode: 0o755,
+            nlink: 2,
+            size: DEVICES.lock().len() as u64,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        DEVICES
+            .lock()
+            .get(name)
+            .map(|inode| inode.clone() as Arc<dyn Inode>)
+            .ok_or(FsError::NotFound)
+    }
+
+    // only the drivers add entries
+    fn create(&self, _name: &str, _kind: FileType, _mode: u16) -> FsResult<Arc<dyn Inode>> {
+        Err(FsError::InvalidArgument)
+    }
+
+    fn unlink(&self, _name: &str) -> FsResult<()> {
+        Err(FsError::InvalidArgument)
+    }
+
+    fn readdir(&self, index: usize) -> FsResult<Option<DirEntry>> {
+        Ok(DEVICES
+            .lock()
+            .iter()
+            .nth(index)
+            .map(|(name, inode)| DirEntry {
+                ino: inode.ino,
+                name: name.clone(),
+                kind: FileType::CharDevice,
+            }))
+    }
+}
+
+impl Inode for DevInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            dev: dev(),
+            ino: self.ino,
+            kind: FileType::CharDevice,
+            mode: 0o666,
+            nlink: 1,
+            size: 0,
+        }
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> FsResult<usize> {
+        self.device.read(buf)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> FsResult<usize> {
+        self.device.write(buf)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: u64) -> FsResult<u64> {
+        self.device.ioctl(cmd, arg)
+    }
+}
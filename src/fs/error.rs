@@ -0,0 +1,28 @@
+// errors returned by the VFS and the filesystems
+// the syscall layer turns them into the errno values the userspace expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    NotEmpty,
+    InvalidArgument,
+    NameTooLong,
+    NoSpace,
+    // lseek on something that has no position (devices, pipes)
+    NotSeekable,
+    // not open or not open for the requested access
+    BadDescriptor,
+    TooManyOpenFiles,
+    // mount point in use
+    Busy,
+    // an ioctl the file doesn't know, e.g. a terminal one on something that's not a terminal
+    InappropriateIoctl,
+    // a pointer from the userspace that's not mapped
+    BadAddress,
+    // a blocking call cut short by an interrupt (^C)
+    Interrupted,
+}
+
+pub(crate) type FsResult<T> = Result<T, FsError>;
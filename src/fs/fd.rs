@@ -0,0 +1,68 @@
+use super::{
+    error::{FsError, FsResult},
+    file::File,
+};
+use alloc::{sync::Arc, vec::Vec};
+
+// most files a process can have open at once
+const MAX_FDS: usize = 256;
+
+// file descriptors of a process, shared by all its threads
+pub(crate) struct FdTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FdTable {
+    pub(crate) const fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    // the new descriptor is the lowest one not in use
+    pub(crate) fn insert(&mut self, file: Arc<dyn File>) -> FsResult<usize> {
+        let fd = match self.files.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None if self.files.len() < MAX_FDS => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return Err(FsError::TooManyOpenFiles),
+        };
+        self.files[fd] = Some(file);
+        Ok(fd)
+    }
+
+    pub(crate) fn get(&self, fd: usize) -> FsResult<Arc<dyn File>> {
+        self.files
+            .get(fd)
+            .and_then(Option::clone)
+            .ok_or(FsError::BadDescriptor)
+    }
+
+    // the file is closed once the last reference to it is dropped
+    pub(crate) fn remove(&mut self, fd: usize) -> FsResult<Arc<dyn File>> {
+        let file = self
+            .files
+            .get_mut(fd)
+            .and_then(Option::take)
+            .ok_or(FsError::BadDescriptor)?;
+        // keep the table short
+        while let Some(None) = self.files.last() {
+            self.files.pop();
+        }
+        Ok(file)
+    }
+}
+
+impl core::fmt::Debug for FdTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.files
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, file)| file.is_some())
+                    .map(|(fd, _)| fd),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,165 @@
+use super::{
+    error::{FsError, FsResult},
+    inode::{DirEntry, FileType, Inode, Metadata},
+};
+use crate::locks::Mutex;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+
+bitflags! {
+    pub(crate) struct OpenFlags: u32 {
+        const READ      = 1 << 0;
+        const WRITE     = 1 << 1;
+        // create the file if it doesn't exist
+        const CREATE    = 1 << 2;
+        // with CREATE, fail if the file exists
+        const EXCLUSIVE = 1 << 3;
+        // cut the file to 0 bytes
+        const TRUNCATE  = 1 << 4;
+        // every write goes to the end of the file
+        const APPEND    = 1 << 5;
+        // fail if it's not a directory
+        const DIRECTORY = 1 << 6;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+// an open file, what a file descriptor refers to
+// shared by the descriptors duplicated from each other
+pub(crate) trait File: Send + Sync {
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize>;
+    fn write(&self, buf: &[u8]) -> FsResult<usize>;
+    fn stat(&self) -> FsResult<Metadata>;
+
+    // returns the new position
+    fn seek(&self, _pos: SeekFrom) -> FsResult<u64> {
+        Err(FsError::NotSeekable)
+    }
+
+    // passes the directory entries from the current position on to `emit`
+    // till it returns false (the entry it was given is not consumed in that case)
+    fn readdir(&self, _emit: &mut dyn FnMut(&DirEntry) -> bool) -> FsResult<()> {
+        Err(FsError::NotADirectory)
+    }
+
+    // device specific requests, `arg` is passed on as is (usually a pointer to the userspace)
+    fn ioctl(&self, _cmd: u32, _arg: u64) -> FsResult<u64> {
+        Err(FsError::InappropriateIoctl)
+    }
+}
+
+// a file backed by an inode, reads and writes go through its position
+pub(crate) struct InodeFile {
+    inode: Arc<dyn Inode>,
+    flags: OpenFlags,
+    // bytes for the regular files, entries for the directories
+    // a sleeping lock, as the reads and writes can sleep (page faults, the filesystem's own locks)
+    offset: Mutex<u64>,
+}
+
+impl InodeFile {
+    pub(crate) fn new(inode: Arc<dyn Inode>, flags: OpenFlags) -> Self {
+        Self {
+            inode,
+            flags,
+            offset: Mutex::new(0),
+        }
+    }
+}
+
+impl File for InodeFile {
+    fn read(&self, buf: &mut [u8]) -> FsResult<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(FsError::BadDescriptor);
+        }
+        // devices have no position, and a read waiting for input (eg: on a terminal)
+        // shouldn't hold up the writes through the same file
+        if self.inode.metadata().kind == FileType::CharDevice {
+            return self.inode.read_at(0, buf);
+        }
+        let mut offset = self.offset.lock();
+        let read = self.inode.read_at(*offset, buf)?;
+        *offset += read as u64;
+        Ok(read)
+    }
+
+    fn write(&self, buf: &[u8]) -> FsResult<usize> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(FsError::BadDescriptor);
+        }
+        if self.inode.metadata().kind == FileType::CharDevice {
+            return self.inode.write_at(0, buf);
+        }
+        let mut offset = self.offset.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *offset = self.inode.metadata().size;
+        }
+        let written = self.inode.write_at(*offset, buf)?;
+        *offset += written as u64;
+        Ok(written)
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(self.inode.metadata())
+    }
+
+    fn seek(&self, pos: SeekFrom) -> FsResult<u64> {
+        let metadata = self.inode.metadata();
+        if metadata.kind == FileType::CharDevice {
+            return Err(FsError::NotSeekable);
+        }
+
+        let mut offset = self.offset.lock();
+        let new = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => metadata.size.checked_add_signed(delta),
+        }
+        .ok_or(FsError::InvalidArgument)?;
+        // seeking past the end is fine, the gap reads as zeroes once written to
+        *offset = new;
+        Ok(new)
+    }
+
+    fn readdir(&self, emit: &mut dyn FnMut(&DirEntry) -> bool) -> FsResult<()> {
+        let metadata = self.inode.metadata();
+        if metadata.kind != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+
+        let mut offset = self.offset.lock();
+        loop {
+            let entry = match *offset {
+                // the filesystems don't keep `.` and `..`
+                // the inode number of `..` is not known here (the parent may be on another filesystem)
+                0 | 1 => Some(DirEntry {
+                    ino: metadata.ino,
+                    name: if *offset == 0 {
+                        ".".into()
+                    } else {
+                        "..".into()
+                    },
+                    kind: FileType::Directory,
+                }),
+                n => self.inode.readdir(n as usize - 2)?,
+            };
+            let Some(entry) = entry else {
+                return Ok(());
+            };
+            if !emit(&entry) {
+                return Ok(());
+            }
+            *offset += 1;
+        }
+    }
+
+    fn ioctl(&self, cmd: u32, arg: u64) -> FsResult<u64> {
+        self.inode.ioctl(cmd, arg)
+    }
+}
@@ -0,0 +1,414 @@
+use super::{
+    error::{FsError, FsResult},
+    file::OpenFlags,
+};
+use alloc::{format, string::String};
+use log::{info, warn};
+
+// file type bits of the mode (cpio keeps them in the mode, tar in the type flag)
+const S_IFMT: u64 = 0o170000;
+const S_IFDIR: u64 = 0o040000;
+const S_IFREG: u64 = 0o100000;
+
+const CPIO_HEADER_LEN: usize = 110;
+const CPIO_TRAILER: &[u8] = b"TRAILER!!!";
+const TAR_BLOCK: usize = 512;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Entry<'a> {
+    Dir { mode: u16 },
+    File { mode: u16, data: &'a [u8] },
+    // symlinks, device nodes, hard links... not supported by the VFS yet
+    Other,
+}
+
+// unpacks a newc cpio (what the Linux initramfs uses) or a ustar archive
+// into the root filesystem
+pub(crate) fn unpack_initramfs(archive: &[u8]) -> FsResult<()> {
+    let count = if archive.starts_with(b"070701") || archive.starts_with(b"070702") {
+        unpack_cpio(archive, add)?
+    } else if archive.get(257..262) == Some(&b"ustar"[..]) {
+        unpack_tar(archive, add)?
+    } else {
+        return Err(FsError::InvalidArgument);
+    };
+    info!("[initramfs] unpacked {} entries", count);
+    Ok(())
+}
+
+// each entry is a header (hex ASCII fields), the name and the data
+// the name and the data are padded to 4 bytes
+// the entries are handed to `add` as they're found
+fn unpack_cpio<'a>(
+    archive: &'a [u8],
+    mut add: impl FnMut(&str, Entry<'a>) -> FsResult<()>,
+) -> FsResult<usize> {
+    let mut pos = 0;
+    let mut count = 0;
+    loop {
+        let header = archive
+            .get(pos..pos + CPIO_HEADER_LEN)
+            .ok_or(FsError::InvalidArgument)?;
+        if &header[..6] != b"070701" && &header[..6] != b"070702" {
+            return Err(FsError::InvalidArgument);
+        }
+        // the fields after the magic: ino, mode, uid, gid, nlink, mtime, filesize,
+        // devmajor, devminor, rdevmajor, rdevminor, namesize, check
+        let field = |i: usize| parse_number(&header[6 + i * 8..6 + (i + 1) * 8], 16);
+        let mode = field(1)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = pos + CPIO_HEADER_LEN;
+        let name = archive
+            .get(name_start..name_start + name_size)
+            .ok_or(FsError::InvalidArgument)?;
+        // the size includes the null byte
+        let name = parse_str(name)?;
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let data = archive
+            .get(data_start..data_start + file_size)
+            .ok_or(FsError::InvalidArgument)?;
+        pos = (data_start + file_size).next_multiple_of(4);
+
+        if name.as_bytes() == CPIO_TRAILER {
+            return Ok(count);
+        }
+
+        let perm = (mode & 0o7777) as u16;
+        let entry = match mode & S_IFMT {
+            S_IFDIR => Entry::Dir { mode: perm },
+            S_IFREG => Entry::File { mode: perm, data },
+            _ => Entry::Other,
+        };
+        add(name, entry)?;
+        count += 1;
+    }
+}
+
+// 512 byte header blocks (octal ASCII fields), each followed by the data rounded up to a block
+// the archive ends with (at least) one block of zeroes
+fn unpack_tar<'a>(
+    archive: &'a [u8],
+    mut add: impl FnMut(&str, Entry<'a>) -> FsResult<()>,
+) -> FsResult<usize> {
+    let mut pos = 0;
+    let mut count = 0;
+    // some tools don't write the end of archive blocks
+    while pos < archive.len() {
+        let header = archive
+            .get(pos..pos + TAR_BLOCK)
+            .ok_or(FsError::InvalidArgument)?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if &header[257..262] != b"ustar" {
+            return Err(FsError::InvalidArgument);
+        }
+        let mode = parse_number(&header[100..108], 8)?;
+        let size = parse_number(&header[124..136], 8)? as usize;
+        let kind = header[156];
+
+        let data_start = pos + TAR_BLOCK;
+        let data = archive
+            .get(data_start..data_start + size)
+            .ok_or(FsError::InvalidArgument)?;
+        pos = data_start + size.next_multiple_of(TAR_BLOCK);
+
+        // long names are split in a prefix and a name
+        let name = parse_str(&header[..100])?;
+        let prefix = parse_str(&header[345..500])?;
+        let path = if prefix.is_empty() {
+            String::from(name)
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        let perm = (mode & 0o7777) as u16;
+        let entry = match kind {
+            b'0' | b'\0' => Entry::File { mode: perm, data },
+            b'5' => Entry::Dir { mode: perm },
+            _ => Entry::Other,
+        };
+        add(&path, entry)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// the archives usually hold relative paths (`./init`, `bin/sh`), all of them are relative to `/`
+fn add(path: &str, entry: Entry) -> FsResult<()> {
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    if path.is_empty() || path == "." {
+        return Ok(());
+    }
+    let path = format!("/{}", path);
+
+    // the archives don't have to list the directories before their contents
+    for (i, _) in path.match_indices('/').skip(1) {
+        match super::mkdir(&path[..i], 0o755) {
+            Ok(()) | Err(FsError::AlreadyExists) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    match entry {
+        Entry::Dir { mode } => match super::mkdir(&path, mode) {
+            Ok(()) | Err(FsError::AlreadyExists) => Ok(()),
+            Err(err) => Err(err),
+        },
+        Entry::File { mode, data } => {
+            let file = super::open(
+                &path,
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+                mode,
+            )?;
+            let mut written = 0;
+            while written < data.len() {
+                written += file.write(&data[written..])?;
+            }
+            Ok(())
+        }
+        Entry::Other => {
+            warn!("[initramfs] skipping {}, unsupported file type", path);
+            Ok(())
+        }
+    }
+}
+
+// fixed width number fields, padded with spaces or null bytes
+fn parse_number(field: &[u8], radix: u32) -> FsResult<u64> {
+    let field = core::str::from_utf8(field).map_err(|_| FsError::InvalidArgument)?;
+    let field = field.trim_matches(|c: char| c == ' ' || c == '\0');
+    if field.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(field, radix).map_err(|_| FsError::InvalidArgument)
+}
+
+// null terminated (or filling the whole field) string
+fn parse_str(field: &[u8]) -> FsResult<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).map_err(|_| FsError::InvalidArgument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    // the entries of the archive, or the error it's rejected with
+    fn entries<'a>(
+        archive: &'a [u8],
+        unpack: fn(&'a [u8], &mut dyn FnMut(&str, Entry<'a>) -> FsResult<()>) -> FsResult<usize>,
+    ) -> FsResult<Vec<(String, Entry<'a>)>> {
+        let mut entries = Vec::new();
+        let count = unpack(archive, &mut |path, entry| {
+            entries.push((String::from(path), entry));
+            Ok(())
+        })?;
+        assert_eq!(count, entries.len());
+        Ok(entries)
+    }
+
+    fn cpio(archive: &[u8]) -> FsResult<Vec<(String, Entry)>> {
+        entries(archive, |archive, add| unpack_cpio(archive, add))
+    }
+
+    fn tar(archive: &[u8]) -> FsResult<Vec<(String, Entry)>> {
+        entries(archive, |archive, add| unpack_tar(archive, add))
+    }
+
+    fn cpio_entry(archive: &mut Vec<u8>, name: &str, mode: u64, data: &[u8]) {
+        archive.extend_from_slice(b"070701");
+        let name_size = name.len() as u64 + 1;
+        let file_size = data.len() as u64;
+        // ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor, rdevminor,
+        // namesize, check
+        let fields = [0, mode, 0, 0, 1, 0, file_size, 0, 0, 0, 0, name_size, 0];
+        for field in fields {
+            archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    fn cpio_archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+        cpio_entry(&mut archive, ".", S_IFDIR | 0o755, b"");
+        cpio_entry(&mut archive, "bin", S_IFDIR | 0o755, b"");
+        cpio_entry(&mut archive, "bin/init", S_IFREG | 0o700, b"\x7fELF");
+        cpio_entry(&mut archive, "dev/null", 0o020666, b"");
+        cpio_entry(&mut archive, "TRAILER!!!", 0, b"");
+        archive
+    }
+
+    fn tar_entry(archive: &mut Vec<u8>, prefix: &str, name: &str, kind: u8, data: &[u8]) {
+        let mut header = [0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(TAR_BLOCK), 0);
+    }
+
+    fn tar_archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+        tar_entry(&mut archive, "", "etc/", b'5', b"");
+        tar_entry(&mut archive, "", "etc/motd", b'0', b"hello");
+        tar_entry(&mut archive, "usr/share", "doc/README", b'0', &[b'x'; 600]);
+        archive.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        archive
+    }
+
+    #[test]
+    fn cpio_entries() {
+        let archive = cpio_archive();
+        let entries = cpio(&archive).unwrap();
+        let paths = entries
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [".", "bin", "bin/init", "dev/null"]);
+        assert_eq!(entries[1].1, Entry::Dir { mode: 0o755 });
+        assert_eq!(
+            entries[2].1,
+            Entry::File {
+                mode: 0o700,
+                data: b"\x7fELF"
+            }
+        );
+        assert_eq!(entries[3].1, Entry::Other);
+    }
+
+    #[test]
+    fn cpio_truncated() {
+        let archive = cpio_archive();
+        // empty, in the middle of a header, of a name, of the data of `bin/init`, and no trailer
+        let trailer = (CPIO_HEADER_LEN + CPIO_TRAILER.len() + 1).next_multiple_of(4);
+        for len in [0, 50, CPIO_HEADER_LEN + 1, 350, archive.len() - trailer] {
+            assert_eq!(
+                cpio(&archive[..len]),
+                Err(FsError::InvalidArgument),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn cpio_bad_header() {
+        let mut archive = cpio_archive();
+        archive[..6].copy_from_slice(b"070707");
+        assert_eq!(cpio(&archive), Err(FsError::InvalidArgument));
+
+        let mut archive = cpio_archive();
+        // the mode of the first entry
+        archive[14..22].copy_from_slice(b"0000zz00");
+        assert_eq!(cpio(&archive), Err(FsError::InvalidArgument));
+    }
+
+    #[test]
+    fn cpio_size_past_the_end() {
+        let mut archive = Vec::new();
+        cpio_entry(&mut archive, "init", S_IFREG | 0o755, b"abcd");
+        // the file size
+        archive[54..62].copy_from_slice(b"ffffffff");
+        assert_eq!(cpio(&archive), Err(FsError::InvalidArgument));
+
+        let mut archive = Vec::new();
+        cpio_entry(&mut archive, "init", S_IFREG | 0o755, b"abcd");
+        // the name size
+        archive[94..102].copy_from_slice(b"00010000");
+        assert_eq!(cpio(&archive), Err(FsError::InvalidArgument));
+    }
+
+    #[test]
+    fn tar_entries() {
+        let archive = tar_archive();
+        let entries = tar(&archive).unwrap();
+        let paths = entries
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["etc/", "etc/motd", "usr/share/doc/README"]);
+        assert_eq!(entries[0].1, Entry::Dir { mode: 0o644 });
+        assert_eq!(
+            entries[1].1,
+            Entry::File {
+                mode: 0o644,
+                data: b"hello"
+            }
+        );
+        assert!(matches!(entries[2].1, Entry::File { data, .. } if data == [b'x'; 600]));
+    }
+
+    #[test]
+    fn tar_without_end_blocks() {
+        let archive = tar_archive();
+        assert_eq!(
+            tar(&archive[..archive.len() - 2 * TAR_BLOCK])
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn tar_truncated() {
+        let archive = tar_archive();
+        // in the middle of the first header, of the second one, and of the data of the last entry
+        for len in [100, TAR_BLOCK + 10, 4 * TAR_BLOCK + 100] {
+            assert_eq!(
+                tar(&archive[..len]),
+                Err(FsError::InvalidArgument),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn tar_bad_header() {
+        let mut archive = tar_archive();
+        // the magic of the second entry
+        archive[TAR_BLOCK + 257..TAR_BLOCK + 262].copy_from_slice(b"xxxxx");
+        assert_eq!(tar(&archive), Err(FsError::InvalidArgument));
+
+        let mut archive = tar_archive();
+        // not octal
+        archive[124..136].copy_from_slice(b"0000000009\0\0");
+        assert_eq!(tar(&archive), Err(FsError::InvalidArgument));
+    }
+
+    #[test]
+    fn tar_size_past_the_end() {
+        let mut archive = tar_archive();
+        archive[TAR_BLOCK + 124..TAR_BLOCK + 136].copy_from_slice(b"77777777777\0");
+        assert_eq!(tar(&archive), Err(FsError::InvalidArgument));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(unpack_initramfs(b""), Err(FsError::InvalidArgument));
+        assert_eq!(unpack_initramfs(&[0; 1024]), Err(FsError::InvalidArgument));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number(b"0000644\0", 8), Ok(0o644));
+        assert_eq!(parse_number(b"   12 \0\0", 8), Ok(0o12));
+        assert_eq!(parse_number(b"000001Ff", 16), Ok(0x1ff));
+        assert_eq!(parse_number(b"\0\0\0\0", 8), Ok(0));
+        assert_eq!(parse_number(b"0000008\0", 8), Err(FsError::InvalidArgument));
+        assert_eq!(parse_number(b"00 12", 8), Err(FsError::InvalidArgument));
+        assert_eq!(parse_number(b"\xff", 8), Err(FsError::InvalidArgument));
+    }
+}
@@ -0,0 +1,99 @@
+use super::error::{FsError, FsResult};
+use alloc::{string::String, sync::Arc};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// longest name of a single path component
+pub(crate) const NAME_MAX: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileType {
+    Regular,
+    Directory,
+    CharDevice,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Metadata {
+    // identifies the filesystem (instance) the inode belongs to
+    pub(crate) dev: u64,
+    // unique within the filesystem
+    pub(crate) ino: u64,
+    pub(crate) kind: FileType,
+    // permission bits (rwxrwxrwx), not enforced yet
+    pub(crate) mode: u16,
+    pub(crate) nlink: u32,
+    pub(crate) size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DirEntry {
+    pub(crate) ino: u64,
+    pub(crate) name: String,
+    pub(crate) kind: FileType,
+}
+
+// a file, directory or device as seen by a filesystem
+// the defaults are for the operations the inode doesn't support
+pub(crate) trait Inode: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> FsResult<usize> {
+        Err(self.not_a_file())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> FsResult<usize> {
+        Err(self.not_a_file())
+    }
+
+    fn truncate(&self, _size: u64) -> FsResult<()> {
+        Err(self.not_a_file())
+    }
+
+    // directories only
+    fn lookup(&self, _name: &str) -> FsResult<Arc<dyn Inode>> {
+        Err(FsError::NotADirectory)
+    }
+
+    // creates a new entry called `name` in this directory
+    fn create(&self, _name: &str, _kind: FileType, _mode: u16) -> FsResult<Arc<dyn Inode>> {
+        Err(FsError::NotADirectory)
+    }
+
+    // removes the entry called `name` from this directory
+    // directories have to be empty
+    fn unlink(&self, _name: &str) -> FsResult<()> {
+        Err(FsError::NotADirectory)
+    }
+
+    // the `index`th entry of this directory, `None` past the last one
+    // `.` and `..` are not included, the VFS takes care of them
+    fn readdir(&self, _index: usize) -> FsResult<Option<DirEntry>> {
+        Err(FsError::NotADirectory)
+    }
+
+    // devices only, see `File::ioctl`
+    fn ioctl(&self, _cmd: u32, _arg: u64) -> FsResult<u64> {
+        Err(FsError::InappropriateIoctl)
+    }
+
+    fn not_a_file(&self) -> FsError {
+        if self.metadata().kind == FileType::Directory {
+            FsError::IsADirectory
+        } else {
+            FsError::InvalidArgument
+        }
+    }
+}
+
+// a filesystem instance, made visible in the tree by mounting it
+pub(crate) trait FileSystem: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> Arc<dyn Inode>;
+}
+
+static NEXT_DEV: AtomicU64 = AtomicU64::new(1);
+
+// to be used by the filesystems to fill in `Metadata::dev`
+pub(crate) fn new_dev_id() -> u64 {
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
@@ -0,0 +1,118 @@
+mod dentry;
+mod devfs;
+mod error;
+mod fd;
+mod file;
+mod initramfs;
+mod inode;
+mod mount;
+mod path;
+mod ramfs;
+
+pub(crate) use devfs::{register_device, CharDevice};
+pub(crate) use error::{FsError, FsResult};
+pub(crate) use fd::FdTable;
+pub(crate) use file::{File, OpenFlags, SeekFrom};
+pub(crate) use inode::{FileType, Metadata};
+pub(crate) use path::PATH_MAX;
+
+use crate::multiboot::MultibootInfo;
+use alloc::sync::Arc;
+use devfs::DevFs;
+use file::InodeFile;
+use initramfs::unpack_initramfs;
+use log::{info, warn};
+use mount::mount;
+use path::{resolve, resolve_parent};
+use ramfs::RamFs;
+
+// opens (and creates, if asked to) the file at `path`
+// `mode` is used only when a new file is created
+pub(crate) fn open(path: &str, flags: OpenFlags, mode: u16) -> FsResult<Arc<dyn File>> {
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        let (dir, name) = resolve_parent(path)?;
+        match dir.lookup(name) {
+            Ok(_) if flags.contains(OpenFlags::EXCLUSIVE) => return Err(FsError::AlreadyExists),
+            Ok(dentry) => dentry.inode.clone(),
+            Err(FsError::NotFound) => dir.inode.create(name, FileType::Regular, mode)?,
+            Err(err) => return Err(err),
+        }
+    } else {
+        resolve(path)?.inode.clone()
+    };
+
+    let kind = inode.metadata().kind;
+    if flags.contains(OpenFlags::DIRECTORY) && kind != FileType::Directory {
+        return Err(FsError::NotADirectory);
+    }
+    if kind == FileType::Directory && flags.contains(OpenFlags::WRITE) {
+        return Err(FsError::IsADirectory);
+    }
+    if kind == FileType::Regular && flags.contains(OpenFlags::TRUNCATE | OpenFlags::WRITE) {
+        inode.truncate(0)?;
+    }
+
+    Ok(Arc::new(InodeFile::new(inode, flags)))
+}
+
+pub(crate) fn stat(path: &str) -> FsResult<Metadata> {
+    Ok(resolve(path)?.inode.metadata())
+}
+
+pub(crate) fn mkdir(path: &str, mode: u16) -> FsResult<()> {
+    let (dir, name) = resolve_parent(path)?;
+    dir.inode.create(name, FileType::Directory, mode)?;
+    Ok(())
+}
+
+// removes anything but a directory
+pub(crate) fn unlink(path: &str) -> FsResult<()> {
+    let (dir, name) = resolve_parent(path)?;
+    if dir.inode.lookup(name)?.metadata().kind == FileType::Directory {
+        return Err(FsError::IsADirectory);
+    }
+    dir.inode.unlink(name)
+}
+
+// removes an empty directory
+pub(crate) fn rmdir(path: &str) -> FsResult<()> {
+    let (dir, name) = resolve_parent(path)?;
+    // the directory itself, not what's mounted on it
+    let inode = dir.inode.lookup(name)?;
+    if inode.metadata().kind != FileType::Directory {
+        return Err(FsError::NotADirectory);
+    }
+    if mount::is_mountpoint(&inode) {
+        return Err(FsError::Busy);
+    }
+    dir.inode.unlink(name)
+}
+
+// mounts a RAM filesystem as the root
+// filled with the contents of the initrd modules, if the bootloader loaded any
+// the devices are under `/dev`
+pub(crate) fn init(multiboot_info: &MultibootInfo) {
+    mount("/", RamFs::new()).unwrap();
+
+    for module in multiboot_info
+        .multiboot_modules()
+        .filter(|module| module.is_initrd())
+    {
+        info!("[vfs] unpacking initrd {:?}", module.cmdline);
+        // SAFETY: the modules are never freed, nothing else writes to them
+        let archive = unsafe { module.bytes() };
+        if let Err(err) = unpack_initramfs(archive) {
+            warn!("[vfs] failed to unpack {:?}: {:?}", module.cmdline, err);
+        }
+    }
+
+    // the initrd may come with its own (empty) `/dev`
+    match mkdir("/dev", 0o755) {
+        Ok(()) | Err(FsError::AlreadyExists) => {}
+        Err(err) => warn!("[vfs] failed to create /dev: {:?}", err),
+    }
+    if let Err(err) = mount("/dev", DevFs::new()) {
+        warn!("[vfs] failed to mount devfs: {:?}", err);
+    }
+    info!("[vfs] initialised");
+}
@@ -0,0 +1,69 @@
+use super::{
+    error::{FsError, FsResult},
+    inode::{FileSystem, FileType, Inode},
+    path,
+};
+use crate::locks::RwLock;
+use alloc::{sync::Arc, vec::Vec};
+use log::info;
+
+struct Mount {
+    // (dev, ino) of the directory the filesystem is mounted on, `None` for the root
+    mountpoint: Option<(u64, u64)>,
+    fs: Arc<dyn FileSystem>,
+}
+
+// looked at on every step of every path walk, changed only by `mount`
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+fn key(inode: &Arc<dyn Inode>) -> (u64, u64) {
+    let metadata = inode.metadata();
+    (metadata.dev, metadata.ino)
+}
+
+// makes `fs` visible at `path`, which has to be an existing directory
+// (except for the very first mount, the root)
+pub(crate) fn mount(path: &str, fs: Arc<dyn FileSystem>) -> FsResult<()> {
+    let mountpoint = if path == "/" {
+        None
+    } else {
+        // resolved without holding the lock, the path resolution needs it
+        let dentry = path::resolve(path)?;
+        if dentry.inode.metadata().kind != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Some(key(&dentry.inode))
+    };
+
+    let mut mounts = MOUNTS.write();
+    if mounts.iter().any(|m| m.mountpoint == mountpoint) {
+        return Err(FsError::Busy);
+    }
+    info!("[vfs] mounting {} at {}", fs.name(), path);
+    mounts.push(Mount { mountpoint, fs });
+    Ok(())
+}
+
+pub(super) fn root() -> FsResult<Arc<dyn Inode>> {
+    MOUNTS
+        .read()
+        .iter()
+        .find(|m| m.mountpoint.is_none())
+        .map(|m| m.fs.root())
+        .ok_or(FsError::NotFound)
+}
+
+// the root of the filesystem mounted on `inode`, or `inode` itself if nothing is
+pub(super) fn covering(mut inode: Arc<dyn Inode>) -> Arc<dyn Inode> {
+    let mounts = MOUNTS.read();
+    // filesystems can be mounted on top of each other
+    while let Some(m) = mounts.iter().find(|m| m.mountpoint == Some(key(&inode))) {
+        inode = m.fs.root();
+    }
+    inode
+}
+
+pub(super) fn is_mountpoint(inode: &Arc<dyn Inode>) -> bool {
+    let key = key(inode);
+    MOUNTS.read().iter().any(|m| m.mountpoint == Some(key))
+}
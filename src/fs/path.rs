@@ -0,0 +1,51 @@
+use super::{
+    dentry::Dentry,
+    error::{FsError, FsResult},
+    inode::NAME_MAX,
+};
+use alloc::sync::Arc;
+
+// longest path accepted, including the terminating null byte
+pub(crate) const PATH_MAX: usize = 4096;
+
+// there's no current directory yet, relative paths are resolved from the root
+pub(crate) fn resolve(path: &str) -> FsResult<Arc<Dentry>> {
+    if path.is_empty() {
+        return Err(FsError::NotFound);
+    }
+    if path.len() >= PATH_MAX {
+        return Err(FsError::NameTooLong);
+    }
+
+    let mut dentry = Dentry::root()?;
+    for name in components(path) {
+        if name.len() > NAME_MAX {
+            return Err(FsError::NameTooLong);
+        }
+        dentry = dentry.lookup(name)?;
+    }
+    Ok(dentry)
+}
+
+// resolves everything but the last component
+// returns the directory the last component is in, and the last component
+// used when creating or removing an entry
+pub(crate) fn resolve_parent(path: &str) -> FsResult<(Arc<Dentry>, &str)> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        Some(pos) => (&path[..pos + 1], &path[pos + 1..]),
+        None => ("/", path),
+    };
+    // `/`, `.` and `..` can't be created or removed
+    if matches!(name, "" | "." | "..") {
+        return Err(FsError::InvalidArgument);
+    }
+    if name.len() > NAME_MAX {
+        return Err(FsError::NameTooLong);
+    }
+    Ok((resolve(dir)?, name))
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
@@ -0,0 +1,182 @@
+use super::{
+    error::{FsError, FsResult},
+    inode::{new_dev_id, DirEntry, FileSystem, FileType, Inode, Metadata},
+};
+use crate::locks::RwLock;
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// filesystem that lives entirely in the kernel heap
+// gone on reboot, used for the root filesystem till there's a disk driver
+pub(crate) struct RamFs {
+    root: Arc<RamInode>,
+}
+
+// shared by all the inodes of a filesystem
+struct RamFsInfo {
+    dev: u64,
+    next_ino: AtomicU64,
+}
+
+enum RamData {
+    File(Vec<u8>),
+    // sorted by name, so that `readdir` is stable
+    Dir(BTreeMap<String, Arc<RamInode>>),
+}
+
+struct RamInode {
+    ino: u64,
+    kind: FileType,
+    mode: u16,
+    fs: Arc<RamFsInfo>,
+    // lookups and reads (the bulk of the path walks) can go on side by side
+    data: RwLock<RamData>,
+}
+
+impl RamFs {
+    pub(crate) fn new() -> Arc<Self> {
+        let info = Arc::new(RamFsInfo {
+            dev: new_dev_id(),
+            next_ino: AtomicU64::new(1),
+        });
+        Arc::new(Self {
+            root: RamInode::new(info, FileType::Directory, 0o755),
+        })
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+impl RamInode {
+    fn new(fs: Arc<RamFsInfo>, kind: FileType, mode: u16) -> Arc<Self> {
+        let data = match kind {
+            FileType::Directory => RamData::Dir(BTreeMap::new()),
+            _ => RamData::File(Vec::new()),
+        };
+        Arc::new(Self {
+            ino: fs.next_ino.fetch_add(1, Ordering::Relaxed),
+            kind,
+            mode,
+            fs,
+            data: RwLock::new(data),
+        })
+    }
+}
+
+impl Inode for RamInode {
+    fn metadata(&self) -> Metadata {
+        let (size, nlink) = match &*self.data.read() {
+            RamData::File(data) => (data.len() as u64, 1),
+            // `.`, the entry in the parent and `..` of every subdirectory
+            RamData::Dir(entries) => (
+                entries.len() as u64,
+                2 + entries
+                    .values()
+                    .filter(|inode| inode.kind == FileType::Directory)
+                    .count() as u32,
+            ),
+        };
+        Metadata {
+            dev: self.fs.dev,
+            ino: self.ino,
+            kind: self.kind,
+            mode: self.mode,
+            nlink,
+            size,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> FsResult<usize> {
+        let RamData::File(data) = &*self.data.read() else {
+            return Err(FsError::IsADirectory);
+        };
+        let Some(available) = data.get(offset as usize..) else {
+            // past the end of the file
+            return Ok(0);
+        };
+        let len = buf.len().min(available.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> FsResult<usize> {
+        let RamData::File(data) = &mut *self.data.write() else {
+            return Err(FsError::IsADirectory);
+        };
+        let end = (offset as usize)
+            .checked_add(buf.len())
+            .ok_or(FsError::InvalidArgument)?;
+        if end > data.len() {
+            data.try_reserve(end - data.len())
+                .map_err(|_| FsError::NoSpace)?;
+            // the gap (if seeked past the end) is filled with zeroes
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> FsResult<()> {
+        let RamData::File(data) = &mut *self.data.write() else {
+            return Err(FsError::IsADirectory);
+        };
+        data.resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        let RamData::Dir(entries) = &*self.data.read() else {
+            return Err(FsError::NotADirectory);
+        };
+        entries
+            .get(name)
+            .map(|inode| inode.clone() as Arc<dyn Inode>)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn create(&self, name: &str, kind: FileType, mode: u16) -> FsResult<Arc<dyn Inode>> {
+        let RamData::Dir(entries) = &mut *self.data.write() else {
+            return Err(FsError::NotADirectory);
+        };
+        if entries.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+        let inode = RamInode::new(self.fs.clone(), kind, mode);
+        entries.insert(String::from(name), inode.clone());
+        Ok(inode)
+    }
+
+    fn unlink(&self, name: &str) -> FsResult<()> {
+        let RamData::Dir(entries) = &mut *self.data.write() else {
+            return Err(FsError::NotADirectory);
+        };
+        let inode = entries.get(name).ok_or(FsError::NotFound)?;
+        if let RamData::Dir(children) = &*inode.data.read() {
+            if !children.is_empty() {
+                return Err(FsError::NotEmpty);
+            }
+        }
+        // the data stays around till the files open on it are closed
+        entries.remove(name);
+        Ok(())
+    }
+
+    fn readdir(&self, index: usize) -> FsResult<Option<DirEntry>> {
+        let RamData::Dir(entries) = &*self.data.read() else {
+            return Err(FsError::NotADirectory);
+        };
+        Ok(entries.iter().nth(index).map(|(name, inode)| DirEntry {
+            ino: inode.ino,
+            name: name.clone(),
+            kind: inode.kind,
+        }))
+    }
+}
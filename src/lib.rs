@@ -1,11 +1,14 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 #![feature(naked_functions)]
 #![feature(const_option)]
 #![feature(core_intrinsics)]
 #![feature(let_chains)]
+#![feature(asm_const)]
 
 mod arch;
+mod cmdline;
+mod fs;
 mod locks;
 mod logging;
 mod mem;
@@ -18,10 +21,10 @@ use core::ptr::addr_of;
 use locks::SpinLock;
 use mem::allocator::bitmap_allocator::BitMapAllocator;
 
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 static HEAP_ALLOCATOR: SpinLock<BitMapAllocator> = BitMapAllocator::locked();
 
-use log::{info, trace, LevelFilter};
+use log::{info, trace};
 use logging::Logger;
 
 use crate::arch::ACTIVE_PAGETABLE;
@@ -45,12 +48,25 @@ extern "C" {
     static __eh_frame_hdr_end: u8;
 }
 
+// the host tests link in the scheduler, but not the assembly that defines the GDT
+#[cfg(test)]
+mod host {
+    #[no_mangle]
+    #[allow(non_upper_case_globals)]
+    static mut gdt64: u8 = 0;
+}
+
 // static mut HIGHER_HALF_ADDRESS: u64 = 0x0;
 
 #[no_mangle]
 pub extern "C" fn rust_start(multiboot_addr: u64) -> ! {
+    // SAFETY: first thing run, before anything takes a lock
+    unsafe {
+        arch::init_this_cpu();
+    }
+
     log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(LevelFilter::Info))
+        .map(|()| log::set_max_level(cmdline::params().loglevel))
         .unwrap();
 
     unsafe {
@@ -70,10 +86,19 @@ pub extern "C" fn rust_start(multiboot_addr: u64) -> ! {
 
     trace!("multiboot_addr: {:#x}", multiboot_addr);
     let multiboot_info = multiboot::MultibootInfo::new(multiboot_addr);
+    info!(
+        "booted by {:?}, cmdline: {:?}",
+        multiboot_info.bootloader_name(),
+        multiboot_info.cmdline()
+    );
 
     // HEAP_ALLOCATOR2.lock().init(&multiboot_info);
     HEAP_ALLOCATOR.lock().init(&multiboot_info);
 
+    cmdline::init(multiboot_info.cmdline().unwrap_or(""));
+
+    fs::init(&multiboot_info);
+
     arch::init(&multiboot_info);
     info!("init done");
 
@@ -90,6 +115,7 @@ pub extern "C" fn rust_start(multiboot_addr: u64) -> ! {
     }
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{}", info);
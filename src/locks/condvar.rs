@@ -0,0 +1,76 @@
+use super::MutexGuard;
+use crate::arch::WaitQueue;
+
+// Condition variable to be used along with the sleeping Mutex
+#[derive(Debug)]
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    // releases the lock and puts the task to sleep till it's notified
+    // the lock is taken again before returning
+    // Spurious wake ups are possible, so check the condition in a loop (or use `wait_while`)
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        // the lock is released with the scheduler locked
+        // so that the notification can't get lost between releasing the lock and going to sleep
+        self.waiters.wait_while(|| {
+            drop(guard);
+            true
+        });
+        mutex.lock()
+    }
+
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locks::Mutex;
+
+    #[test]
+    fn wait_while_false() {
+        let mutex = Mutex::new(3);
+        let condvar = Condvar::new();
+        // the condition doesn't hold, so it doesn't sleep, and the lock stays taken
+        let mut guard = condvar.wait_while(mutex.lock(), |value| *value == 0);
+        assert_eq!(*guard, 3);
+        *guard = 0;
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert_eq!(*mutex.lock(), 0);
+    }
+
+    #[test]
+    fn notify_without_waiters() {
+        let condvar = Condvar::new();
+        condvar.notify_one();
+        condvar.notify_all();
+        assert!(condvar.waiters.is_empty());
+    }
+}
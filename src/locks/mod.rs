@@ -1,5 +1,18 @@
+// the drivers waiting on their interrupts are to sleep on these, nothing does yet
+#[allow(dead_code)]
+mod condvar;
+mod mutex;
+mod rwlock;
+#[allow(dead_code)]
+mod semaphore;
 mod spinlock;
 mod spinlock_irq;
 
+#[allow(unused_imports)]
+pub use condvar::*;
+pub use mutex::*;
+pub use rwlock::*;
+#[allow(unused_imports)]
+pub use semaphore::*;
 pub use spinlock::*;
 pub use spinlock_irq::*;
@@ -0,0 +1,76 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::WaitQueue;
+
+// Counting semaphore
+// `down` puts the task to sleep while the count is 0
+#[derive(Debug)]
+pub struct Semaphore {
+    count: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn down(&self) {
+        loop {
+            if self.try_down() {
+                return;
+            }
+            self.waiters
+                .wait_while(|| self.count.load(Ordering::Relaxed) == 0);
+        }
+    }
+
+    pub fn try_down(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    // can be called from within an interrupt handler
+    // eg: to signal the arrival of data to the task waiting on it
+    pub fn up(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_down_till_zero() {
+        let semaphore = Semaphore::new(2);
+        assert!(semaphore.try_down());
+        assert!(semaphore.try_down());
+        assert!(!semaphore.try_down());
+        assert_eq!(semaphore.count(), 0);
+    }
+
+    #[test]
+    fn up_then_down() {
+        let semaphore = Semaphore::new(0);
+        semaphore.up();
+        semaphore.up();
+        assert_eq!(semaphore.count(), 2);
+        // the count is not 0, so it doesn't sleep
+        semaphore.down();
+        semaphore.down();
+        assert_eq!(semaphore.count(), 0);
+        assert!(!semaphore.try_down());
+    }
+}
@@ -2,6 +2,10 @@ use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicBool, Ordering};
 
+use crate::arch::{preempt_disable, preempt_enable};
+
+// Holding the lock disables preemption, so the holder can't be switched out
+// and leave the others spinning for a whole time slice
 #[derive(Debug)]
 pub struct SpinLock<T> {
     locked: AtomicBool,
@@ -17,6 +21,7 @@ impl<T> SpinLock<T> {
     }
 
     pub fn lock(&self) -> Guard<T> {
+        preempt_disable();
         while self.locked.swap(true, Ordering::Acquire) {
             core::hint::spin_loop();
         }
@@ -53,5 +58,6 @@ impl<T> DerefMut for Guard<'_, T> {
 impl<T> Drop for Guard<'_, T> {
     fn drop(&mut self) {
         self.lock.locked.store(false, Ordering::Release);
+        preempt_enable();
     }
 }
@@ -2,7 +2,9 @@ use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::arch::{disable_interrupts, enable_interrupts, is_int_enabled};
+use crate::arch::{
+    disable_interrupts, enable_interrupts, is_int_enabled, preempt_disable, preempt_enable,
+};
 
 // Interrupts safe version of SpinLock<T>
 // To be used to lock the resources that are modifed/read from within an interrupt handler
@@ -37,6 +39,7 @@ impl<T> SpinLockIrq<T> {
         if interrupts_enabled {
             disable_interrupts();
         }
+        preempt_disable();
 
         while self.locked.swap(true, Ordering::Acquire) {
             core::hint::spin_loop();
@@ -70,5 +73,7 @@ impl<T> Drop for GuardIrq<'_, T> {
         if self.lock.interrupts.load(Ordering::Relaxed) {
             enable_interrupts();
         }
+        // after enabling the interrupts, so that a pending reschedule can happen right away
+        preempt_enable();
     }
 }
@@ -8,8 +8,12 @@ impl log::Log for Logger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
+            // time since boot, like dmesg
+            let now = crate::arch::monotonic_ns();
             crate::println!(
-                "[{}:{}] {}",
+                "[{:>5}.{:06}] [{}:{}] {}",
+                now / 10u64.pow(9),
+                now % 10u64.pow(9) / 1000,
                 record.file().unwrap(),
                 record.line().unwrap(),
                 record.args()
@@ -23,11 +27,10 @@ impl log::Log for Logger {
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ({
-        const VGA: bool = true;
-        const SERIAL: bool = true;
+        let console = crate::cmdline::params().console;
 
-        if VGA { crate::arch::_print(format_args!($($arg)*)); }
-        if SERIAL { crate::logging::_print_port(format_args!($($arg)*)); }
+        if console.contains(crate::cmdline::Console::VGA) { crate::arch::_print(format_args!($($arg)*)); }
+        if console.contains(crate::cmdline::Console::SERIAL) { crate::arch::_print_serial(format_args!($($arg)*)); }
     });
 }
 
@@ -36,35 +39,3 @@ macro_rules! println {
     () => ($crate::print!("\n"));
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
-
-struct PortWriter(u16);
-static mut PW: PortWriter = PortWriter(0x3f8);
-
-impl PortWriter {
-    fn write_byte(&self, b: u8) {
-        unsafe {
-            core::arch::asm!("out dx, al", in("dx") self.0, in("al") b);
-        }
-    }
-}
-
-impl core::fmt::Write for PortWriter {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
-            }
-        }
-        Ok(())
-    }
-}
-
-pub fn _print_port(args: core::fmt::Arguments) {
-    use core::fmt::Write;
-    unsafe {
-        PW.write_fmt(args).unwrap();
-    }
-}
@@ -97,6 +97,47 @@ impl MemMapEntry {
     }
 }
 
+// bits of a pixel that hold a colour channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ColourField {
+    pub(crate) position: u8,
+    pub(crate) size: u8,
+}
+
+impl ColourField {
+    pub(crate) const fn new(position: u8, size: u8) -> Self {
+        Self { position, size }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FramebufferType {
+    // palette based
+    Indexed,
+    // direct colour
+    Rgb {
+        red: ColourField,
+        green: ColourField,
+        blue: ColourField,
+    },
+    // the text mode, as in `vga_buffer`
+    EgaText,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FramebufferInfo {
+    pub(crate) addr: PhysicalAddress,
+    // bytes per line
+    pub(crate) pitch: u32,
+    // in pixels (characters for the text mode)
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // bits per pixel
+    pub(crate) bpp: u8,
+    pub(crate) kind: FramebufferType,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MemMapEntryType {
     Ram,
@@ -208,21 +249,78 @@ impl MultibootInfo {
             unsafe {
                 let mod_start = ptr::read_unaligned(start_addr.offset(8).as_const_ptr::<u32>());
                 let mod_end = ptr::read_unaligned(start_addr.offset(12).as_const_ptr::<u32>());
-                let s = core::slice::from_raw_parts(
-                    start_addr.offset(16).as_const_ptr(),
-                    total_size as usize - 16,
-                );
 
                 MultibootModule {
                     size: total_size,
                     mod_start,
                     mod_end,
-                    string: s,
+                    cmdline: tag_string(start_addr, 16, total_size),
                 }
             }
         })
     }
 
+    // the arguments after the kernel path in the `multiboot2` line
+    pub(crate) fn cmdline(&self) -> Option<&'static str> {
+        self.find_tags_of_type(1)
+            .next()
+            // SAFETY:
+            // we are only dereferencing the addresses that fall within the limits of what the multiboot protocol returns
+            // start to start + size
+            .map(|(start_addr, total_size)| unsafe { tag_string(start_addr, 8, total_size) })
+    }
+
+    pub(crate) fn bootloader_name(&self) -> Option<&'static str> {
+        self.find_tags_of_type(2)
+            .next()
+            // SAFETY:
+            // we are only dereferencing the addresses that fall within the limits of what the multiboot protocol returns
+            // start to start + size
+            .map(|(start_addr, total_size)| unsafe { tag_string(start_addr, 8, total_size) })
+    }
+
+    // the video mode the bootloader set, if it set one
+    pub(crate) fn framebuffer(&self) -> Option<FramebufferInfo> {
+        self.find_tags_of_type(8)
+            .next()
+            .map(|(start_addr, total_size)| {
+                // SAFETY:
+                // we are only dereferencing the addresses that fall within the limits of what the multiboot protocol returns
+                // start to start + size
+                unsafe {
+                    let read_u8 = |offset: u64| -> u8 {
+                        ptr::read_unaligned(start_addr.offset(offset).as_const_ptr())
+                    };
+                    let read_u32 = |offset: u64| -> u32 {
+                        ptr::read_unaligned(start_addr.offset(offset).as_const_ptr())
+                    };
+
+                    let kind = match read_u8(29) {
+                        0 => FramebufferType::Indexed,
+                        // the colour info is the position and the size of each of the channels
+                        1 if total_size >= 38 => FramebufferType::Rgb {
+                            red: ColourField::new(read_u8(32), read_u8(33)),
+                            green: ColourField::new(read_u8(34), read_u8(35)),
+                            blue: ColourField::new(read_u8(36), read_u8(37)),
+                        },
+                        2 => FramebufferType::EgaText,
+                        o => FramebufferType::Other(o),
+                    };
+
+                    FramebufferInfo {
+                        addr: PhysicalAddress::new(ptr::read_unaligned(
+                            start_addr.offset(8).as_const_ptr::<u64>(),
+                        )),
+                        pitch: read_u32(16),
+                        width: read_u32(20),
+                        height: read_u32(24),
+                        bpp: read_u8(28),
+                        kind,
+                    }
+                }
+            })
+    }
+
     // assumes presence of only one memory tags entry
     pub fn multiboot_elf_tags(&self) -> Option<MultibootIter<Elf64SectionHeader>> {
         self.find_tags_of_type(9)
@@ -345,10 +443,57 @@ impl Iterator for TagIterator {
     }
 }
 
+// the null terminated string that takes up the rest of a tag, starting at `offset`
+// the bootloaders don't promise anything about the encoding, invalid UTF-8 is treated as an empty string
+//
+// SAFETY: `start_addr` has to point to a tag of `total_size` bytes
+unsafe fn tag_string(start_addr: VirtualAddress, offset: u32, total_size: u32) -> &'static str {
+    let bytes = core::slice::from_raw_parts(
+        start_addr.offset(offset as u64).as_const_ptr::<u8>(),
+        total_size.saturating_sub(offset) as usize,
+    );
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).unwrap_or("")
+}
+
 #[derive(Debug)]
 pub(crate) struct MultibootModule {
     pub(crate) size: u32,
     pub(crate) mod_start: u32,
     pub(crate) mod_end: u32,
-    pub(crate) string: &'static [u8],
+    // whatever follows the module path in the `module2` line
+    // by convention, the path it's loaded from followed by its arguments
+    pub(crate) cmdline: &'static str,
+}
+
+impl MultibootModule {
+    // the cmdline split in words, the first one being the module path, like `argv`
+    pub(crate) fn args(&self) -> impl Iterator<Item = &'static str> {
+        self.cmdline.split_whitespace()
+    }
+
+    // the last component of the module path (`proc1` for `/boot/proc1`)
+    pub(crate) fn name(&self) -> Option<&'static str> {
+        self.args()
+            .next()
+            .and_then(|path| path.rsplit('/').next())
+            .filter(|name| !name.is_empty())
+    }
+
+    // an initial RAM filesystem, unpacked into `/` instead of being run
+    // marked by an `initrd` word in the cmdline, e.g. `module2 /boot/initrd.cpio /boot/initrd.cpio initrd`
+    pub(crate) fn is_initrd(&self) -> bool {
+        self.args().any(|arg| arg == "initrd")
+    }
+
+    // SAFETY: the module memory must not have been reused
+    pub(crate) unsafe fn bytes(&self) -> &'static [u8] {
+        let start = PhysicalAddress::new(self.mod_start as u64)
+            .to_virt()
+            .unwrap();
+        core::slice::from_raw_parts(
+            start.as_const_ptr(),
+            (self.mod_end - self.mod_start) as usize,
+        )
+    }
 }